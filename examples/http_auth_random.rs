@@ -19,7 +19,8 @@ use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
 use std::time::Duration;
 
-#[no_mangle]
+// Exported under a different name in tests, where `_start` belongs to the C runtime.
+#[cfg_attr(not(test), no_mangle)]
 pub fn _start() {
     proxy_wasm::set_log_level(LogLevel::Trace);
     proxy_wasm::set_http_context(|_, _| -> Box<dyn HttpContext> { Box::new(HttpAuthRandom) });
@@ -67,3 +68,40 @@ impl Context for HttpAuthRandom {
         );
     }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use proxy_wasm::testing::HttpFilterTest;
+
+    fn request() -> HttpFilterTest {
+        _start();
+        HttpFilterTest::new()
+            .request_headers(&[(":method", "GET"), (":path", "/")], true)
+            .expect_action(Action::Pause)
+            .expect_http_call("httpbin")
+    }
+
+    #[test]
+    fn test_access_granted() {
+        request()
+            .http_call_response(&[(":status", "200")], Some(&[2]), &[])
+            .expect_resumed(StreamType::Request)
+            .expect_no_local_response()
+            .expect_log(LogLevel::Trace, "Access granted.")
+            .response_headers(&[(":status", "200")], false)
+            .expect_action(Action::Continue)
+            .expect_response_header("powered-by", Some("proxy-wasm"))
+            .complete();
+    }
+
+    #[test]
+    fn test_access_forbidden() {
+        request()
+            .http_call_response(&[(":status", "200")], Some(&[1]), &[])
+            .expect_local_response(403)
+            .expect_local_response_body("Access forbidden.\n")
+            .expect_log(LogLevel::Trace, "Access forbidden.")
+            .complete();
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed accessors for well-known [Envoy attributes].
+//!
+//! Every accessor returns `Ok(None)` if the attribute is not available, e.g.
+//! `response_code` before the response has been received, and an error if the
+//! host has returned a value that cannot be decoded.
+//!
+//! [Envoy attributes]: https://www.envoyproxy.io/docs/envoy/latest/intro/arch_overview/advanced/attributes
+
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::hostcalls;
+
+/// Returns the path portion of the URL, including the query string.
+pub fn request_path() -> Result<Option<String>> {
+    get_string(&["request", "path"])
+}
+
+/// Returns the host portion of the URL.
+pub fn request_host() -> Result<Option<String>> {
+    get_string(&["request", "host"])
+}
+
+/// Returns the scheme portion of the URL, e.g. `http`.
+pub fn request_scheme() -> Result<Option<String>> {
+    get_string(&["request", "scheme"])
+}
+
+/// Returns the request method, e.g. `GET`.
+pub fn request_method() -> Result<Option<String>> {
+    get_string(&["request", "method"])
+}
+
+/// Returns the referer request header.
+pub fn request_referer() -> Result<Option<String>> {
+    get_string(&["request", "referer"])
+}
+
+/// Returns the user agent request header.
+pub fn request_useragent() -> Result<Option<String>> {
+    get_string(&["request", "useragent"])
+}
+
+/// Returns the time of the first byte received.
+pub fn request_time() -> Result<Option<SystemTime>> {
+    get_timestamp(&["request", "time"])
+}
+
+/// Returns the request ID corresponding to the `x-request-id` header value.
+pub fn request_id() -> Result<Option<String>> {
+    get_string(&["request", "id"])
+}
+
+/// Returns the request protocol, e.g. `HTTP/2`.
+pub fn request_protocol() -> Result<Option<String>> {
+    get_string(&["request", "protocol"])
+}
+
+/// Returns the total duration of the request.
+pub fn request_duration() -> Result<Option<Duration>> {
+    get_duration(&["request", "duration"])
+}
+
+/// Returns the size of the request body.
+pub fn request_size() -> Result<Option<u64>> {
+    get_u64(&["request", "size"])
+}
+
+/// Returns the total size of the request, including the headers.
+pub fn request_total_size() -> Result<Option<u64>> {
+    get_u64(&["request", "total_size"])
+}
+
+/// Returns the response HTTP status code.
+pub fn response_code() -> Result<Option<u16>> {
+    get_int(&["response", "code"], "a status code")
+}
+
+/// Returns the internal response code details.
+pub fn response_code_details() -> Result<Option<String>> {
+    get_string(&["response", "code_details"])
+}
+
+/// Returns additional details about the response beyond the standard response code.
+pub fn response_flags() -> Result<Option<u64>> {
+    get_u64(&["response", "flags"])
+}
+
+/// Returns the gRPC status code of the response.
+pub fn response_grpc_status() -> Result<Option<u32>> {
+    get_int(&["response", "grpc_status"], "a gRPC status code")
+}
+
+/// Returns the size of the response body.
+pub fn response_size() -> Result<Option<u64>> {
+    get_u64(&["response", "size"])
+}
+
+/// Returns the total size of the response, including the headers.
+pub fn response_total_size() -> Result<Option<u64>> {
+    get_u64(&["response", "total_size"])
+}
+
+/// Returns the downstream connection remote address.
+pub fn source_address() -> Result<Option<SocketAddr>> {
+    get_socket_addr(&["source", "address"])
+}
+
+/// Returns the downstream connection remote port.
+pub fn source_port() -> Result<Option<u16>> {
+    get_int(&["source", "port"], "a port")
+}
+
+/// Returns the downstream connection local address.
+pub fn destination_address() -> Result<Option<SocketAddr>> {
+    get_socket_addr(&["destination", "address"])
+}
+
+/// Returns the downstream connection local port.
+pub fn destination_port() -> Result<Option<u16>> {
+    get_int(&["destination", "port"], "a port")
+}
+
+/// Returns the downstream connection ID.
+pub fn connection_id() -> Result<Option<u64>> {
+    get_u64(&["connection", "id"])
+}
+
+/// Returns whether mutual TLS is used on the downstream connection.
+pub fn connection_mtls() -> Result<Option<bool>> {
+    get_bool(&["connection", "mtls"])
+}
+
+/// Returns the requested server name of the downstream TLS connection.
+pub fn connection_requested_server_name() -> Result<Option<String>> {
+    get_string(&["connection", "requested_server_name"])
+}
+
+/// Returns the TLS version of the downstream TLS connection.
+pub fn connection_tls_version() -> Result<Option<String>> {
+    get_string(&["connection", "tls_version"])
+}
+
+/// Returns the subject field of the local certificate in the downstream TLS connection.
+pub fn connection_subject_local_certificate() -> Result<Option<String>> {
+    get_string(&["connection", "subject_local_certificate"])
+}
+
+/// Returns the subject field of the peer certificate in the downstream TLS connection.
+pub fn connection_subject_peer_certificate() -> Result<Option<String>> {
+    get_string(&["connection", "subject_peer_certificate"])
+}
+
+/// Returns the upstream connection remote address.
+pub fn upstream_address() -> Result<Option<SocketAddr>> {
+    get_socket_addr(&["upstream", "address"])
+}
+
+/// Returns the upstream connection remote port.
+pub fn upstream_port() -> Result<Option<u16>> {
+    get_int(&["upstream", "port"], "a port")
+}
+
+/// Returns the upstream connection local address.
+pub fn upstream_local_address() -> Result<Option<SocketAddr>> {
+    get_socket_addr(&["upstream", "local_address"])
+}
+
+/// Returns the TLS version of the upstream TLS connection.
+pub fn upstream_tls_version() -> Result<Option<String>> {
+    get_string(&["upstream", "tls_version"])
+}
+
+/// Returns the upstream transport failure reason, e.g. certificate validation failed.
+pub fn upstream_transport_failure_reason() -> Result<Option<String>> {
+    get_string(&["upstream", "transport_failure_reason"])
+}
+
+/// Returns the name of the upstream cluster.
+pub fn upstream_cluster() -> Result<Option<String>> {
+    get_string(&["cluster_name"])
+}
+
+/// Returns the name of the route.
+pub fn route_name() -> Result<Option<String>> {
+    get_string(&["route_name"])
+}
+
+/// Returns the ID of the local node.
+pub fn node_id() -> Result<Option<String>> {
+    let path = ["node"];
+    match hostcalls::get_property(&path)? {
+        // `Node` message, with the ID in the field number 1.
+        Some(node) => match protobuf_field(&node, 1) {
+            Some(id) => Ok(Some(utf8(&path, id.to_vec())?)),
+            None => Ok(None),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns the name of the plugin.
+pub fn plugin_name() -> Result<Option<String>> {
+    get_string(&["plugin_name"])
+}
+
+/// Returns the root ID of the plugin.
+pub fn plugin_root_id() -> Result<Option<String>> {
+    get_string(&["plugin_root_id"])
+}
+
+/// Returns the VM ID of the plugin.
+pub fn plugin_vm_id() -> Result<Option<String>> {
+    get_string(&["plugin_vm_id"])
+}
+
+fn get_string(path: &[&str]) -> Result<Option<String>> {
+    match hostcalls::get_property(path)? {
+        Some(value) => Ok(Some(utf8(path, value.into_bytes())?)),
+        None => Ok(None),
+    }
+}
+
+/// Integers are encoded as 64-bit little-endian values.
+fn get_i64(path: &[&str]) -> Result<Option<i64>> {
+    match hostcalls::get_property(path)? {
+        Some(value) if value.len() == 8 => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&value);
+            Ok(Some(i64::from_le_bytes(bytes)))
+        }
+        Some(_) => Err(invalid(path, "an integer")),
+        None => Ok(None),
+    }
+}
+
+fn get_int<T>(path: &[&str], kind: &str) -> Result<Option<T>>
+where
+    T: std::convert::TryFrom<i64>,
+{
+    match get_i64(path)? {
+        Some(value) => T::try_from(value)
+            .map(Some)
+            .map_err(|_| invalid(path, kind)),
+        None => Ok(None),
+    }
+}
+
+fn get_u64(path: &[&str]) -> Result<Option<u64>> {
+    get_int(path, "an unsigned integer")
+}
+
+fn get_bool(path: &[&str]) -> Result<Option<bool>> {
+    match hostcalls::get_property(path)? {
+        Some(value) if value.len() == 1 => Ok(Some(value[0] != 0)),
+        Some(_) => Err(invalid(path, "a boolean")),
+        None => Ok(None),
+    }
+}
+
+/// Timestamps are encoded as nanoseconds since the Unix epoch.
+fn get_timestamp(path: &[&str]) -> Result<Option<SystemTime>> {
+    match get_int::<u64>(path, "a timestamp")? {
+        Some(nanos) => Ok(Some(UNIX_EPOCH + Duration::from_nanos(nanos))),
+        None => Ok(None),
+    }
+}
+
+/// Durations are encoded as nanoseconds.
+fn get_duration(path: &[&str]) -> Result<Option<Duration>> {
+    match get_int::<u64>(path, "a duration")? {
+        Some(nanos) => Ok(Some(Duration::from_nanos(nanos))),
+        None => Ok(None),
+    }
+}
+
+fn get_socket_addr(path: &[&str]) -> Result<Option<SocketAddr>> {
+    match get_string(path)? {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid(path, "a socket address")),
+        None => Ok(None),
+    }
+}
+
+fn utf8(path: &[&str], value: Vec<u8>) -> Result<String> {
+    String::from_utf8(value).map_err(|_| invalid(path, "a UTF-8 string"))
+}
+
+fn invalid(path: &[&str], kind: &str) -> Error {
+    format!("attribute \"{}\" is not {}", path.join("."), kind).into()
+}
+
+/// Returns the first length-delimited field with a given number in a serialized
+/// protobuf message.
+fn protobuf_field(mut message: &[u8], field_number: u64) -> Option<&[u8]> {
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        match key & 0x7 {
+            0 => {
+                read_varint(&mut message)?;
+            }
+            1 => message = message.get(8..)?,
+            2 => {
+                let len = read_varint(&mut message)? as usize;
+                let value = message.get(..len)?;
+                message = &message[len..];
+                if key >> 3 == field_number {
+                    return Some(value);
+                }
+            }
+            5 => message = message.get(4..)?,
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn read_varint(message: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (index, byte) in message.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            *message = &message[index + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
@@ -0,0 +1,108 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+pub type Headers = Vec<(String, String)>;
+
+/// A scenario replayed against a filter.
+///
+/// ```yaml
+/// vm_config: ""
+/// plugin_config: '{"header": "x-powered-by"}'
+/// properties:
+///   - path: [source, address]
+///     value: 127.0.0.1:12345
+/// http_calls:
+///   - upstream: httpbin
+///     path: /bytes/1
+///     response:
+///       headers: [[":status", "200"]]
+///       body: "\x02"
+/// streams:
+///   - request:
+///       headers: [[":method", "GET"], [":path", "/"]]
+///     response:
+///       headers: [[":status", "200"]]
+///       body: Hello, World!
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fixture {
+    pub vm_config: String,
+    pub plugin_config: String,
+    pub properties: Vec<Property>,
+    pub http_calls: Vec<HttpCall>,
+    pub streams: Vec<Stream>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Property {
+    pub path: Vec<String>,
+    pub value: String,
+}
+
+/// A canned response to HTTP calls to a given upstream.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpCall {
+    pub upstream: String,
+    /// Restricts the fixture to calls with a given `:path`.
+    #[serde(default)]
+    pub path: Option<String>,
+    pub response: Message,
+}
+
+/// An HTTP stream, i.e. a request and the response from the upstream.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stream {
+    pub request: Message,
+    pub response: Message,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Message {
+    pub headers: Headers,
+    pub body: Option<String>,
+    pub trailers: Headers,
+}
+
+impl Fixture {
+    /// Loads a fixture from a JSON or YAML file, depending on its extension.
+    pub fn load(path: &Path) -> Result<Fixture> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read fixture {}", path.display()))?;
+        let fixture = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => serde_yaml::from_str(&content)?,
+        };
+        Ok(fixture)
+    }
+
+    pub fn http_call_response(&self, upstream: &str, path: Option<&str>) -> Option<&Message> {
+        self.http_calls
+            .iter()
+            .find(|call| {
+                call.upstream == upstream && (call.path.is_none() || call.path.as_deref() == path)
+            })
+            .map(|call| &call.response)
+    }
+}
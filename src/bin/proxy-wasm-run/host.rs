@@ -0,0 +1,803 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use proxy_wasm_experimental::hostcalls::abi;
+use proxy_wasm_experimental::types::{BufferType, MapType, Status, StreamType};
+use wasmtime::{Caller, Extern, Linker};
+
+pub type Pairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[derive(Debug)]
+pub struct LocalResponse {
+    pub status_code: u32,
+    pub status_code_details: Vec<u8>,
+    pub headers: Pairs,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct HttpCall {
+    pub token_id: u32,
+    pub upstream: String,
+    pub headers: Pairs,
+    pub body: Vec<u8>,
+}
+
+/// State of the host seen by the filter through the `env` imports.
+#[derive(Debug, Default)]
+pub struct HostState {
+    pub buffers: HashMap<i32, Vec<u8>>,
+    pub maps: HashMap<i32, Pairs>,
+    pub properties: HashMap<Vec<u8>, Vec<u8>>,
+    pub shared_data: HashMap<Vec<u8>, (Vec<u8>, u32)>,
+    pub queues: Vec<(String, VecDeque<Vec<u8>>)>,
+    pub metrics: Vec<(String, i64)>,
+    pub tick_period: u32,
+    pub effective_context: u32,
+    pub continued_streams: Vec<i32>,
+    pub closed_streams: Vec<i32>,
+    pub local_response: Option<LocalResponse>,
+    pub pending_http_calls: VecDeque<HttpCall>,
+    next_token_id: u32,
+}
+
+const VM_ID: &str = "";
+
+impl HostState {
+    pub fn new() -> Self {
+        HostState {
+            next_token_id: 1,
+            ..HostState::default()
+        }
+    }
+
+    pub fn set_buffer(&mut self, buffer_type: BufferType, value: Option<&str>) {
+        match value {
+            Some(value) => self
+                .buffers
+                .insert(buffer_type as i32, value.as_bytes().to_vec()),
+            None => self.buffers.remove(&(buffer_type as i32)),
+        };
+    }
+
+    pub fn buffer(&self, buffer_type: BufferType) -> Option<&[u8]> {
+        self.buffers.get(&(buffer_type as i32)).map(Vec::as_slice)
+    }
+
+    pub fn set_map(&mut self, map_type: MapType, map: &[(String, String)]) {
+        self.maps.insert(
+            map_type as i32,
+            map.iter()
+                .map(|(name, value)| (name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+                .collect(),
+        );
+    }
+
+    pub fn map(&self, map_type: MapType) -> &[(Vec<u8>, Vec<u8>)] {
+        self.maps.get(&(map_type as i32)).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set_property(&mut self, path: &[String], value: &str) {
+        self.properties
+            .insert(path.join("\0").into_bytes(), value.as_bytes().to_vec());
+    }
+}
+
+/// Adds implementations of the `env` imports to a given linker.
+pub fn add_to_linker(linker: &mut Linker<HostState>) -> Result<()> {
+    linker.func_wrap(
+        "env",
+        abi::PROXY_LOG,
+        |mut caller: Caller<'_, HostState>, level: i32, data: i32, size: i32| {
+            let message = read_bytes(&mut caller, data, size)?;
+            println!(
+                "[{}] {}",
+                log_level_name(level),
+                String::from_utf8_lossy(&message)
+            );
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        // Defined regardless of the ABI version the runner itself is built with.
+        "proxy_get_log_level",
+        |mut caller: Caller<'_, HostState>, return_level: i32| {
+            write_u32(&mut caller, return_level, 0)?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_CURRENT_TIME_NANOSECONDS,
+        |mut caller: Caller<'_, HostState>, return_time: i32| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+            write_memory(&mut caller, return_time, &now.to_le_bytes())?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SET_TICK_PERIOD_MILLISECONDS,
+        |mut caller: Caller<'_, HostState>, period: i32| {
+            caller.data_mut().tick_period = period as u32;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_BUFFER_BYTES,
+        |mut caller: Caller<'_, HostState>,
+         buffer_type: i32,
+         start: i32,
+         max_size: i32,
+         return_data: i32,
+         return_size: i32| {
+            let value = match caller.data().buffers.get(&buffer_type) {
+                Some(buffer) => {
+                    let start = (start as u32 as usize).min(buffer.len());
+                    let end = start
+                        .saturating_add(max_size as u32 as usize)
+                        .min(buffer.len());
+                    buffer[start..end].to_vec()
+                }
+                None => return status(Status::NotFound),
+            };
+            return_bytes(&mut caller, &value, return_data, return_size)?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SET_BUFFER_BYTES,
+        |mut caller: Caller<'_, HostState>,
+         buffer_type: i32,
+         start: i32,
+         size: i32,
+         data: i32,
+         data_size: i32| {
+            let value = read_bytes(&mut caller, data, data_size)?;
+            let buffer = caller.data_mut().buffers.entry(buffer_type).or_default();
+            let start = (start as u32 as usize).min(buffer.len());
+            let end = start.saturating_add(size as u32 as usize).min(buffer.len());
+            buffer.splice(start..end, value);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_HEADER_MAP_PAIRS,
+        |mut caller: Caller<'_, HostState>, map_type: i32, return_data: i32, return_size: i32| {
+            let map = serialize_map(caller.data().maps.get(&map_type).map_or(&[], Vec::as_slice));
+            return_bytes(&mut caller, &map, return_data, return_size)?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SET_HEADER_MAP_PAIRS,
+        |mut caller: Caller<'_, HostState>, map_type: i32, data: i32, size: i32| {
+            let map = deserialize_map(&read_bytes(&mut caller, data, size)?)?;
+            caller.data_mut().maps.insert(map_type, map);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_HEADER_MAP_VALUE,
+        |mut caller: Caller<'_, HostState>,
+         map_type: i32,
+         key_data: i32,
+         key_size: i32,
+         return_data: i32,
+         return_size: i32| {
+            let key = read_bytes(&mut caller, key_data, key_size)?;
+            let value = caller.data().maps.get(&map_type).and_then(|map| {
+                map.iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(&key))
+                    .map(|(_, value)| value.clone())
+            });
+            if let Some(value) = value {
+                return_bytes(&mut caller, &value, return_data, return_size)?;
+            }
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_REPLACE_HEADER_MAP_VALUE,
+        |mut caller: Caller<'_, HostState>,
+         map_type: i32,
+         key_data: i32,
+         key_size: i32,
+         value_data: i32,
+         value_size: i32| {
+            let key = read_bytes(&mut caller, key_data, key_size)?;
+            let value = read_bytes(&mut caller, value_data, value_size)?;
+            let map = caller.data_mut().maps.entry(map_type).or_default();
+            map.retain(|(name, _)| !name.eq_ignore_ascii_case(&key));
+            map.push((key, value));
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_REMOVE_HEADER_MAP_VALUE,
+        |mut caller: Caller<'_, HostState>, map_type: i32, key_data: i32, key_size: i32| {
+            let key = read_bytes(&mut caller, key_data, key_size)?;
+            if let Some(map) = caller.data_mut().maps.get_mut(&map_type) {
+                map.retain(|(name, _)| !name.eq_ignore_ascii_case(&key));
+            }
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_ADD_HEADER_MAP_VALUE,
+        |mut caller: Caller<'_, HostState>,
+         map_type: i32,
+         key_data: i32,
+         key_size: i32,
+         value_data: i32,
+         value_size: i32| {
+            let key = read_bytes(&mut caller, key_data, key_size)?;
+            let value = read_bytes(&mut caller, value_data, value_size)?;
+            caller
+                .data_mut()
+                .maps
+                .entry(map_type)
+                .or_default()
+                .push((key, value));
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_PROPERTY,
+        |mut caller: Caller<'_, HostState>,
+         path_data: i32,
+         path_size: i32,
+         return_data: i32,
+         return_size: i32| {
+            let path = read_bytes(&mut caller, path_data, path_size)?;
+            match caller.data().properties.get(&path).cloned() {
+                Some(value) => {
+                    return_bytes(&mut caller, &value, return_data, return_size)?;
+                    ok()
+                }
+                None => status(Status::NotFound),
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SET_PROPERTY,
+        |mut caller: Caller<'_, HostState>,
+         path_data: i32,
+         path_size: i32,
+         value_data: i32,
+         value_size: i32| {
+            let path = read_bytes(&mut caller, path_data, path_size)?;
+            let value = read_bytes(&mut caller, value_data, value_size)?;
+            caller.data_mut().properties.insert(path, value);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_SHARED_DATA,
+        |mut caller: Caller<'_, HostState>,
+         key_data: i32,
+         key_size: i32,
+         return_data: i32,
+         return_size: i32,
+         return_cas: i32| {
+            let key = read_bytes(&mut caller, key_data, key_size)?;
+            match caller.data().shared_data.get(&key).cloned() {
+                Some((value, cas)) => {
+                    return_bytes(&mut caller, &value, return_data, return_size)?;
+                    write_u32(&mut caller, return_cas, cas)?;
+                    ok()
+                }
+                None => status(Status::NotFound),
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SET_SHARED_DATA,
+        |mut caller: Caller<'_, HostState>,
+         key_data: i32,
+         key_size: i32,
+         value_data: i32,
+         value_size: i32,
+         cas: i32| {
+            let key = read_bytes(&mut caller, key_data, key_size)?;
+            let value = read_bytes(&mut caller, value_data, value_size)?;
+            let shared_data = &mut caller.data_mut().shared_data;
+            let next_cas = match shared_data.get(&key) {
+                Some((_, current)) if cas != 0 && cas as u32 != *current => {
+                    return status(Status::CasMismatch)
+                }
+                Some((_, current)) => current + 1,
+                None => 1,
+            };
+            shared_data.insert(key, (value, next_cas));
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_REGISTER_SHARED_QUEUE,
+        |mut caller: Caller<'_, HostState>, name_data: i32, name_size: i32, return_id: i32| {
+            let name = read_string(&mut caller, name_data, name_size)?;
+            let queue_id = find_queue(caller.data(), VM_ID, &name).unwrap_or_else(|| {
+                let queues = &mut caller.data_mut().queues;
+                queues.push((format!("{}.{}", VM_ID, name), VecDeque::new()));
+                queues.len() as u32
+            });
+            write_u32(&mut caller, return_id, queue_id)?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_RESOLVE_SHARED_QUEUE,
+        |mut caller: Caller<'_, HostState>,
+         vm_id_data: i32,
+         vm_id_size: i32,
+         name_data: i32,
+         name_size: i32,
+         return_id: i32| {
+            let vm_id = read_string(&mut caller, vm_id_data, vm_id_size)?;
+            let name = read_string(&mut caller, name_data, name_size)?;
+            match find_queue(caller.data(), &vm_id, &name) {
+                Some(queue_id) => {
+                    write_u32(&mut caller, return_id, queue_id)?;
+                    ok()
+                }
+                None => status(Status::NotFound),
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_DEQUEUE_SHARED_QUEUE,
+        |mut caller: Caller<'_, HostState>, queue_id: i32, return_data: i32, return_size: i32| {
+            let value = match caller
+                .data_mut()
+                .queues
+                .get_mut((queue_id as usize).wrapping_sub(1))
+            {
+                Some((_, queue)) => queue.pop_front(),
+                None => return status(Status::NotFound),
+            };
+            match value {
+                Some(value) => {
+                    return_bytes(&mut caller, &value, return_data, return_size)?;
+                    ok()
+                }
+                None => status(Status::Empty),
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_ENQUEUE_SHARED_QUEUE,
+        |mut caller: Caller<'_, HostState>, queue_id: i32, data: i32, size: i32| {
+            let value = read_bytes(&mut caller, data, size)?;
+            match caller
+                .data_mut()
+                .queues
+                .get_mut((queue_id as usize).wrapping_sub(1))
+            {
+                Some((_, queue)) => {
+                    queue.push_back(value);
+                    ok()
+                }
+                None => status(Status::NotFound),
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        // Defined regardless of the ABI version the runner itself is built with.
+        "proxy_continue_stream",
+        |mut caller: Caller<'_, HostState>, stream_type: i32| {
+            caller.data_mut().continued_streams.push(stream_type);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_continue_request",
+        |mut caller: Caller<'_, HostState>| {
+            caller
+                .data_mut()
+                .continued_streams
+                .push(StreamType::Request as i32);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_continue_response",
+        |mut caller: Caller<'_, HostState>| {
+            caller
+                .data_mut()
+                .continued_streams
+                .push(StreamType::Response as i32);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_CLOSE_STREAM,
+        |mut caller: Caller<'_, HostState>, stream_type: i32| {
+            caller.data_mut().closed_streams.push(stream_type);
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SEND_LOCAL_RESPONSE,
+        |mut caller: Caller<'_, HostState>,
+         status_code: i32,
+         details_data: i32,
+         details_size: i32,
+         body_data: i32,
+         body_size: i32,
+         headers_data: i32,
+         headers_size: i32,
+         _grpc_status: i32| {
+            let status_code_details = read_bytes(&mut caller, details_data, details_size)?;
+            let body = read_bytes(&mut caller, body_data, body_size)?;
+            let headers = deserialize_map(&read_bytes(&mut caller, headers_data, headers_size)?)?;
+            caller.data_mut().local_response = Some(LocalResponse {
+                status_code: status_code as u32,
+                status_code_details,
+                headers,
+                body,
+            });
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_HTTP_CALL,
+        |mut caller: Caller<'_, HostState>,
+         upstream_data: i32,
+         upstream_size: i32,
+         headers_data: i32,
+         headers_size: i32,
+         body_data: i32,
+         body_size: i32,
+         _trailers_data: i32,
+         _trailers_size: i32,
+         _timeout: i32,
+         return_token: i32| {
+            let upstream = read_string(&mut caller, upstream_data, upstream_size)?;
+            let headers = deserialize_map(&read_bytes(&mut caller, headers_data, headers_size)?)?;
+            let body = read_bytes(&mut caller, body_data, body_size)?;
+            let state = caller.data_mut();
+            let token_id = state.next_token_id;
+            state.next_token_id += 1;
+            state.pending_http_calls.push_back(HttpCall {
+                token_id,
+                upstream,
+                headers,
+                body,
+            });
+            write_u32(&mut caller, return_token, token_id)?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GRPC_CALL,
+        |_: Caller<'_, HostState>,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32| status(Status::Unimplemented),
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GRPC_STREAM,
+        |_: Caller<'_, HostState>,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32,
+         _: i32| status(Status::Unimplemented),
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GRPC_SEND,
+        |_: Caller<'_, HostState>, _: i32, _: i32, _: i32, _: i32| status(Status::Unimplemented),
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GRPC_CANCEL,
+        |_: Caller<'_, HostState>, _: i32| status(Status::Unimplemented),
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GRPC_CLOSE,
+        |_: Caller<'_, HostState>, _: i32| status(Status::Unimplemented),
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_SET_EFFECTIVE_CONTEXT,
+        |mut caller: Caller<'_, HostState>, context_id: i32| {
+            caller.data_mut().effective_context = context_id as u32;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap("env", abi::PROXY_DONE, |_: Caller<'_, HostState>| ok())?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_DEFINE_METRIC,
+        |mut caller: Caller<'_, HostState>,
+         _metric_type: i32,
+         name_data: i32,
+         name_size: i32,
+         return_id: i32| {
+            let name = read_string(&mut caller, name_data, name_size)?;
+            let metrics = &mut caller.data_mut().metrics;
+            let metric_id = match metrics.iter().position(|(n, _)| *n == name) {
+                Some(index) => index,
+                None => {
+                    metrics.push((name, 0));
+                    metrics.len() - 1
+                }
+            };
+            write_u32(&mut caller, return_id, metric_id as u32)?;
+            ok()
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_INCREMENT_METRIC,
+        |mut caller: Caller<'_, HostState>, metric_id: i32, offset: i64| match caller
+            .data_mut()
+            .metrics
+            .get_mut(metric_id as usize)
+        {
+            Some((_, value)) => {
+                *value = value.wrapping_add(offset);
+                ok()
+            }
+            None => status(Status::NotFound),
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_RECORD_METRIC,
+        |mut caller: Caller<'_, HostState>, metric_id: i32, value: i64| match caller
+            .data_mut()
+            .metrics
+            .get_mut(metric_id as usize)
+        {
+            Some((_, current)) => {
+                *current = value;
+                ok()
+            }
+            None => status(Status::NotFound),
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        abi::PROXY_GET_METRIC,
+        |mut caller: Caller<'_, HostState>, metric_id: i32, return_value: i32| match caller
+            .data()
+            .metrics
+            .get(metric_id as usize)
+            .map(|m| m.1)
+        {
+            Some(value) => {
+                write_memory(&mut caller, return_value, &(value as u64).to_le_bytes())?;
+                ok()
+            }
+            None => status(Status::NotFound),
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_call_foreign_function",
+        |_: Caller<'_, HostState>, _: i32, _: i32, _: i32, _: i32, _: i32, _: i32| {
+            status(Status::NotFound)
+        },
+    )?;
+
+    Ok(())
+}
+
+fn ok() -> Result<i32> {
+    status(Status::Ok)
+}
+
+fn status(status: Status) -> Result<i32> {
+    Ok(status as i32)
+}
+
+fn log_level_name(level: i32) -> &'static str {
+    match level {
+        0 => "trace",
+        1 => "debug",
+        2 => "info",
+        3 => "warn",
+        4 => "error",
+        _ => "critical",
+    }
+}
+
+fn find_queue(state: &HostState, vm_id: &str, name: &str) -> Option<u32> {
+    let key = format!("{}.{}", vm_id, name);
+    state
+        .queues
+        .iter()
+        .position(|(n, _)| *n == key)
+        .map(|index| index as u32 + 1)
+}
+
+fn memory(caller: &mut Caller<'_, HostState>) -> Result<wasmtime::Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(anyhow!("filter does not export \"memory\"")),
+    }
+}
+
+fn read_bytes(caller: &mut Caller<'_, HostState>, data: i32, size: i32) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; size as u32 as usize];
+    if !bytes.is_empty() {
+        memory(caller)?.read(&*caller, data as u32 as usize, &mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+fn read_string(caller: &mut Caller<'_, HostState>, data: i32, size: i32) -> Result<String> {
+    Ok(String::from_utf8(read_bytes(caller, data, size)?)?)
+}
+
+fn write_memory(caller: &mut Caller<'_, HostState>, ptr: i32, bytes: &[u8]) -> Result<()> {
+    memory(caller)?.write(&mut *caller, ptr as u32 as usize, bytes)?;
+    Ok(())
+}
+
+fn write_u32(caller: &mut Caller<'_, HostState>, ptr: i32, value: u32) -> Result<()> {
+    write_memory(caller, ptr, &value.to_le_bytes())
+}
+
+/// Copies bytes into memory allocated by the filter and returns them via out-pointers.
+fn return_bytes(
+    caller: &mut Caller<'_, HostState>,
+    bytes: &[u8],
+    return_data: i32,
+    return_size: i32,
+) -> Result<()> {
+    // SDK builds for `wasm32-unknown-unknown` export the allocator as `malloc`.
+    let allocate = caller
+        .get_export("proxy_on_memory_allocate")
+        .or_else(|| caller.get_export("malloc"))
+        .and_then(Extern::into_func)
+        .ok_or_else(|| {
+            anyhow!("filter does not export \"proxy_on_memory_allocate\" or \"malloc\"")
+        })?
+        .typed::<i32, i32>(&*caller)?;
+    let ptr = allocate.call(&mut *caller, bytes.len() as i32)?;
+    write_memory(caller, ptr, bytes)?;
+    write_u32(caller, return_data, ptr as u32)?;
+    write_u32(caller, return_size, bytes.len() as u32)
+}
+
+fn serialize_map(map: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(map.len() as u32).to_le_bytes());
+    for (name, value) in map {
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    }
+    for (name, value) in map {
+        bytes.extend_from_slice(name);
+        bytes.push(0);
+        bytes.extend_from_slice(value);
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn deserialize_map(bytes: &[u8]) -> Result<Pairs> {
+    let invalid = || anyhow!("malformed map");
+    let read_u32 = |at: usize| -> Result<usize> {
+        let chunk = bytes.get(at..at + 4).ok_or_else(invalid)?;
+        Ok(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize)
+    };
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let size = read_u32(0)?;
+    if size > (bytes.len() - 4) / 8 {
+        return Err(invalid());
+    }
+    let mut map = Vec::with_capacity(size);
+    let mut data = 4 + size * 8;
+    let mut read_bytes = |len: usize| -> Result<Vec<u8>> {
+        let end = data.checked_add(len).ok_or_else(invalid)?;
+        let chunk = bytes.get(data..end).ok_or_else(invalid)?;
+        data = end + 1;
+        Ok(chunk.to_vec())
+    };
+    for i in 0..size {
+        let name_size = read_u32(4 + i * 8)?;
+        let value_size = read_u32(8 + i * 8)?;
+        let name = read_bytes(name_size)?;
+        let value = read_bytes(value_size)?;
+        map.push((name, value));
+    }
+    Ok(map)
+}
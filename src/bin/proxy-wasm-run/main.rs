@@ -0,0 +1,53 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs a compiled filter in wasmtime against HTTP fixtures, without a proxy.
+//!
+//! ```text
+//! proxy-wasm-run <filter.wasm> <fixture.yaml|fixture.json>
+//! ```
+
+mod fixture;
+mod host;
+mod runner;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use anyhow::Result;
+
+use crate::fixture::Fixture;
+use crate::runner::Filter;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!(
+            "usage: {} <filter.wasm> <fixture.yaml|fixture.json>",
+            args[0]
+        );
+        process::exit(2);
+    }
+    if let Err(err) = run(Path::new(&args[1]), Path::new(&args[2])) {
+        eprintln!("error: {:?}", err);
+        process::exit(1);
+    }
+}
+
+fn run(filter: &Path, fixture: &Path) -> Result<()> {
+    let fixture = Fixture::load(fixture)?;
+    let mut filter = Filter::load(filter)?;
+    filter.run(&fixture)
+}
@@ -0,0 +1,323 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use proxy_wasm_experimental::types::{Action, BufferType, MapType, StreamType};
+use wasmtime::{Engine, Instance, Linker, Module, Store, WasmParams, WasmResults};
+
+use crate::fixture::{Fixture, Message, Stream};
+use crate::host::{self, HostState};
+
+const ROOT_CONTEXT_ID: i32 = 1;
+
+/// A filter instantiated in wasmtime.
+pub struct Filter {
+    store: Store<HostState>,
+    instance: Instance,
+    // ABI 0.1.0 doesn't pass `end_of_stream` to the headers callbacks.
+    abi_0_1_0: bool,
+    next_context_id: i32,
+}
+
+impl Filter {
+    pub fn load(path: &Path) -> Result<Filter> {
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, path)?;
+        let mut linker = Linker::new(&engine);
+        host::add_to_linker(&mut linker)?;
+        // Imports outside of the ABI, e.g. WASI, trap only if actually called.
+        linker.define_unknown_imports_as_traps(&module)?;
+        let mut store = Store::new(&engine, HostState::new());
+        let instance = linker.instantiate(&mut store, &module)?;
+        for start in &["_initialize", "_start"] {
+            if instance.get_func(&mut store, start).is_some() {
+                instance
+                    .get_typed_func::<(), ()>(&mut store, start)?
+                    .call(&mut store, ())?;
+            }
+        }
+        let abi_0_1_0 = instance
+            .get_func(&mut store, "proxy_abi_version_0_1_0")
+            .is_some();
+        Ok(Filter {
+            store,
+            instance,
+            abi_0_1_0,
+            next_context_id: ROOT_CONTEXT_ID + 1,
+        })
+    }
+
+    /// Replays a given fixture against the filter.
+    pub fn run(&mut self, fixture: &Fixture) -> Result<()> {
+        for property in &fixture.properties {
+            self.store
+                .data_mut()
+                .set_property(&property.path, &property.value);
+        }
+
+        self.call::<_, ()>("proxy_on_context_create", (ROOT_CONTEXT_ID, 0))?;
+        let config = &fixture.vm_config;
+        self.store
+            .data_mut()
+            .set_buffer(BufferType::VmConfiguration, Some(config));
+        let started: i32 =
+            self.call("proxy_on_vm_start", (ROOT_CONTEXT_ID, config.len() as i32))?;
+        println!("vm start: {}", started != 0);
+        let config = &fixture.plugin_config;
+        self.store
+            .data_mut()
+            .set_buffer(BufferType::PluginConfiguration, Some(config));
+        let configured: i32 =
+            self.call("proxy_on_configure", (ROOT_CONTEXT_ID, config.len() as i32))?;
+        println!("configure: {}", configured != 0);
+        if started == 0 || configured == 0 {
+            return Err(anyhow!("filter rejected its configuration"));
+        }
+
+        for (index, stream) in fixture.streams.iter().enumerate() {
+            println!();
+            println!("=== stream #{}", index + 1);
+            self.run_stream(fixture, stream)?;
+        }
+        Ok(())
+    }
+
+    fn run_stream(&mut self, fixture: &Fixture, stream: &Stream) -> Result<()> {
+        let context_id = self.next_context_id;
+        self.next_context_id += 1;
+        {
+            let state = self.store.data_mut();
+            state.local_response = None;
+            state.continued_streams.clear();
+            state.closed_streams.clear();
+        }
+        self.call::<_, ()>("proxy_on_context_create", (context_id, ROOT_CONTEXT_ID))?;
+
+        let phases: [(&Message, MapType, BufferType, MapType, StreamType, &str); 2] = [
+            (
+                &stream.request,
+                MapType::HttpRequestHeaders,
+                BufferType::HttpRequestBody,
+                MapType::HttpRequestTrailers,
+                StreamType::Request,
+                "request",
+            ),
+            (
+                &stream.response,
+                MapType::HttpResponseHeaders,
+                BufferType::HttpResponseBody,
+                MapType::HttpResponseTrailers,
+                StreamType::Response,
+                "response",
+            ),
+        ];
+        'phases: for (message, headers, body, trailers, stream_type, name) in phases.iter() {
+            let has_body = message.body.is_some();
+            let has_trailers = !message.trailers.is_empty();
+
+            self.store.data_mut().set_map(*headers, &message.headers);
+            let callback = format!("proxy_on_{}_headers", name);
+            let end_of_stream = !has_body && !has_trailers;
+            let num_headers = message.headers.len() as i32;
+            let action = if self.abi_0_1_0 {
+                self.call_http(&callback, (context_id, num_headers))?
+            } else {
+                self.call_http(&callback, (context_id, num_headers, end_of_stream as i32))?
+            };
+            if !self.proceed(fixture, context_id, *stream_type, action)? {
+                break 'phases;
+            }
+
+            if let Some(data) = &message.body {
+                self.store.data_mut().set_buffer(*body, Some(data));
+                let callback = format!("proxy_on_{}_body", name);
+                let action = self.call_http(
+                    &callback,
+                    (context_id, data.len() as i32, !has_trailers as i32),
+                )?;
+                if !self.proceed(fixture, context_id, *stream_type, action)? {
+                    break 'phases;
+                }
+            }
+
+            if has_trailers {
+                self.store.data_mut().set_map(*trailers, &message.trailers);
+                let callback = format!("proxy_on_{}_trailers", name);
+                let action =
+                    self.call_http(&callback, (context_id, message.trailers.len() as i32))?;
+                if !self.proceed(fixture, context_id, *stream_type, action)? {
+                    break 'phases;
+                }
+            }
+
+            let state = self.store.data();
+            println!("{} headers:", name);
+            print_pairs(state.map(*headers));
+            if let Some(data) = state.buffer(*body) {
+                println!("{} body:", name);
+                println!("  {}", String::from_utf8_lossy(data));
+            }
+            if has_trailers {
+                println!("{} trailers:", name);
+                print_pairs(state.map(*trailers));
+            }
+        }
+
+        if let Some(response) = &self.store.data().local_response {
+            println!(
+                "local response: {} {}",
+                response.status_code,
+                String::from_utf8_lossy(&response.status_code_details)
+            );
+            print_pairs(&response.headers);
+            if !response.body.is_empty() {
+                println!("  {}", String::from_utf8_lossy(&response.body));
+            }
+        }
+
+        self.call::<_, i32>("proxy_on_done", context_id)?;
+        self.call::<_, ()>("proxy_on_log", context_id)?;
+        self.call::<_, ()>("proxy_on_delete", context_id)?;
+        Ok(())
+    }
+
+    /// Resolves pending HTTP calls and returns whether processing of the stream continues.
+    fn proceed(
+        &mut self,
+        fixture: &Fixture,
+        context_id: i32,
+        stream_type: StreamType,
+        mut action: Action,
+    ) -> Result<bool> {
+        loop {
+            if self.store.data().local_response.is_some() {
+                return Ok(false);
+            }
+            if self
+                .store
+                .data()
+                .closed_streams
+                .contains(&(stream_type as i32))
+            {
+                println!("{:?} stream closed", stream_type);
+                return Ok(false);
+            }
+            if action == Action::Continue
+                || self
+                    .store
+                    .data()
+                    .continued_streams
+                    .contains(&(stream_type as i32))
+            {
+                self.store.data_mut().continued_streams.clear();
+                return Ok(true);
+            }
+            let call = match self.store.data_mut().pending_http_calls.pop_front() {
+                Some(call) => call,
+                None => {
+                    println!("{:?} stream paused with no pending HTTP calls", stream_type);
+                    return Ok(false);
+                }
+            };
+            self.resolve_http_call(fixture, context_id, call)?;
+            // The stream stays paused until the filter resumes it.
+            action = Action::Pause;
+        }
+    }
+
+    fn resolve_http_call(
+        &mut self,
+        fixture: &Fixture,
+        context_id: i32,
+        call: host::HttpCall,
+    ) -> Result<()> {
+        let path = call
+            .headers
+            .iter()
+            .find(|(name, _)| name.as_slice() == b":path")
+            .map(|(_, value)| String::from_utf8_lossy(value).into_owned());
+        println!(
+            "http call #{} to {:?}: {}",
+            call.token_id,
+            call.upstream,
+            path.as_deref().unwrap_or("")
+        );
+        if !call.body.is_empty() {
+            println!("  {}", String::from_utf8_lossy(&call.body));
+        }
+        let (num_headers, body_size, num_trailers) =
+            match fixture.http_call_response(&call.upstream, path.as_deref()) {
+                Some(response) => {
+                    let state = self.store.data_mut();
+                    state.set_map(MapType::HttpCallResponseHeaders, &response.headers);
+                    state.set_buffer(BufferType::HttpCallResponseBody, response.body.as_deref());
+                    state.set_map(MapType::HttpCallResponseTrailers, &response.trailers);
+                    (
+                        response.headers.len(),
+                        response.body.as_ref().map_or(0, String::len),
+                        response.trailers.len(),
+                    )
+                }
+                None => {
+                    // Reported to the filter the same way as a failed call.
+                    println!("  no fixture for this call");
+                    (0, 0, 0)
+                }
+            };
+        self.call::<_, ()>(
+            "proxy_on_http_call_response",
+            (
+                context_id,
+                call.token_id as i32,
+                num_headers as i32,
+                body_size as i32,
+                num_trailers as i32,
+            ),
+        )
+    }
+
+    fn call_http<P>(&mut self, name: &str, params: P) -> Result<Action>
+    where
+        P: WasmParams,
+    {
+        let action = match self.call::<P, i32>(name, params)? {
+            0 => Action::Continue,
+            _ => Action::Pause,
+        };
+        println!("{}: {:?}", name, action);
+        Ok(action)
+    }
+
+    fn call<P, R>(&mut self, name: &str, params: P) -> Result<R>
+    where
+        P: WasmParams,
+        R: WasmResults,
+    {
+        self.instance
+            .get_typed_func::<P, R>(&mut self.store, name)?
+            .call(&mut self.store, params)
+    }
+}
+
+fn print_pairs(pairs: &[(Vec<u8>, Vec<u8>)]) {
+    for (name, value) in pairs {
+        println!(
+            "  {}: {}",
+            String::from_utf8_lossy(name),
+            String::from_utf8_lossy(value)
+        );
+    }
+}
@@ -87,7 +87,7 @@ impl fmt::Debug for ByteStr {
             } else if b == b'\0' {
                 write!(f, "\\0")?;
             // ASCII printable
-            } else if b >= 0x20 && b < 0x7f {
+            } else if (0x20..0x7f).contains(&b) {
                 write!(f, "{}", b as char)?;
             } else {
                 write!(f, "\\x{:02x}", b)?;
@@ -524,7 +524,7 @@ impl hash::Hash for ByteString {
     where
         H: hash::Hasher,
     {
-        (&**self).hash(state);
+        (**self).hash(state);
     }
 }
 
@@ -539,9 +539,9 @@ mod tests {
         use bstr::ByteSlice;
 
         let string: ByteString = "hello".into();
-        assert_eq!(string.is_utf8(), true);
-        assert_eq!(string.starts_with_str("hel"), true);
-        assert_eq!(string.ends_with_str("lo"), true);
+        assert!(string.is_utf8());
+        assert!(string.starts_with_str("hel"));
+        assert!(string.ends_with_str("lo"));
     }
 
     #[test]
@@ -549,9 +549,9 @@ mod tests {
         use bstr::ByteSlice;
 
         let bytes: ByteString = vec![144u8, 145u8, 146u8].into();
-        assert_eq!(bytes.is_utf8(), false);
-        assert_eq!(bytes.starts_with_str(b"\x90"), true);
-        assert_eq!(bytes.ends_with_str(b"\x92"), true);
+        assert!(!bytes.is_utf8());
+        assert!(bytes.starts_with_str(b"\x90"));
+        assert!(bytes.ends_with_str(b"\x92"));
     }
 
     #[test]
@@ -0,0 +1,228 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed plugin and VM configuration.
+//!
+//! Requires the `serde` feature. Configuration in YAML and TOML additionally
+//! requires the `serde-yaml` and `serde-toml` features.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use proxy_wasm_experimental as proxy_wasm;
+//! use proxy_wasm::config::ConfigurableRootContext;
+//! use proxy_wasm::traits::*;
+//! use serde::Deserialize;
+//! use std::rc::Rc;
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     header: String,
+//! }
+//!
+//! struct AddHeader {
+//!     config: Rc<Config>,
+//! }
+//!
+//! impl Context for AddHeader {}
+//!
+//! impl HttpContext for AddHeader {}
+//!
+//! #[no_mangle]
+//! pub fn _start() {
+//!     proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
+//!         Box::new(
+//!             ConfigurableRootContext::<Config>::new()
+//!                 .with_validator(|config| match config.header.is_empty() {
+//!                     true => Err("header must not be empty".into()),
+//!                     false => Ok(()),
+//!                 })
+//!                 .with_http_context(|_, config| Box::new(AddHeader { config })),
+//!         )
+//!     });
+//! }
+//! ```
+
+use std::rc::Rc;
+
+use serde::de::DeserializeOwned;
+
+use crate::error::Result;
+use crate::hostcalls;
+use crate::traits::*;
+use crate::types::*;
+
+/// Format of a configuration.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    #[cfg(feature = "serde-yaml")]
+    Yaml,
+    #[cfg(feature = "serde-toml")]
+    Toml,
+}
+
+/// Parses a configuration in a given format.
+pub fn parse<C>(data: &[u8], format: ConfigFormat) -> Result<C>
+where
+    C: DeserializeOwned,
+{
+    match format {
+        ConfigFormat::Json => Ok(serde_json::from_slice(data)?),
+        #[cfg(feature = "serde-yaml")]
+        ConfigFormat::Yaml => Ok(serde_yaml::from_slice(data)?),
+        #[cfg(feature = "serde-toml")]
+        ConfigFormat::Toml => Ok(toml::from_slice(data)?),
+    }
+}
+
+/// Reads a configuration from a given buffer, e.g. [`BufferType::VmConfiguration`],
+/// and parses it.
+///
+/// [`BufferType::VmConfiguration`]: ../types/enum.BufferType.html#variant.VmConfiguration
+pub fn load<C>(buffer_type: BufferType, size: usize, format: ConfigFormat) -> Result<C>
+where
+    C: DeserializeOwned,
+{
+    let data = match size {
+        0 => None,
+        _ => hostcalls::get_buffer(buffer_type, 0, size)?,
+    };
+    parse(data.as_ref().map_or(&[], |data| data.as_bytes()), format)
+}
+
+type Validator<C> = dyn Fn(&C) -> Result<()>;
+type NewHttpContextFn<C> = dyn Fn(u32, Rc<C>) -> Box<dyn HttpContext>;
+type NewStreamContextFn<C> = dyn Fn(u32, Rc<C>) -> Box<dyn StreamContext>;
+
+/// A root context that parses the plugin configuration and hands it to its
+/// child contexts.
+///
+/// A configuration that cannot be parsed or is rejected by the validator is
+/// logged at [`LogLevel::Error`] and rejected, keeping the previous one.
+///
+/// [`LogLevel::Error`]: ../types/enum.LogLevel.html#variant.Error
+pub struct ConfigurableRootContext<C> {
+    format: ConfigFormat,
+    config: Option<Rc<C>>,
+    validator: Option<Box<Validator<C>>>,
+    new_http_context: Option<Box<NewHttpContextFn<C>>>,
+    new_stream_context: Option<Box<NewStreamContextFn<C>>>,
+}
+
+impl<C> ConfigurableRootContext<C>
+where
+    C: DeserializeOwned + 'static,
+{
+    /// Creates a root context expecting the plugin configuration in JSON.
+    pub fn new() -> Self {
+        ConfigurableRootContext {
+            format: ConfigFormat::default(),
+            config: None,
+            validator: None,
+            new_http_context: None,
+            new_stream_context: None,
+        }
+    }
+
+    pub fn with_format(mut self, format: ConfigFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets a function that validates the parsed configuration.
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&C) -> Result<()> + 'static,
+    {
+        self.validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Sets a function that creates HTTP contexts with the current configuration.
+    pub fn with_http_context<F>(mut self, new_context: F) -> Self
+    where
+        F: Fn(u32, Rc<C>) -> Box<dyn HttpContext> + 'static,
+    {
+        self.new_http_context = Some(Box::new(new_context));
+        self
+    }
+
+    /// Sets a function that creates TCP stream contexts with the current configuration.
+    pub fn with_stream_context<F>(mut self, new_context: F) -> Self
+    where
+        F: Fn(u32, Rc<C>) -> Box<dyn StreamContext> + 'static,
+    {
+        self.new_stream_context = Some(Box::new(new_context));
+        self
+    }
+
+    /// Returns the current configuration, if any has been accepted.
+    pub fn config(&self) -> Option<&Rc<C>> {
+        self.config.as_ref()
+    }
+
+    fn load(&self, size: usize) -> Result<C> {
+        let config = load(BufferType::PluginConfiguration, size, self.format)?;
+        if let Some(validator) = &self.validator {
+            validator(&config)?;
+        }
+        Ok(config)
+    }
+}
+
+impl<C> Default for ConfigurableRootContext<C>
+where
+    C: DeserializeOwned + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Context for ConfigurableRootContext<C> {}
+
+impl<C> RootContext for ConfigurableRootContext<C>
+where
+    C: DeserializeOwned + 'static,
+{
+    fn on_configure(&mut self, plugin_configuration_size: usize) -> bool {
+        match self.load(plugin_configuration_size) {
+            Ok(config) => {
+                self.config = Some(Rc::new(config));
+                true
+            }
+            Err(error) => {
+                hostcalls::log(
+                    LogLevel::Error,
+                    &format!("invalid plugin configuration: {}", error),
+                )
+                .ok();
+                false
+            }
+        }
+    }
+
+    fn on_create_child_context(&mut self, context_id: u32) -> Option<ChildContext> {
+        let config = Rc::clone(self.config.as_ref()?);
+        if let Some(new_context) = &self.new_http_context {
+            Some(ChildContext::HttpContext(new_context(context_id, config)))
+        } else {
+            self.new_stream_context
+                .as_ref()
+                .map(|new_context| ChildContext::StreamContext(new_context(context_id, config)))
+        }
+    }
+}
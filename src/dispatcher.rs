@@ -12,11 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::error::Result;
+use crate::error::{has_status, Error};
 use crate::hostcalls;
+use crate::logger;
+use crate::task::{HttpCallResponse, HttpCallSlot};
+use crate::timer::{self, Callback, TimerHandle, Timers};
 use crate::traits::*;
 use crate::types::*;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context as TaskContext, RawWaker, RawWakerVTable, Waker};
+use std::time::{Duration, SystemTime};
 
 thread_local! {
 static DISPATCHER: Dispatcher = Dispatcher::new();
@@ -25,6 +35,7 @@ static DISPATCHER: Dispatcher = Dispatcher::new();
 type NewRootContextFn = dyn FnMut(u32) -> Box<dyn RootContext>;
 type NewStreamContextFn = dyn FnMut(u32, u32) -> Box<dyn StreamContext>;
 type NewHttpContextFn = dyn FnMut(u32, u32) -> Box<dyn HttpContext>;
+type Task = Pin<Box<dyn Future<Output = ()>>>;
 
 pub(crate) fn set_root_context(callback: Box<NewRootContextFn>) {
     DISPATCHER.with(|dispatcher| dispatcher.set_root_context(callback));
@@ -38,15 +49,102 @@ pub(crate) fn set_http_context(callback: Box<NewHttpContextFn>) {
     DISPATCHER.with(|dispatcher| dispatcher.set_http_context(callback));
 }
 
+pub(crate) fn set_error_policy(policy: ErrorPolicy) {
+    DISPATCHER.with(|dispatcher| dispatcher.error_policy.set(policy));
+}
+
+pub(crate) fn set_panic_policy(policy: PanicPolicy) {
+    DISPATCHER.with(|dispatcher| dispatcher.panic_policy.set(policy));
+}
+
 pub(crate) fn register_callout(token_id: u32) {
     DISPATCHER.with(|dispatcher| dispatcher.register_callout(token_id));
 }
 
+pub(crate) fn register_async_callout(token_id: u32, response: HttpCallSlot) {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher
+            .async_callouts
+            .borrow_mut()
+            .insert(token_id, response)
+    });
+}
+
+pub(crate) fn spawn(task: Task) {
+    DISPATCHER.with(|dispatcher| dispatcher.spawn(task));
+}
+
+pub(crate) fn schedule_timer(
+    delay: Duration,
+    period: Option<Duration>,
+    callback: Callback,
+) -> Result<TimerHandle> {
+    DISPATCHER.with(|dispatcher| dispatcher.schedule_timer(delay, period, callback))
+}
+
+pub(crate) fn cancel_timer(handle: TimerHandle) -> Result<()> {
+    DISPATCHER.with(|dispatcher| dispatcher.cancel_timer(handle))
+}
+
+pub(crate) fn set_tick_period(period: Duration) -> Result<()> {
+    DISPATCHER.with(|dispatcher| dispatcher.set_tick_period(period))
+}
+
+pub(crate) fn set_timer_resolution(resolution: Duration) {
+    DISPATCHER.with(|dispatcher| dispatcher.timer_resolution.set(resolution));
+}
+
+pub(crate) fn set_queue_handler(queue_id: u32, handler: Box<dyn FnMut()>) {
+    DISPATCHER.with(|dispatcher| dispatcher.set_queue_handler(queue_id, handler));
+}
+
+/// Returns the id of the context of the current callback, or `0` before the first one.
+pub(crate) fn active_context_id() -> u32 {
+    DISPATCHER.with(|dispatcher| dispatcher.active_id.get())
+}
+
+/// Returns whether the context of the current callback is a root context, which has no stream.
+pub(crate) fn is_root_active() -> bool {
+    DISPATCHER.with(|dispatcher| {
+        let active_id = dispatcher.active_id.get();
+        active_id != 0 && !dispatcher.parents.borrow().contains_key(&active_id)
+    })
+}
+
+pub(crate) fn register_closed_stream(stream_type: StreamType) {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher
+            .closed_streams
+            .borrow_mut()
+            .insert((dispatcher.active_id.get(), stream_type))
+    });
+}
+
+pub(crate) fn register_grpc_callout(token_id: u32) {
+    DISPATCHER.with(|dispatcher| dispatcher.register_grpc_callout(token_id));
+}
+
+pub(crate) fn register_grpc_stream(token_id: u32) {
+    DISPATCHER.with(|dispatcher| dispatcher.register_grpc_stream(token_id));
+}
+
+pub(crate) fn unregister_grpc_callout(token_id: u32) {
+    DISPATCHER.with(|dispatcher| dispatcher.unregister_grpc_callout(token_id));
+}
+
 struct NoopRoot;
 
 impl Context for NoopRoot {}
 impl RootContext for NoopRoot {}
 
+struct QueueHandler {
+    root_context_id: u32,
+    // Context that has set the handler.
+    context_id: u32,
+    // Taken out while the handler runs.
+    handler: Option<Box<dyn FnMut()>>,
+}
+
 struct Dispatcher {
     new_root: RefCell<Option<Box<NewRootContextFn>>>,
     roots: RefCell<HashMap<u32, Box<dyn RootContext>>>,
@@ -54,8 +152,20 @@ struct Dispatcher {
     streams: RefCell<HashMap<u32, Box<dyn StreamContext>>>,
     new_http_stream: RefCell<Option<Box<NewHttpContextFn>>>,
     http_streams: RefCell<HashMap<u32, Box<dyn HttpContext>>>,
+    parents: RefCell<HashMap<u32, u32>>,
     active_id: Cell<u32>,
+    error_policy: Cell<ErrorPolicy>,
+    panic_policy: Cell<PanicPolicy>,
+    failed: RefCell<HashSet<u32>>,
+    closed_streams: RefCell<HashSet<(u32, StreamType)>>,
     callouts: RefCell<HashMap<u32, u32>>,
+    async_callouts: RefCell<HashMap<u32, HttpCallSlot>>,
+    tasks: RefCell<HashMap<u32, Vec<Task>>>,
+    timers: RefCell<HashMap<u32, Timers>>,
+    timer_resolution: Cell<Duration>,
+    queue_handlers: RefCell<HashMap<u32, QueueHandler>>,
+    grpc_callouts: RefCell<HashMap<u32, u32>>,
+    grpc_streams: RefCell<HashMap<u32, u32>>,
 }
 
 impl Dispatcher {
@@ -67,8 +177,20 @@ impl Dispatcher {
             streams: RefCell::new(HashMap::new()),
             new_http_stream: RefCell::new(None),
             http_streams: RefCell::new(HashMap::new()),
+            parents: RefCell::new(HashMap::new()),
             active_id: Cell::new(0),
+            error_policy: Cell::new(ErrorPolicy::default()),
+            panic_policy: Cell::new(PanicPolicy::default()),
+            failed: RefCell::new(HashSet::new()),
+            closed_streams: RefCell::new(HashSet::new()),
             callouts: RefCell::new(HashMap::new()),
+            async_callouts: RefCell::new(HashMap::new()),
+            tasks: RefCell::new(HashMap::new()),
+            timers: RefCell::new(HashMap::new()),
+            timer_resolution: Cell::new(timer::DEFAULT_RESOLUTION),
+            queue_handlers: RefCell::new(HashMap::new()),
+            grpc_callouts: RefCell::new(HashMap::new()),
+            grpc_streams: RefCell::new(HashMap::new()),
         }
     }
 
@@ -177,6 +299,491 @@ impl Dispatcher {
         }
     }
 
+    fn register_grpc_callout(&self, token_id: u32) {
+        if self
+            .grpc_callouts
+            .borrow_mut()
+            .insert(token_id, self.active_id.get())
+            .is_some()
+        {
+            panic!("duplicate token_id")
+        }
+    }
+
+    fn register_grpc_stream(&self, token_id: u32) {
+        if self
+            .grpc_streams
+            .borrow_mut()
+            .insert(token_id, self.active_id.get())
+            .is_some()
+        {
+            panic!("duplicate token_id")
+        }
+    }
+
+    fn unregister_grpc_callout(&self, token_id: u32) {
+        self.grpc_callouts.borrow_mut().remove(&token_id);
+        self.grpc_streams.borrow_mut().remove(&token_id);
+    }
+
+    /// Spawns a task on the active context and polls it for the first time.
+    fn spawn(&self, task: Task) {
+        let context_id = self.active_id.get();
+        self.tasks
+            .borrow_mut()
+            .entry(context_id)
+            .or_default()
+            .push(task);
+        self.poll_tasks(context_id);
+    }
+
+    fn poll_tasks(&self, context_id: u32) {
+        // Tasks are taken out of the map, since they can spawn new tasks while polled.
+        let tasks = match self.tasks.borrow_mut().remove(&context_id) {
+            Some(tasks) => tasks,
+            None => return,
+        };
+        let waker = noop_waker();
+        let mut cx = TaskContext::from_waker(&waker);
+        let mut pending: Vec<Task> = tasks
+            .into_iter()
+            .filter_map(|mut task| match task.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(()) => None,
+                std::task::Poll::Pending => Some(task),
+            })
+            .collect();
+        let mut all_tasks = self.tasks.borrow_mut();
+        if let Some(spawned) = all_tasks.remove(&context_id) {
+            pending.extend(spawned);
+        }
+        if !pending.is_empty() {
+            all_tasks.insert(context_id, pending);
+        }
+    }
+
+    /// Returns whether a given context exists and has not failed.
+    fn exists(&self, context_id: u32) -> bool {
+        self.http_streams.borrow().contains_key(&context_id)
+            || self.streams.borrow().contains_key(&context_id)
+            || self.roots.borrow().contains_key(&context_id)
+    }
+
+    /// Returns the root context of a given context.
+    fn root_of(&self, context_id: u32) -> u32 {
+        self.parents
+            .borrow()
+            .get(&context_id)
+            .copied()
+            .unwrap_or(context_id)
+    }
+
+    fn schedule_timer(
+        &self,
+        delay: Duration,
+        period: Option<Duration>,
+        callback: Callback,
+    ) -> Result<TimerHandle> {
+        let context_id = self.active_id.get();
+        let root_context_id = self.root_of(context_id);
+        let now = hostcalls::get_current_time()?;
+        let timer_id = self
+            .timers
+            .borrow_mut()
+            .entry(root_context_id)
+            .or_default()
+            .insert(now, context_id, delay, period, callback);
+        let handle = TimerHandle {
+            root_context_id,
+            timer_id,
+        };
+        if let Err(error) = self.update_tick_period(root_context_id) {
+            self.cancel_timer(handle).ok();
+            return Err(error);
+        }
+        Ok(handle)
+    }
+
+    fn cancel_timer(&self, handle: TimerHandle) -> Result<()> {
+        let removed = match self.timers.borrow_mut().get_mut(&handle.root_context_id) {
+            Some(timers) => timers.remove(handle.timer_id),
+            None => false,
+        };
+        if removed {
+            self.update_tick_period(handle.root_context_id)?;
+        }
+        Ok(())
+    }
+
+    fn set_tick_period(&self, period: Duration) -> Result<()> {
+        let root_context_id = self.root_of(self.active_id.get());
+        let now = hostcalls::get_current_time()?;
+        let previous = self
+            .timers
+            .borrow_mut()
+            .entry(root_context_id)
+            .or_default()
+            .set_user_period(now, period);
+        let result = self.update_tick_period(root_context_id);
+        let mut timers = self.timers.borrow_mut();
+        if let Some(entry) = timers.get_mut(&root_context_id) {
+            if result.is_err() {
+                entry.set_user_period(now, previous);
+            }
+            if entry.is_idle() {
+                timers.remove(&root_context_id);
+            }
+        }
+        result
+    }
+
+    /// Sets the tick period of a root context to match its timers and its own tick period.
+    fn update_tick_period(&self, root_context_id: u32) -> Result<()> {
+        let (previous, period) = match self.timers.borrow_mut().get_mut(&root_context_id) {
+            Some(timers) => {
+                let previous = timers.tick_period();
+                match timers.update_tick_period(self.timer_resolution.get()) {
+                    Some(period) => (previous, period),
+                    None => return Ok(()),
+                }
+            }
+            None => return Ok(()),
+        };
+        let active_id = self.active_id.get();
+        if active_id != root_context_id {
+            hostcalls::set_effective_context(root_context_id)?;
+        }
+        let result = hostcalls::set_tick_period(period);
+        if active_id != root_context_id {
+            hostcalls::set_effective_context(active_id)?;
+        }
+        if result.is_err() {
+            if let Some(timers) = self.timers.borrow_mut().get_mut(&root_context_id) {
+                timers.reset_tick_period(previous);
+            }
+        }
+        result
+    }
+
+    fn fire_timers(&self, root_context_id: u32, now: SystemTime) {
+        let expired = match self.timers.borrow_mut().get_mut(&root_context_id) {
+            Some(timers) => timers.take_expired(now),
+            None => return,
+        };
+        for (timer_id, context_id, callback) in expired {
+            let callback = self
+                .run_on_behalf(root_context_id, context_id, move || match callback {
+                    Callback::Once(callback) => {
+                        callback();
+                        None
+                    }
+                    Callback::Every(mut callback) => {
+                        callback();
+                        Some(Callback::Every(callback))
+                    }
+                })
+                .flatten();
+            if let Some(timers) = self.timers.borrow_mut().get_mut(&root_context_id) {
+                timers.restore(timer_id, callback, now);
+            }
+        }
+        self.active_id.set(root_context_id);
+        if let Err(error) = self.update_tick_period(root_context_id) {
+            log_error(root_context_id, error);
+        }
+        let mut timers = self.timers.borrow_mut();
+        if timers.get(&root_context_id).is_some_and(Timers::is_idle) {
+            timers.remove(&root_context_id);
+        }
+    }
+
+    fn set_queue_handler(&self, queue_id: u32, handler: Box<dyn FnMut()>) {
+        let context_id = self.active_id.get();
+        let root_context_id = self.root_of(context_id);
+        self.queue_handlers.borrow_mut().insert(
+            queue_id,
+            QueueHandler {
+                root_context_id,
+                context_id,
+                handler: Some(handler),
+            },
+        );
+    }
+
+    /// Runs the handler of a given queue, if any, returning whether it has run.
+    fn run_queue_handler(&self, context_id: u32, queue_id: u32) -> bool {
+        if !self.roots.borrow().contains_key(&context_id) {
+            return false;
+        }
+        let handler = match self.queue_handlers.borrow_mut().get_mut(&queue_id) {
+            Some(entry) => entry
+                .handler
+                .take()
+                .map(|handler| (entry.context_id, handler)),
+            None => None,
+        };
+        let (owner_id, mut handler) = match handler {
+            Some(handler) => handler,
+            None => return false,
+        };
+        let handler = self.run_on_behalf(context_id, owner_id, move || {
+            handler();
+            handler
+        });
+        let mut handlers = self.queue_handlers.borrow_mut();
+        match handler {
+            // Unless the handler has been replaced or the root context deleted.
+            Some(handler) => {
+                if let Some(entry) = handlers.get_mut(&queue_id) {
+                    entry.handler.get_or_insert(handler);
+                }
+            }
+            None => {
+                if handlers
+                    .get(&queue_id)
+                    .is_some_and(|entry| entry.handler.is_none())
+                {
+                    handlers.remove(&queue_id);
+                }
+            }
+        }
+        true
+    }
+
+    /// Runs a callback of a timer or a queue handler on behalf of the context that has set it.
+    ///
+    /// While that is a child context, it is the effective context of the callback, and a panic
+    /// fails it rather than the root context. Returns `None` if the callback has panicked.
+    fn run_on_behalf<T, F>(&self, root_context_id: u32, context_id: u32, f: F) -> Option<T>
+    where
+        F: FnOnce() -> T,
+    {
+        let is_child = context_id != root_context_id
+            && (self.http_streams.borrow().contains_key(&context_id)
+                || self.streams.borrow().contains_key(&context_id));
+        if !is_child || hostcalls::set_effective_context(context_id).is_err() {
+            self.active_id.set(root_context_id);
+            return Some(f());
+        }
+        self.active_id.set(context_id);
+        let result = self.isolate(Some(context_id), None, None, || Some(f()));
+        self.active_id.set(root_context_id);
+        if let Err(error) = hostcalls::set_effective_context(root_context_id) {
+            log_error(root_context_id, error);
+        }
+        result
+    }
+
+    fn is_closed(&self, context_id: u32, stream_type: StreamType) -> bool {
+        self.closed_streams
+            .borrow()
+            .contains(&(context_id, stream_type))
+    }
+
+    /// Buffers a body until the end of the stream, rejecting it if it exceeds a given limit.
+    fn buffer_body<F>(
+        &self,
+        buffer_type: BufferType,
+        limit: usize,
+        status_code: u32,
+        body_size: usize,
+        end_of_stream: bool,
+        on_complete: F,
+    ) -> Result<Action>
+    where
+        F: FnOnce(&[u8]) -> Result<Action>,
+    {
+        if body_size > limit {
+            hostcalls::send_http_response(status_code, hostcalls::NO_HEADERS, hostcalls::NO_BODY)?;
+            return Ok(Action::Pause);
+        }
+        if !end_of_stream {
+            return Ok(Action::Pause);
+        }
+        self.complete_body(buffer_type, body_size, on_complete)
+    }
+
+    /// Passes the buffered body to a given callback, e.g. once trailers end the stream.
+    fn complete_body<F>(
+        &self,
+        buffer_type: BufferType,
+        body_size: usize,
+        on_complete: F,
+    ) -> Result<Action>
+    where
+        F: FnOnce(&[u8]) -> Result<Action>,
+    {
+        let body = match body_size {
+            0 => None,
+            _ => hostcalls::get_buffer(buffer_type, 0, body_size)?,
+        };
+        on_complete(body.as_ref().map_or(&[], |body| body.as_bytes()))
+    }
+
+    /// Replaces a body chunk with its transformed content.
+    fn transform_body(
+        &self,
+        buffer_type: BufferType,
+        body_size: usize,
+        end_of_stream: bool,
+        transformer: &mut dyn BodyTransformer,
+    ) -> Result<Action> {
+        let chunk = match body_size {
+            0 => None,
+            _ => hostcalls::get_buffer(buffer_type, 0, body_size)?,
+        };
+        let transformed = transformer.transform(
+            chunk.as_ref().map_or(&[], |chunk| chunk.as_bytes()),
+            end_of_stream,
+        );
+        hostcalls::set_buffer(buffer_type, 0, body_size, transformed)?;
+        Ok(Action::Continue)
+    }
+
+    /// Appends data held back by a transformer to the body, e.g. once trailers end the stream.
+    ///
+    /// Hosts that no longer buffer the body by then, e.g. Envoy, cannot take the data,
+    /// so it is dropped with a warning rather than failing the stream.
+    fn flush_body(
+        &self,
+        buffer_type: BufferType,
+        transformer: &mut dyn BodyTransformer,
+    ) -> Result<Action> {
+        let flushed = transformer.transform(&[], true);
+        if flushed.is_empty() {
+            return Ok(Action::Continue);
+        }
+        match hostcalls::set_buffer(buffer_type, usize::MAX, 0, &flushed) {
+            Err(error) if has_status(&*error, Status::NotFound) => {
+                hostcalls::log(
+                    LogLevel::Warn,
+                    &format!(
+                        "dropped {} bytes held back by the body transformer: no body buffer",
+                        flushed.len()
+                    ),
+                )?;
+                Ok(Action::Continue)
+            }
+            result => result.map(|()| Action::Continue),
+        }
+    }
+
+    fn on_http_error(&self, context_id: u32, error: Error) -> Action {
+        log_error(context_id, error);
+        match self.error_policy.get() {
+            ErrorPolicy::FailOpen => Action::Continue,
+            ErrorPolicy::FailClosed => {
+                if let Err(error) =
+                    hostcalls::send_http_response(500, hostcalls::NO_HEADERS, hostcalls::NO_BODY)
+                {
+                    log_error(context_id, error);
+                }
+                Action::Pause
+            }
+        }
+    }
+
+    /// Handles an error of a callback of a given TCP stream, i.e. downstream or upstream data.
+    fn on_stream_error(&self, context_id: u32, stream_type: StreamType, error: Error) -> Action {
+        log_error(context_id, error);
+        match self.error_policy.get() {
+            ErrorPolicy::FailOpen => Action::Continue,
+            ErrorPolicy::FailClosed => {
+                if let Err(error) = hostcalls::close_stream(stream_type) {
+                    log_error(context_id, error);
+                }
+                Action::Pause
+            }
+        }
+    }
+
+    /// Handles an error of a callout callback, which has no action to continue with.
+    fn on_callout_error(&self, context_id: u32, error: Error) {
+        if self.error_policy.get() == ErrorPolicy::FailOpen {
+            log_error(context_id, error);
+        } else if self.http_streams.borrow().contains_key(&context_id) {
+            self.on_http_error(context_id, error);
+        } else if self.streams.borrow().contains_key(&context_id) {
+            self.on_stream_error(context_id, StreamType::Downstream, error);
+        } else {
+            log_error(context_id, error);
+        }
+    }
+
+    /// Calls a given function, isolating a panic in a stream context according to the policy.
+    ///
+    /// Returns `skipped` without calling the function if the context has already failed,
+    /// and `failed` if the context fails now.
+    fn isolate<T, F>(&self, context_id: Option<u32>, skipped: T, failed: T, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        if self.panic_policy.get() == PanicPolicy::Propagate {
+            return f();
+        }
+        if let Some(context_id) = context_id {
+            if self.failed.borrow().contains(&context_id) {
+                return skipped;
+            }
+        }
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(value) => value,
+            Err(payload) => {
+                // Context that was active when the panic occurred.
+                if !self.fail_context(self.active_id.get()) {
+                    panic::resume_unwind(payload)
+                }
+                failed
+            }
+        }
+    }
+
+    /// Returns the action of a response callback skipped for a failed HTTP context.
+    ///
+    /// A local response sent for the context passes through its response callbacks,
+    /// which must not hold it.
+    fn skipped_response_action(&self) -> Action {
+        match self.panic_policy.get() {
+            PanicPolicy::SendLocalResponse(_) => Action::Continue,
+            _ => Action::Pause,
+        }
+    }
+
+    fn fail_context(&self, context_id: u32) -> bool {
+        let http_stream = self.http_streams.borrow_mut().remove(&context_id);
+        let stream = self.streams.borrow_mut().remove(&context_id);
+        if http_stream.is_none() && stream.is_none() {
+            return false;
+        }
+        self.failed.borrow_mut().insert(context_id);
+        let root_context_id = self.root_of(context_id);
+        if let Some(timers) = self.timers.borrow_mut().get_mut(&root_context_id) {
+            timers.remove_context(context_id);
+        }
+        self.queue_handlers
+            .borrow_mut()
+            .retain(|_, entry| entry.context_id != context_id);
+        hostcalls::log(
+            LogLevel::Error,
+            &format!("context {}: panicked, no further callbacks", context_id),
+        )
+        .ok();
+        let closed = match self.panic_policy.get() {
+            PanicPolicy::SendLocalResponse(status_code) if http_stream.is_some() => {
+                hostcalls::send_http_response(
+                    status_code,
+                    hostcalls::NO_HEADERS,
+                    hostcalls::NO_BODY,
+                )
+            }
+            _ if http_stream.is_some() => hostcalls::close_stream(StreamType::Request),
+            _ => hostcalls::close_stream(StreamType::Downstream),
+        };
+        if let Err(error) = closed {
+            log_error(context_id, error);
+        }
+        true
+    }
+
     fn on_create_context(&self, context_id: u32, root_context_id: u32) {
         if root_context_id == 0 {
             self.create_root_context(context_id)
@@ -189,6 +796,11 @@ impl Dispatcher {
         } else {
             panic!("missing constructors")
         }
+        if root_context_id != 0 {
+            self.parents
+                .borrow_mut()
+                .insert(context_id, root_context_id);
+        }
     }
 
     fn on_done(&self, context_id: u32) -> bool {
@@ -222,11 +834,37 @@ impl Dispatcher {
     }
 
     fn on_delete(&self, context_id: u32) {
-        if !(self.http_streams.borrow_mut().remove(&context_id).is_some()
-            || self.streams.borrow_mut().remove(&context_id).is_some()
-            || self.roots.borrow_mut().remove(&context_id).is_some())
-        {
-            panic!("invalid context_id")
+        self.tasks.borrow_mut().remove(&context_id);
+        self.timers.borrow_mut().remove(&context_id);
+        self.queue_handlers
+            .borrow_mut()
+            .retain(|_, entry| entry.root_context_id != context_id);
+        self.parents.borrow_mut().remove(&context_id);
+        self.closed_streams
+            .borrow_mut()
+            .retain(|(id, _)| *id != context_id);
+        if self.failed.borrow_mut().remove(&context_id) {
+            return;
+        }
+        let http_stream = self.http_streams.borrow_mut().remove(&context_id);
+        let stream = self.streams.borrow_mut().remove(&context_id);
+        if http_stream.is_none() && stream.is_none() {
+            if self.roots.borrow_mut().remove(&context_id).is_none() {
+                panic!("invalid context_id")
+            }
+            return;
+        }
+        // Dropping a stream context might panic as well, but there is no stream to close anymore.
+        let contexts = (http_stream, stream);
+        self.active_id.set(context_id);
+        if self.panic_policy.get() == PanicPolicy::Propagate {
+            drop(contexts)
+        } else if panic::catch_unwind(AssertUnwindSafe(move || drop(contexts))).is_err() {
+            hostcalls::log(
+                LogLevel::Error,
+                &format!("context {}: panicked while being deleted", context_id),
+            )
+            .ok();
         }
     }
 
@@ -242,6 +880,7 @@ impl Dispatcher {
     fn on_configure(&self, context_id: u32, plugin_configuration_size: usize) -> bool {
         if let Some(root) = self.roots.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
+            logger::refresh_host_level();
             root.on_configure(plugin_configuration_size)
         } else {
             panic!("invalid context_id")
@@ -249,18 +888,54 @@ impl Dispatcher {
     }
 
     fn on_tick(&self, context_id: u32) {
+        if !self.roots.borrow().contains_key(&context_id) {
+            panic!("invalid context_id")
+        }
+        self.active_id.set(context_id);
+        logger::refresh_host_level();
+        let now = if self.timers.borrow().contains_key(&context_id) {
+            match hostcalls::get_current_time() {
+                Ok(now) => Some(now),
+                Err(error) => {
+                    // Skips the round rather than firing timers at the wrong time.
+                    log_error(context_id, error);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let user_tick = match (now, self.timers.borrow_mut().get_mut(&context_id)) {
+            (Some(now), Some(timers)) => timers.take_user_tick(now),
+            _ => true,
+        };
+        if user_tick {
+            if let Some(root) = self.roots.borrow_mut().get_mut(&context_id) {
+                root.on_tick()
+            }
+        }
+        if let Some(now) = now {
+            self.fire_timers(context_id, now);
+        }
+    }
+
+    fn on_queue_ready(&self, context_id: u32, queue_id: u32) {
+        if self.run_queue_handler(context_id, queue_id) {
+            return;
+        }
         if let Some(root) = self.roots.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            root.on_tick()
+            root.on_queue_ready(queue_id)
         } else {
             panic!("invalid context_id")
         }
     }
 
-    fn on_queue_ready(&self, context_id: u32, queue_id: u32) {
+    #[cfg(feature = "abi-0-2-1")]
+    fn on_foreign_function(&self, context_id: u32, function_id: u32, arguments_size: usize) {
         if let Some(root) = self.roots.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            root.on_queue_ready(queue_id)
+            root.on_foreign_function(function_id, arguments_size)
         } else {
             panic!("invalid context_id")
         }
@@ -276,9 +951,15 @@ impl Dispatcher {
     }
 
     fn on_downstream_data(&self, context_id: u32, data_size: usize, end_of_stream: bool) -> Action {
+        if self.is_closed(context_id, StreamType::Downstream) {
+            return Action::Pause;
+        }
         if let Some(stream) = self.streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            stream.on_downstream_data(data_size, end_of_stream)
+            match stream.try_on_downstream_data(data_size, end_of_stream) {
+                Ok(action) => action,
+                Err(error) => self.on_stream_error(context_id, StreamType::Downstream, error),
+            }
         } else {
             panic!("invalid context_id")
         }
@@ -294,9 +975,15 @@ impl Dispatcher {
     }
 
     fn on_upstream_data(&self, context_id: u32, data_size: usize, end_of_stream: bool) -> Action {
+        if self.is_closed(context_id, StreamType::Upstream) {
+            return Action::Pause;
+        }
         if let Some(stream) = self.streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            stream.on_upstream_data(data_size, end_of_stream)
+            match stream.try_on_upstream_data(data_size, end_of_stream) {
+                Ok(action) => action,
+                Err(error) => self.on_stream_error(context_id, StreamType::Upstream, error),
+            }
         } else {
             panic!("invalid context_id")
         }
@@ -317,9 +1004,34 @@ impl Dispatcher {
         num_headers: usize,
         end_of_stream: bool,
     ) -> Action {
+        if self.is_closed(context_id, StreamType::Request) {
+            return Action::Pause;
+        }
         if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            http_stream.on_http_request_headers(num_headers, end_of_stream)
+            let result = http_stream
+                .try_on_http_request_headers(num_headers, end_of_stream)
+                .and_then(|action| {
+                    // A body limit takes precedence over a transformer, as in the body callbacks.
+                    if http_stream.http_request_body_limit().is_some() {
+                        if end_of_stream && action == Action::Continue {
+                            return http_stream.try_on_http_request_body_complete(&[]);
+                        }
+                    } else if !end_of_stream
+                        && http_stream.http_request_body_transformer().is_some()
+                    {
+                        hostcalls::set_map_value(
+                            MapType::HttpRequestHeaders,
+                            "content-length",
+                            None::<&str>,
+                        )?;
+                    }
+                    Ok(action)
+                });
+            match result {
+                Ok(action) => action,
+                Err(error) => self.on_http_error(context_id, error),
+            }
         } else {
             panic!("invalid context_id")
         }
@@ -331,18 +1043,63 @@ impl Dispatcher {
         body_size: usize,
         end_of_stream: bool,
     ) -> Action {
+        if self.is_closed(context_id, StreamType::Request) {
+            return Action::Pause;
+        }
         if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            http_stream.on_http_request_body(body_size, end_of_stream)
+            let result = match http_stream.http_request_body_limit() {
+                Some(limit) => self.buffer_body(
+                    BufferType::HttpRequestBody,
+                    limit,
+                    http_stream.http_request_body_limit_status(),
+                    body_size,
+                    end_of_stream,
+                    |body| http_stream.try_on_http_request_body_complete(body),
+                ),
+                None => match http_stream.http_request_body_transformer() {
+                    Some(transformer) => self.transform_body(
+                        BufferType::HttpRequestBody,
+                        body_size,
+                        end_of_stream,
+                        transformer,
+                    ),
+                    None => http_stream.try_on_http_request_body(body_size, end_of_stream),
+                },
+            };
+            match result {
+                Ok(action) => action,
+                Err(error) => self.on_http_error(context_id, error),
+            }
         } else {
             panic!("invalid context_id")
         }
     }
 
     fn on_http_request_trailers(&self, context_id: u32, num_trailers: usize) -> Action {
+        if self.is_closed(context_id, StreamType::Request) {
+            return Action::Pause;
+        }
         if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            http_stream.on_http_request_trailers(num_trailers)
+            // Trailers end the stream, so a buffered or transformed body is complete.
+            let result = match http_stream.http_request_body_limit() {
+                Some(_) => self.complete_body(BufferType::HttpRequestBody, usize::MAX, |body| {
+                    http_stream.try_on_http_request_body_complete(body)
+                }),
+                None => match http_stream.http_request_body_transformer() {
+                    Some(transformer) => self.flush_body(BufferType::HttpRequestBody, transformer),
+                    None => Ok(Action::Continue),
+                },
+            }
+            .and_then(|action| match action {
+                Action::Continue => http_stream.try_on_http_request_trailers(num_trailers),
+                action => Ok(action),
+            });
+            match result {
+                Ok(action) => action,
+                Err(error) => self.on_http_error(context_id, error),
+            }
         } else {
             panic!("invalid context_id")
         }
@@ -354,9 +1111,34 @@ impl Dispatcher {
         num_headers: usize,
         end_of_stream: bool,
     ) -> Action {
+        if self.is_closed(context_id, StreamType::Response) {
+            return Action::Pause;
+        }
         if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            http_stream.on_http_response_headers(num_headers, end_of_stream)
+            let result = http_stream
+                .try_on_http_response_headers(num_headers, end_of_stream)
+                .and_then(|action| {
+                    // A body limit takes precedence over a transformer, as in the body callbacks.
+                    if http_stream.http_response_body_limit().is_some() {
+                        if end_of_stream && action == Action::Continue {
+                            return http_stream.try_on_http_response_body_complete(&[]);
+                        }
+                    } else if !end_of_stream
+                        && http_stream.http_response_body_transformer().is_some()
+                    {
+                        hostcalls::set_map_value(
+                            MapType::HttpResponseHeaders,
+                            "content-length",
+                            None::<&str>,
+                        )?;
+                    }
+                    Ok(action)
+                });
+            match result {
+                Ok(action) => action,
+                Err(error) => self.on_http_error(context_id, error),
+            }
         } else {
             panic!("invalid context_id")
         }
@@ -368,18 +1150,63 @@ impl Dispatcher {
         body_size: usize,
         end_of_stream: bool,
     ) -> Action {
+        if self.is_closed(context_id, StreamType::Response) {
+            return Action::Pause;
+        }
         if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            http_stream.on_http_response_body(body_size, end_of_stream)
+            let result = match http_stream.http_response_body_limit() {
+                Some(limit) => self.buffer_body(
+                    BufferType::HttpResponseBody,
+                    limit,
+                    http_stream.http_response_body_limit_status(),
+                    body_size,
+                    end_of_stream,
+                    |body| http_stream.try_on_http_response_body_complete(body),
+                ),
+                None => match http_stream.http_response_body_transformer() {
+                    Some(transformer) => self.transform_body(
+                        BufferType::HttpResponseBody,
+                        body_size,
+                        end_of_stream,
+                        transformer,
+                    ),
+                    None => http_stream.try_on_http_response_body(body_size, end_of_stream),
+                },
+            };
+            match result {
+                Ok(action) => action,
+                Err(error) => self.on_http_error(context_id, error),
+            }
         } else {
             panic!("invalid context_id")
         }
     }
 
     fn on_http_response_trailers(&self, context_id: u32, num_trailers: usize) -> Action {
+        if self.is_closed(context_id, StreamType::Response) {
+            return Action::Pause;
+        }
         if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
             self.active_id.set(context_id);
-            http_stream.on_http_response_trailers(num_trailers)
+            // Trailers end the stream, so a buffered or transformed body is complete.
+            let result = match http_stream.http_response_body_limit() {
+                Some(_) => self.complete_body(BufferType::HttpResponseBody, usize::MAX, |body| {
+                    http_stream.try_on_http_response_body_complete(body)
+                }),
+                None => match http_stream.http_response_body_transformer() {
+                    Some(transformer) => self.flush_body(BufferType::HttpResponseBody, transformer),
+                    None => Ok(Action::Continue),
+                },
+            }
+            .and_then(|action| match action {
+                Action::Continue => http_stream.try_on_http_response_trailers(num_trailers),
+                action => Ok(action),
+            });
+            match result {
+                Ok(action) => action,
+                Err(error) => self.on_http_error(context_id, error),
+            }
         } else {
             panic!("invalid context_id")
         }
@@ -392,24 +1219,131 @@ impl Dispatcher {
         body_size: usize,
         num_trailers: usize,
     ) {
-        if let Some(context_id) = self.callouts.borrow_mut().remove(&token_id) {
-            if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id) {
-                self.active_id.set(context_id);
-                hostcalls::set_effective_context(context_id).unwrap();
-                http_stream.on_http_call_response(token_id, num_headers, body_size, num_trailers)
-            } else if let Some(stream) = self.streams.borrow_mut().get_mut(&context_id) {
-                self.active_id.set(context_id);
-                hostcalls::set_effective_context(context_id).unwrap();
-                stream.on_http_call_response(token_id, num_headers, body_size, num_trailers)
-            } else if let Some(root) = self.roots.borrow_mut().get_mut(&context_id) {
-                self.active_id.set(context_id);
-                hostcalls::set_effective_context(context_id).unwrap();
-                root.on_http_call_response(token_id, num_headers, body_size, num_trailers)
+        let context_id = self.callouts.borrow_mut().remove(&token_id);
+        let async_response = self.async_callouts.borrow_mut().remove(&token_id);
+        if let (Some(context_id), Some(async_response)) = (context_id, async_response) {
+            if !self.exists(context_id) {
+                // The future has been dropped together with its context.
+                return;
             }
+            self.active_id.set(context_id);
+            let response = hostcalls::set_effective_context(context_id)
+                .and_then(|()| HttpCallResponse::load(num_headers, body_size, num_trailers));
+            async_response.replace(Some(response));
+            self.poll_tasks(context_id);
+        } else if let Some(context_id) = context_id {
+            self.with_callout_context(context_id, |context| {
+                context.try_on_http_call_response(token_id, num_headers, body_size, num_trailers)
+            })
         } else {
             panic!("invalid token_id")
         }
     }
+
+    /// Calls a given function with the context that has made a callout, as the effective context,
+    /// and handles its error.
+    ///
+    /// Does nothing if the context no longer exists.
+    fn with_callout_context<F>(&self, context_id: u32, f: F)
+    where
+        F: FnOnce(&mut dyn Context) -> Result<()>,
+    {
+        let activate = || {
+            self.active_id.set(context_id);
+            hostcalls::set_effective_context(context_id).unwrap();
+        };
+        let result = if let Some(http_stream) = self.http_streams.borrow_mut().get_mut(&context_id)
+        {
+            activate();
+            f(http_stream.as_mut())
+        } else if let Some(stream) = self.streams.borrow_mut().get_mut(&context_id) {
+            activate();
+            f(stream.as_mut())
+        } else if let Some(root) = self.roots.borrow_mut().get_mut(&context_id) {
+            activate();
+            f(root.as_mut())
+        } else {
+            return;
+        };
+        if let Err(error) = result {
+            self.on_callout_error(context_id, error);
+        }
+    }
+
+    fn on_grpc_receive_initial_metadata(&self, token_id: u32, num_elements: usize) {
+        let context_id = self.grpc_streams.borrow().get(&token_id).copied();
+        if let Some(context_id) = context_id {
+            self.with_callout_context(context_id, |context| {
+                context.on_grpc_receive_initial_metadata(token_id, num_elements);
+                Ok(())
+            })
+        } else if !self.grpc_callouts.borrow().contains_key(&token_id) {
+            panic!("invalid token_id")
+        }
+    }
+
+    fn on_grpc_receive(&self, token_id: u32, response_size: usize) {
+        let call_context_id = self.grpc_callouts.borrow_mut().remove(&token_id);
+        let stream_context_id = self.grpc_streams.borrow().get(&token_id).copied();
+        if let Some(context_id) = call_context_id {
+            self.with_callout_context(context_id, |context| {
+                context.try_on_grpc_call_response(token_id, 0, response_size)
+            })
+        } else if let Some(context_id) = stream_context_id {
+            self.with_callout_context(context_id, |context| {
+                context.on_grpc_receive(token_id, response_size);
+                Ok(())
+            })
+        } else {
+            panic!("invalid token_id")
+        }
+    }
+
+    fn on_grpc_receive_trailing_metadata(&self, token_id: u32, num_elements: usize) {
+        let context_id = self.grpc_streams.borrow().get(&token_id).copied();
+        if let Some(context_id) = context_id {
+            self.with_callout_context(context_id, |context| {
+                context.on_grpc_receive_trailing_metadata(token_id, num_elements);
+                Ok(())
+            })
+        } else if !self.grpc_callouts.borrow().contains_key(&token_id) {
+            panic!("invalid token_id")
+        }
+    }
+
+    fn on_grpc_close(&self, token_id: u32, status_code: u32) {
+        let call_context_id = self.grpc_callouts.borrow_mut().remove(&token_id);
+        let stream_context_id = self.grpc_streams.borrow_mut().remove(&token_id);
+        if let Some(context_id) = call_context_id {
+            self.with_callout_context(context_id, |context| {
+                context.try_on_grpc_call_response(token_id, status_code, 0)
+            })
+        } else if let Some(context_id) = stream_context_id {
+            self.with_callout_context(context_id, |context| {
+                context.on_grpc_close(token_id, status_code);
+                Ok(())
+            })
+        } else {
+            panic!("invalid token_id")
+        }
+    }
+}
+
+fn log_error(context_id: u32, error: Error) {
+    hostcalls::log(
+        LogLevel::Error,
+        &format!("context {}: {}", context_id, error),
+    )
+    .ok();
+}
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
 }
 
 #[no_mangle]
@@ -419,12 +1353,18 @@ pub extern "C" fn proxy_on_context_create(context_id: u32, root_context_id: u32)
 
 #[no_mangle]
 pub extern "C" fn proxy_on_done(context_id: u32) -> bool {
-    DISPATCHER.with(|dispatcher| dispatcher.on_done(context_id))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), true, true, || {
+            dispatcher.on_done(context_id)
+        })
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn proxy_on_log(context_id: u32) {
-    DISPATCHER.with(|dispatcher| dispatcher.on_log(context_id))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), (), (), || dispatcher.on_log(context_id))
+    })
 }
 
 #[no_mangle]
@@ -452,9 +1392,24 @@ pub extern "C" fn proxy_on_queue_ready(context_id: u32, queue_id: u32) {
     DISPATCHER.with(|dispatcher| dispatcher.on_queue_ready(context_id, queue_id))
 }
 
+#[cfg(feature = "abi-0-2-1")]
+#[no_mangle]
+pub extern "C" fn proxy_on_foreign_function(
+    context_id: u32,
+    function_id: u32,
+    arguments_size: usize,
+) {
+    DISPATCHER
+        .with(|dispatcher| dispatcher.on_foreign_function(context_id, function_id, arguments_size))
+}
+
 #[no_mangle]
 pub extern "C" fn proxy_on_new_connection(context_id: u32) -> Action {
-    DISPATCHER.with(|dispatcher| dispatcher.on_new_connection(context_id))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_new_connection(context_id)
+        })
+    })
 }
 
 #[no_mangle]
@@ -463,13 +1418,20 @@ pub extern "C" fn proxy_on_downstream_data(
     data_size: usize,
     end_of_stream: bool,
 ) -> Action {
-    DISPATCHER
-        .with(|dispatcher| dispatcher.on_downstream_data(context_id, data_size, end_of_stream))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_downstream_data(context_id, data_size, end_of_stream)
+        })
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn proxy_on_downstream_connection_close(context_id: u32, peer_type: PeerType) {
-    DISPATCHER.with(|dispatcher| dispatcher.on_downstream_close(context_id, peer_type))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), (), (), || {
+            dispatcher.on_downstream_close(context_id, peer_type)
+        })
+    })
 }
 
 #[no_mangle]
@@ -478,14 +1440,23 @@ pub extern "C" fn proxy_on_upstream_data(
     data_size: usize,
     end_of_stream: bool,
 ) -> Action {
-    DISPATCHER.with(|dispatcher| dispatcher.on_upstream_data(context_id, data_size, end_of_stream))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_upstream_data(context_id, data_size, end_of_stream)
+        })
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn proxy_on_upstream_connection_close(context_id: u32, peer_type: PeerType) {
-    DISPATCHER.with(|dispatcher| dispatcher.on_upstream_close(context_id, peer_type))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), (), (), || {
+            dispatcher.on_upstream_close(context_id, peer_type)
+        })
+    })
 }
 
+#[cfg(feature = "abi-0-2-1")]
 #[no_mangle]
 pub extern "C" fn proxy_on_request_headers(
     context_id: u32,
@@ -493,7 +1464,20 @@ pub extern "C" fn proxy_on_request_headers(
     end_of_stream: bool,
 ) -> Action {
     DISPATCHER.with(|dispatcher| {
-        dispatcher.on_http_request_headers(context_id, num_headers, end_of_stream)
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_http_request_headers(context_id, num_headers, end_of_stream)
+        })
+    })
+}
+
+// ABI 0.1.0 doesn't signal the end of stream with headers.
+#[cfg(not(feature = "abi-0-2-1"))]
+#[no_mangle]
+pub extern "C" fn proxy_on_request_headers(context_id: u32, num_headers: usize) -> Action {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_http_request_headers(context_id, num_headers, false)
+        })
     })
 }
 
@@ -503,15 +1487,23 @@ pub extern "C" fn proxy_on_request_body(
     body_size: usize,
     end_of_stream: bool,
 ) -> Action {
-    DISPATCHER
-        .with(|dispatcher| dispatcher.on_http_request_body(context_id, body_size, end_of_stream))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_http_request_body(context_id, body_size, end_of_stream)
+        })
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn proxy_on_request_trailers(context_id: u32, num_trailers: usize) -> Action {
-    DISPATCHER.with(|dispatcher| dispatcher.on_http_request_trailers(context_id, num_trailers))
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(Some(context_id), Action::Pause, Action::Pause, || {
+            dispatcher.on_http_request_trailers(context_id, num_trailers)
+        })
+    })
 }
 
+#[cfg(feature = "abi-0-2-1")]
 #[no_mangle]
 pub extern "C" fn proxy_on_response_headers(
     context_id: u32,
@@ -519,7 +1511,22 @@ pub extern "C" fn proxy_on_response_headers(
     end_of_stream: bool,
 ) -> Action {
     DISPATCHER.with(|dispatcher| {
-        dispatcher.on_http_response_headers(context_id, num_headers, end_of_stream)
+        let skipped = dispatcher.skipped_response_action();
+        dispatcher.isolate(Some(context_id), skipped, Action::Pause, || {
+            dispatcher.on_http_response_headers(context_id, num_headers, end_of_stream)
+        })
+    })
+}
+
+// ABI 0.1.0 doesn't signal the end of stream with headers.
+#[cfg(not(feature = "abi-0-2-1"))]
+#[no_mangle]
+pub extern "C" fn proxy_on_response_headers(context_id: u32, num_headers: usize) -> Action {
+    DISPATCHER.with(|dispatcher| {
+        let skipped = dispatcher.skipped_response_action();
+        dispatcher.isolate(Some(context_id), skipped, Action::Pause, || {
+            dispatcher.on_http_response_headers(context_id, num_headers, false)
+        })
     })
 }
 
@@ -529,13 +1536,22 @@ pub extern "C" fn proxy_on_response_body(
     body_size: usize,
     end_of_stream: bool,
 ) -> Action {
-    DISPATCHER
-        .with(|dispatcher| dispatcher.on_http_response_body(context_id, body_size, end_of_stream))
+    DISPATCHER.with(|dispatcher| {
+        let skipped = dispatcher.skipped_response_action();
+        dispatcher.isolate(Some(context_id), skipped, Action::Pause, || {
+            dispatcher.on_http_response_body(context_id, body_size, end_of_stream)
+        })
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn proxy_on_response_trailers(context_id: u32, num_trailers: usize) -> Action {
-    DISPATCHER.with(|dispatcher| dispatcher.on_http_response_trailers(context_id, num_trailers))
+    DISPATCHER.with(|dispatcher| {
+        let skipped = dispatcher.skipped_response_action();
+        dispatcher.isolate(Some(context_id), skipped, Action::Pause, || {
+            dispatcher.on_http_response_trailers(context_id, num_trailers)
+        })
+    })
 }
 
 #[no_mangle]
@@ -547,6 +1563,52 @@ pub extern "C" fn proxy_on_http_call_response(
     num_trailers: usize,
 ) {
     DISPATCHER.with(|dispatcher| {
-        dispatcher.on_http_call_response(token_id, num_headers, body_size, num_trailers)
+        dispatcher.isolate(None, (), (), || {
+            dispatcher.on_http_call_response(token_id, num_headers, body_size, num_trailers)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_on_grpc_receive_initial_metadata(
+    _context_id: u32,
+    token_id: u32,
+    num_elements: usize,
+) {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(None, (), (), || {
+            dispatcher.on_grpc_receive_initial_metadata(token_id, num_elements)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_on_grpc_receive(_context_id: u32, token_id: u32, response_size: usize) {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(None, (), (), || {
+            dispatcher.on_grpc_receive(token_id, response_size)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_on_grpc_receive_trailing_metadata(
+    _context_id: u32,
+    token_id: u32,
+    num_elements: usize,
+) {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(None, (), (), || {
+            dispatcher.on_grpc_receive_trailing_metadata(token_id, num_elements)
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn proxy_on_grpc_close(_context_id: u32, token_id: u32, status_code: u32) {
+    DISPATCHER.with(|dispatcher| {
+        dispatcher.isolate(None, (), (), || {
+            dispatcher.on_grpc_close(token_id, status_code)
+        })
     })
 }
@@ -65,6 +65,16 @@ impl<'a> fmt::Display for HostCallError<'a> {
 
 impl<'a> std::error::Error for HostCallError<'a> {}
 
+/// Returns whether a given error is a hostcall failing with a given status.
+pub(crate) fn has_status(
+    error: &(dyn std::error::Error + Send + Sync + 'static),
+    status: Status,
+) -> bool {
+    error
+        .downcast_ref::<HostCallError<'static>>()
+        .is_some_and(|error| error.status() == status)
+}
+
 /// An error to parse the response from a Host ABI.
 #[derive(Debug)]
 pub struct HostResponseError<'a> {
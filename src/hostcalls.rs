@@ -28,8 +28,11 @@ pub const NO_BODY: Option<&[u8]> = None;
 /// Represents empty trailers map.
 pub const NO_TRAILERS: &[(&[u8], &[u8])] = &[];
 
-mod abi {
+/// Names of the functions imported from the host.
+pub mod abi {
     pub const PROXY_LOG: &str = "proxy_log";
+    #[cfg(feature = "abi-0-2-1")]
+    pub const PROXY_GET_LOG_LEVEL: &str = "proxy_get_log_level";
     pub const PROXY_GET_CURRENT_TIME_NANOSECONDS: &str = "proxy_get_current_time_nanoseconds";
     pub const PROXY_SET_TICK_PERIOD_MILLISECONDS: &str = "proxy_set_tick_period_milliseconds";
     pub const PROXY_GET_BUFFER_BYTES: &str = "proxy_get_buffer_bytes";
@@ -48,12 +51,28 @@ mod abi {
     pub const PROXY_RESOLVE_SHARED_QUEUE: &str = "proxy_resolve_shared_queue";
     pub const PROXY_DEQUEUE_SHARED_QUEUE: &str = "proxy_dequeue_shared_queue";
     pub const PROXY_ENQUEUE_SHARED_QUEUE: &str = "proxy_enqueue_shared_queue";
+    #[cfg(feature = "abi-0-2-1")]
     pub const PROXY_CONTINUE_STREAM: &str = "proxy_continue_stream";
+    #[cfg(not(feature = "abi-0-2-1"))]
+    pub const PROXY_CONTINUE_REQUEST: &str = "proxy_continue_request";
+    #[cfg(not(feature = "abi-0-2-1"))]
+    pub const PROXY_CONTINUE_RESPONSE: &str = "proxy_continue_response";
     pub const PROXY_CLOSE_STREAM: &str = "proxy_close_stream";
     pub const PROXY_SEND_LOCAL_RESPONSE: &str = "proxy_send_local_response";
     pub const PROXY_HTTP_CALL: &str = "proxy_http_call";
+    pub const PROXY_GRPC_CALL: &str = "proxy_grpc_call";
+    pub const PROXY_GRPC_STREAM: &str = "proxy_grpc_stream";
+    pub const PROXY_GRPC_SEND: &str = "proxy_grpc_send";
+    pub const PROXY_GRPC_CANCEL: &str = "proxy_grpc_cancel";
+    pub const PROXY_GRPC_CLOSE: &str = "proxy_grpc_close";
     pub const PROXY_SET_EFFECTIVE_CONTEXT: &str = "proxy_set_effective_context";
     pub const PROXY_DONE: &str = "proxy_done";
+    pub const PROXY_DEFINE_METRIC: &str = "proxy_define_metric";
+    pub const PROXY_INCREMENT_METRIC: &str = "proxy_increment_metric";
+    pub const PROXY_RECORD_METRIC: &str = "proxy_record_metric";
+    pub const PROXY_GET_METRIC: &str = "proxy_get_metric";
+    #[cfg(feature = "abi-0-2-1")]
+    pub const PROXY_CALL_FOREIGN_FUNCTION: &str = "proxy_call_foreign_function";
 }
 
 extern "C" {
@@ -70,6 +89,35 @@ pub fn log(level: LogLevel, message: &str) -> Result<()> {
     }
 }
 
+#[cfg(feature = "abi-0-2-1")]
+extern "C" {
+    fn proxy_get_log_level(return_level: *mut u32) -> Status;
+}
+
+/// Returns the log level configured in the host.
+#[cfg(feature = "abi-0-2-1")]
+pub fn get_log_level() -> Result<LogLevel> {
+    let mut return_level: u32 = 0;
+    unsafe {
+        match proxy_get_log_level(&mut return_level) {
+            Status::Ok => match return_level {
+                0 => Ok(LogLevel::Trace),
+                1 => Ok(LogLevel::Debug),
+                2 => Ok(LogLevel::Info),
+                3 => Ok(LogLevel::Warn),
+                4 => Ok(LogLevel::Error),
+                5 => Ok(LogLevel::Critical),
+                level => Err(HostResponseError::new(
+                    abi::PROXY_GET_LOG_LEVEL,
+                    format!("unknown log level: {}", level).into(),
+                )
+                .into()),
+            },
+            status => Err(HostCallError::new(abi::PROXY_GET_LOG_LEVEL, status).into()),
+        }
+    }
+}
+
 extern "C" {
     fn proxy_get_current_time_nanoseconds(return_time: *mut u64) -> Status;
 }
@@ -131,9 +179,11 @@ pub fn get_buffer(
         ) {
             Status::Ok => {
                 if !return_data.is_null() {
-                    Ok(Vec::from_raw_parts(return_data, return_size, return_size))
-                        .map(ByteString::from)
-                        .map(Option::from)
+                    Ok(Some(ByteString::from(Vec::from_raw_parts(
+                        return_data,
+                        return_size,
+                        return_size,
+                    ))))
                 } else {
                     Ok(None)
                 }
@@ -293,9 +343,11 @@ where
         ) {
             Status::Ok => {
                 if !return_data.is_null() {
-                    Ok(Vec::from_raw_parts(return_data, return_size, return_size))
-                        .map(ByteString::from)
-                        .map(Option::from)
+                    Ok(Some(ByteString::from(Vec::from_raw_parts(
+                        return_data,
+                        return_size,
+                        return_size,
+                    ))))
                 } else {
                     Ok(None)
                 }
@@ -449,9 +501,11 @@ where
         ) {
             Status::Ok => {
                 if !return_data.is_null() {
-                    Ok(Vec::from_raw_parts(return_data, return_size, return_size))
-                        .map(ByteString::from)
-                        .map(Option::from)
+                    Ok(Some(ByteString::from(Vec::from_raw_parts(
+                        return_data,
+                        return_size,
+                        return_size,
+                    ))))
                 } else {
                     Ok(None)
                 }
@@ -490,7 +544,7 @@ where
     V: AsRef<[u8]>,
 {
     let serialized_path = utils::serialize_property_path(path);
-    let (value_ptr, value_len) = value.map_or((null(), 0), |value| {
+    let (value_ptr, value_len) = value.as_ref().map_or((null(), 0), |value| {
         (value.as_ref().as_ptr(), value.as_ref().len())
     });
     unsafe {
@@ -551,8 +605,11 @@ where
                 };
                 if !return_data.is_null() {
                     Ok((
-                        Some(Vec::from_raw_parts(return_data, return_size, return_size))
-                            .map(ByteString::from),
+                        Some(ByteString::from(Vec::from_raw_parts(
+                            return_data,
+                            return_size,
+                            return_size,
+                        ))),
                         cas,
                     ))
                 } else {
@@ -593,7 +650,7 @@ where
     K: AsRef<str>,
     V: AsRef<[u8]>,
 {
-    let (value_ptr, value_len) = value.map_or((null(), 0), |value| {
+    let (value_ptr, value_len) = value.as_ref().map_or((null(), 0), |value| {
         (value.as_ref().as_ptr(), value.as_ref().len())
     });
     unsafe {
@@ -673,9 +730,11 @@ pub fn dequeue_shared_queue(queue_id: u32) -> Result<Option<ByteString>> {
         match proxy_dequeue_shared_queue(queue_id, &mut return_data, &mut return_size) {
             Status::Ok => {
                 if !return_data.is_null() {
-                    Ok(Vec::from_raw_parts(return_data, return_size, return_size))
-                        .map(ByteString::from)
-                        .map(Option::from)
+                    Ok(Some(ByteString::from(Vec::from_raw_parts(
+                        return_data,
+                        return_size,
+                        return_size,
+                    ))))
                 } else {
                     Ok(None)
                 }
@@ -711,7 +770,7 @@ pub fn enqueue_shared_queue<V>(queue_id: u32, value: Option<V>) -> Result<()>
 where
     V: AsRef<[u8]>,
 {
-    let (value_ptr, value_len) = value.map_or((null(), 0), |value| {
+    let (value_ptr, value_len) = value.as_ref().map_or((null(), 0), |value| {
         (value.as_ref().as_ptr(), value.as_ref().len())
     });
     unsafe {
@@ -722,11 +781,13 @@ where
     }
 }
 
+#[cfg(feature = "abi-0-2-1")]
 extern "C" {
     fn proxy_continue_stream(stream: StreamType) -> Status;
 }
 
 /// Resumes processing of a given stream, i.e. HTTP request or HTTP response.
+#[cfg(feature = "abi-0-2-1")]
 pub fn continue_stream(stream_type: StreamType) -> Result<()> {
     unsafe {
         match proxy_continue_stream(stream_type) {
@@ -736,15 +797,54 @@ pub fn continue_stream(stream_type: StreamType) -> Result<()> {
     }
 }
 
+#[cfg(not(feature = "abi-0-2-1"))]
+extern "C" {
+    fn proxy_continue_request() -> Status;
+    fn proxy_continue_response() -> Status;
+}
+
+/// Resumes processing of a given stream, i.e. HTTP request or HTTP response.
+///
+/// ABI 0.1.0 cannot resume TCP streams, so `Downstream` and `Upstream` are rejected
+/// without calling the host.
+#[cfg(not(feature = "abi-0-2-1"))]
+pub fn continue_stream(stream_type: StreamType) -> Result<()> {
+    unsafe {
+        let (function_name, status) = match stream_type {
+            StreamType::Request => (abi::PROXY_CONTINUE_REQUEST, proxy_continue_request()),
+            StreamType::Response => (abi::PROXY_CONTINUE_RESPONSE, proxy_continue_response()),
+            _ => {
+                return Err(format!("cannot continue {:?} stream on ABI 0.1.0", stream_type).into())
+            }
+        };
+        match status {
+            Status::Ok => Ok(()),
+            status => Err(HostCallError::new(function_name, status).into()),
+        }
+    }
+}
+
 extern "C" {
     fn proxy_close_stream(stream: StreamType) -> Status;
 }
 
-/// Terminates processing of a given stream, i.e. HTTP request or HTTP response.
+/// Terminates processing of a given stream, i.e. HTTP request or HTTP response,
+/// or downstream or upstream data of a TCP stream.
+///
+/// Callbacks for a closed stream are no longer called.
+///
+/// Fails without calling the host if the current callback is one of a root context, e.g.
+/// `on_tick`, since the host would apply it to the root context rather than to any stream.
 pub fn close_stream(stream_type: StreamType) -> Result<()> {
+    if dispatcher::is_root_active() {
+        return Err(format!("cannot close {:?} stream from a root context", stream_type).into());
+    }
     unsafe {
         match proxy_close_stream(stream_type) {
-            Status::Ok => Ok(()),
+            Status::Ok => {
+                dispatcher::register_closed_stream(stream_type);
+                Ok(())
+            }
             status => Err(HostCallError::new(abi::PROXY_CLOSE_STREAM, status).into()),
         }
     }
@@ -791,7 +891,7 @@ where
     B: AsRef<[u8]>,
 {
     let serialized_headers = utils::serialize_map(headers);
-    let (body_ptr, body_len) = body.map_or((null(), 0), |body| {
+    let (body_ptr, body_len) = body.as_ref().map_or((null(), 0), |body| {
         (body.as_ref().as_ptr(), body.as_ref().len())
     });
     unsafe {
@@ -865,7 +965,7 @@ where
 {
     let serialized_headers = utils::serialize_map(headers);
     let serialized_trailers = utils::serialize_map(trailers);
-    let (body_ptr, body_len) = body.map_or((null(), 0), |body| {
+    let (body_ptr, body_len) = body.as_ref().map_or((null(), 0), |body| {
         (body.as_ref().as_ptr(), body.as_ref().len())
     });
     let mut return_token: u32 = 0;
@@ -891,6 +991,210 @@ where
     }
 }
 
+extern "C" {
+    fn proxy_grpc_call(
+        upstream_data: *const u8,
+        upstream_size: usize,
+        service_name_data: *const u8,
+        service_name_size: usize,
+        method_name_data: *const u8,
+        method_name_size: usize,
+        initial_metadata_data: *const u8,
+        initial_metadata_size: usize,
+        message_data: *const u8,
+        message_size: usize,
+        timeout: u32,
+        return_token: *mut u32,
+    ) -> Status;
+}
+
+/// Dispatches a unary gRPC call to a given upstream.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::hostcalls;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let request_handle = hostcalls::dispatch_grpc_call(
+///     "cluster_name",
+///     "envoy.service.auth.v3.Authorization",
+///     "Check",
+///     &vec![("x-request-id", "abc")],
+///     Some(b"serialized request"),
+///     Duration::from_secs(10),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn dispatch_grpc_call<K, V, M>(
+    upstream: &str,
+    service_name: &str,
+    method_name: &str,
+    initial_metadata: &[(K, V)],
+    message: Option<M>,
+    timeout: Duration,
+) -> Result<u32>
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+    M: AsRef<[u8]>,
+{
+    let serialized_initial_metadata = utils::serialize_map(initial_metadata);
+    let (message_ptr, message_len) = message.as_ref().map_or((null(), 0), |message| {
+        (message.as_ref().as_ptr(), message.as_ref().len())
+    });
+    let mut return_token: u32 = 0;
+    unsafe {
+        match proxy_grpc_call(
+            upstream.as_ptr(),
+            upstream.len(),
+            service_name.as_ptr(),
+            service_name.len(),
+            method_name.as_ptr(),
+            method_name.len(),
+            serialized_initial_metadata.as_ptr(),
+            serialized_initial_metadata.len(),
+            message_ptr,
+            message_len,
+            timeout.as_millis() as u32,
+            &mut return_token,
+        ) {
+            Status::Ok => {
+                dispatcher::register_grpc_callout(return_token);
+                Ok(return_token)
+            }
+            status => Err(HostCallError::new(abi::PROXY_GRPC_CALL, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_grpc_stream(
+        upstream_data: *const u8,
+        upstream_size: usize,
+        service_name_data: *const u8,
+        service_name_size: usize,
+        method_name_data: *const u8,
+        method_name_size: usize,
+        initial_metadata_data: *const u8,
+        initial_metadata_size: usize,
+        return_token: *mut u32,
+    ) -> Status;
+}
+
+/// Opens a bidirectional gRPC stream to a given upstream.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::hostcalls;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let stream_handle = hostcalls::open_grpc_stream(
+///     "cluster_name",
+///     "envoy.service.ratelimit.v3.RateLimitService",
+///     "ShouldRateLimit",
+///     &vec![("x-request-id", "abc")],
+/// )?;
+/// hostcalls::send_grpc_stream_message(stream_handle, Some(b"serialized message"), false)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn open_grpc_stream<K, V>(
+    upstream: &str,
+    service_name: &str,
+    method_name: &str,
+    initial_metadata: &[(K, V)],
+) -> Result<u32>
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+{
+    let serialized_initial_metadata = utils::serialize_map(initial_metadata);
+    let mut return_token: u32 = 0;
+    unsafe {
+        match proxy_grpc_stream(
+            upstream.as_ptr(),
+            upstream.len(),
+            service_name.as_ptr(),
+            service_name.len(),
+            method_name.as_ptr(),
+            method_name.len(),
+            serialized_initial_metadata.as_ptr(),
+            serialized_initial_metadata.len(),
+            &mut return_token,
+        ) {
+            Status::Ok => {
+                dispatcher::register_grpc_stream(return_token);
+                Ok(return_token)
+            }
+            status => Err(HostCallError::new(abi::PROXY_GRPC_STREAM, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_grpc_send(
+        token: u32,
+        message_data: *const u8,
+        message_size: usize,
+        end_stream: bool,
+    ) -> Status;
+}
+
+/// Sends a message over a given gRPC stream.
+pub fn send_grpc_stream_message<M>(token: u32, message: Option<M>, end_stream: bool) -> Result<()>
+where
+    M: AsRef<[u8]>,
+{
+    let (message_ptr, message_len) = message.as_ref().map_or((null(), 0), |message| {
+        (message.as_ref().as_ptr(), message.as_ref().len())
+    });
+    unsafe {
+        match proxy_grpc_send(token, message_ptr, message_len, end_stream) {
+            Status::Ok => Ok(()),
+            status => Err(HostCallError::new(abi::PROXY_GRPC_SEND, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_grpc_cancel(token: u32) -> Status;
+}
+
+/// Cancels a given gRPC call or stream.
+///
+/// No further callbacks will be invoked for the call or stream.
+pub fn cancel_grpc_call(token: u32) -> Result<()> {
+    unsafe {
+        match proxy_grpc_cancel(token) {
+            Status::Ok => {
+                dispatcher::unregister_grpc_callout(token);
+                Ok(())
+            }
+            status => Err(HostCallError::new(abi::PROXY_GRPC_CANCEL, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_grpc_close(token: u32) -> Status;
+}
+
+/// Half-closes a given gRPC stream, i.e. indicates that no more messages will be sent.
+pub fn close_grpc_stream(token: u32) -> Result<()> {
+    unsafe {
+        match proxy_grpc_close(token) {
+            Status::Ok => Ok(()),
+            status => Err(HostCallError::new(abi::PROXY_GRPC_CLOSE, status).into()),
+        }
+    }
+}
+
 extern "C" {
     fn proxy_set_effective_context(context_id: u32) -> Status;
 }
@@ -919,12 +1223,152 @@ pub fn done() -> Result<()> {
     }
 }
 
-mod utils {
+extern "C" {
+    fn proxy_define_metric(
+        metric_type: MetricType,
+        name_data: *const u8,
+        name_size: usize,
+        return_id: *mut u32,
+    ) -> Status;
+}
+
+/// Defines a metric of a given type and returns its id.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::hostcalls;
+/// use proxy_wasm::types::MetricType;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let metric_id = hostcalls::define_metric(MetricType::Counter, "my_filter.requests_total")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn define_metric(metric_type: MetricType, name: &str) -> Result<u32> {
+    let mut return_id: u32 = 0;
+    unsafe {
+        match proxy_define_metric(metric_type, name.as_ptr(), name.len(), &mut return_id) {
+            Status::Ok => Ok(return_id),
+            status => Err(HostCallError::new(abi::PROXY_DEFINE_METRIC, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_increment_metric(metric_id: u32, offset: i64) -> Status;
+}
+
+/// Increments (or decrements, if offset is negative) value of a given metric.
+pub fn increment_metric(metric_id: u32, offset: i64) -> Result<()> {
+    unsafe {
+        match proxy_increment_metric(metric_id, offset) {
+            Status::Ok => Ok(()),
+            status => Err(HostCallError::new(abi::PROXY_INCREMENT_METRIC, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_record_metric(metric_id: u32, value: u64) -> Status;
+}
+
+/// Sets value of a given gauge or records a value into a given histogram.
+pub fn record_metric(metric_id: u32, value: u64) -> Result<()> {
+    unsafe {
+        match proxy_record_metric(metric_id, value) {
+            Status::Ok => Ok(()),
+            status => Err(HostCallError::new(abi::PROXY_RECORD_METRIC, status).into()),
+        }
+    }
+}
+
+extern "C" {
+    fn proxy_get_metric(metric_id: u32, return_value: *mut u64) -> Status;
+}
+
+/// Returns value of a given counter or gauge.
+pub fn get_metric(metric_id: u32) -> Result<u64> {
+    let mut return_value: u64 = 0;
+    unsafe {
+        match proxy_get_metric(metric_id, &mut return_value) {
+            Status::Ok => Ok(return_value),
+            status => Err(HostCallError::new(abi::PROXY_GET_METRIC, status).into()),
+        }
+    }
+}
+
+#[cfg(feature = "abi-0-2-1")]
+extern "C" {
+    fn proxy_call_foreign_function(
+        function_name_data: *const u8,
+        function_name_size: usize,
+        arguments_data: *const u8,
+        arguments_size: usize,
+        return_results_data: *mut *mut u8,
+        return_results_size: *mut usize,
+    ) -> Status;
+}
+
+/// Calls a host-specific function that is not part of the ABI.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::hostcalls;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let compressed = hostcalls::call_foreign_function("compress", Some("uncompressed data"))?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "abi-0-2-1")]
+pub fn call_foreign_function<A>(
+    function_name: &str,
+    arguments: Option<A>,
+) -> Result<Option<ByteString>>
+where
+    A: AsRef<[u8]>,
+{
+    let (arguments_ptr, arguments_len) = arguments.as_ref().map_or((null(), 0), |arguments| {
+        (arguments.as_ref().as_ptr(), arguments.as_ref().len())
+    });
+    let mut return_data: *mut u8 = null_mut();
+    let mut return_size: usize = 0;
+    unsafe {
+        match proxy_call_foreign_function(
+            function_name.as_ptr(),
+            function_name.len(),
+            arguments_ptr,
+            arguments_len,
+            &mut return_data,
+            &mut return_size,
+        ) {
+            Status::Ok => {
+                if !return_data.is_null() {
+                    Ok(Some(ByteString::from(Vec::from_raw_parts(
+                        return_data,
+                        return_size,
+                        return_size,
+                    ))))
+                } else {
+                    Ok(None)
+                }
+            }
+            Status::NotFound => Ok(None),
+            status => Err(HostCallError::new(abi::PROXY_CALL_FOREIGN_FUNCTION, status).into()),
+        }
+    }
+}
+
+pub(crate) mod utils {
     use crate::error::Result;
     use crate::types::ByteString;
     use std::convert::TryFrom;
 
-    pub(super) fn serialize_property_path<P>(path: &[P]) -> Vec<u8>
+    pub(crate) fn serialize_property_path<P>(path: &[P]) -> Vec<u8>
     where
         P: AsRef<str>,
     {
@@ -944,7 +1388,7 @@ mod utils {
         bytes
     }
 
-    pub(super) fn serialize_map<K, V>(map: &[(K, V)]) -> Vec<u8>
+    pub(crate) fn serialize_map<K, V>(map: &[(K, V)]) -> Vec<u8>
     where
         K: AsRef<[u8]>,
         V: AsRef<[u8]>,
@@ -954,10 +1398,10 @@ mod utils {
             size += name.as_ref().len() + value.as_ref().len() + 10;
         }
         let mut bytes: Vec<u8> = Vec::with_capacity(size);
-        bytes.extend_from_slice(&map.len().to_le_bytes());
+        bytes.extend_from_slice(&(map.len() as u32).to_le_bytes());
         for (name, value) in map {
-            bytes.extend_from_slice(&name.as_ref().len().to_le_bytes());
-            bytes.extend_from_slice(&value.as_ref().len().to_le_bytes());
+            bytes.extend_from_slice(&(name.as_ref().len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(value.as_ref().len() as u32).to_le_bytes());
         }
         for (name, value) in map {
             bytes.extend_from_slice(name.as_ref());
@@ -968,7 +1412,7 @@ mod utils {
         bytes
     }
 
-    pub(super) fn deserialize_map(bytes: &[u8]) -> Result<Vec<(ByteString, ByteString)>> {
+    pub(crate) fn deserialize_map(bytes: &[u8]) -> Result<Vec<(ByteString, ByteString)>> {
         let mut map = Vec::new();
         if bytes.is_empty() {
             return Ok(map);
@@ -0,0 +1,169 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversions from and to the types of the [`http`] crate.
+//!
+//! Pseudo-headers of the host, e.g. `:method` or `:path`, are mapped to the
+//! respective parts of [`http::Request`] and [`http::Response`].
+//!
+//! Requires the `http-compat` feature.
+//!
+//! [`http`]: https://docs.rs/http
+//! [`http::Request`]: https://docs.rs/http/*/http/request/struct.Request.html
+//! [`http::Response`]: https://docs.rs/http/*/http/response/struct.Response.html
+
+use std::time::Duration;
+
+use http::header::{HeaderName, HeaderValue, HOST};
+use http::uri::{PathAndQuery, Uri};
+use http::{request, response, Method, Request, Response, StatusCode};
+
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::{ByteString, MapType};
+
+/// Converts request headers, including pseudo-headers, into [`request::Parts`].
+///
+/// [`request::Parts`]: https://docs.rs/http/*/http/request/struct.Parts.html
+pub fn request_parts(headers: &[(ByteString, ByteString)]) -> Result<request::Parts> {
+    let mut builder = Request::builder();
+    let mut scheme = None;
+    let mut authority = None;
+    let mut path = None;
+    for (name, value) in headers {
+        match name.as_bytes() {
+            b":method" => builder = builder.method(Method::from_bytes(value)?),
+            b":scheme" => scheme = Some(value.as_bytes()),
+            b":authority" => authority = Some(value.as_bytes()),
+            b":path" => path = Some(value.as_bytes()),
+            name if name.starts_with(b":") => {}
+            name => {
+                builder = builder.header(
+                    HeaderName::from_bytes(name)?,
+                    HeaderValue::from_bytes(value)?,
+                )
+            }
+        }
+    }
+    let mut uri = Uri::builder().path_and_query(path.unwrap_or(b"/"));
+    if let Some(authority) = authority {
+        uri = uri.scheme(scheme.unwrap_or(b"http")).authority(authority);
+    }
+    let (parts, ()) = builder.uri(uri.build()?).body(())?.into_parts();
+    Ok(parts)
+}
+
+/// Converts response headers, including the `:status` pseudo-header, into [`response::Parts`].
+///
+/// [`response::Parts`]: https://docs.rs/http/*/http/response/struct.Parts.html
+pub fn response_parts(headers: &[(ByteString, ByteString)]) -> Result<response::Parts> {
+    let mut builder = Response::builder();
+    for (name, value) in headers {
+        match name.as_bytes() {
+            b":status" => builder = builder.status(StatusCode::from_bytes(value)?),
+            name if name.starts_with(b":") => {}
+            name => {
+                builder = builder.header(
+                    HeaderName::from_bytes(name)?,
+                    HeaderValue::from_bytes(value)?,
+                )
+            }
+        }
+    }
+    let (parts, ()) = builder.body(())?.into_parts();
+    Ok(parts)
+}
+
+/// Returns the HTTP request headers as [`request::Parts`].
+///
+/// [`request::Parts`]: https://docs.rs/http/*/http/request/struct.Parts.html
+pub fn get_request_parts() -> Result<request::Parts> {
+    request_parts(&hostcalls::get_map(MapType::HttpRequestHeaders)?)
+}
+
+/// Returns the HTTP response headers as [`response::Parts`].
+///
+/// [`response::Parts`]: https://docs.rs/http/*/http/response/struct.Parts.html
+pub fn get_response_parts() -> Result<response::Parts> {
+    response_parts(&hostcalls::get_map(MapType::HttpResponseHeaders)?)
+}
+
+/// Sends a given HTTP response without forwarding the request to the upstream.
+///
+/// An empty body is sent as no body.
+pub fn send_http_response<B>(response: Response<B>) -> Result<()>
+where
+    B: AsRef<[u8]>,
+{
+    let (parts, body) = response.into_parts();
+    let headers: Vec<(&[u8], &[u8])> = parts
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str().as_bytes(), value.as_bytes()))
+        .collect();
+    hostcalls::send_http_response(
+        u32::from(parts.status.as_u16()),
+        &headers,
+        non_empty(body.as_ref()),
+    )
+}
+
+/// Dispatches a given HTTP request to an upstream.
+///
+/// The `:authority` pseudo-header is taken from the URI of the request, or
+/// from its `host` header.
+pub fn dispatch_http_call<B>(upstream: &str, request: Request<B>, timeout: Duration) -> Result<u32>
+where
+    B: AsRef<[u8]>,
+{
+    let (parts, body) = request.into_parts();
+    let path = parts.uri.path_and_query().map_or("/", PathAndQuery::as_str);
+    let authority = match parts.uri.authority() {
+        Some(authority) => authority.as_str().as_bytes(),
+        None => parts
+            .headers
+            .get(HOST)
+            .map_or(&b""[..], HeaderValue::as_bytes),
+    };
+    let mut headers: Vec<(&[u8], &[u8])> = vec![
+        (b":method", parts.method.as_str().as_bytes()),
+        (b":path", path.as_bytes()),
+        (b":authority", authority),
+    ];
+    if let Some(scheme) = parts.uri.scheme_str() {
+        headers.push((b":scheme", scheme.as_bytes()));
+    }
+    headers.extend(
+        parts
+            .headers
+            .iter()
+            .filter(|(name, _)| **name != HOST)
+            .map(|(name, value)| (name.as_str().as_bytes(), value.as_bytes())),
+    );
+    hostcalls::dispatch_http_call(
+        upstream,
+        &headers,
+        non_empty(body.as_ref()),
+        hostcalls::NO_TRAILERS,
+        timeout,
+    )
+}
+
+fn non_empty(body: &[u8]) -> Option<&[u8]> {
+    if body.is_empty() {
+        None
+    } else {
+        Some(body)
+    }
+}
@@ -14,20 +14,92 @@
 
 #![doc(html_root_url = "https://docs.rs/proxy-wasm-experimental/0.0.7")]
 
+#[cfg(not(any(feature = "abi-0-1-0", feature = "abi-0-2-1")))]
+compile_error!("either feature \"abi-0-1-0\" or feature \"abi-0-2-1\" must be enabled");
+
+pub mod attributes;
+#[cfg(feature = "serde")]
+pub mod config;
 pub mod error;
 pub mod hostcalls;
+#[cfg(feature = "http-compat")]
+pub mod http_compat;
+pub mod metrics;
+pub mod shared;
+pub mod task;
+pub mod timer;
 pub mod traits;
 pub mod types;
 
+#[cfg(all(feature = "proxy-wasm-test", not(target_arch = "wasm32")))]
+pub mod testing;
+
 mod allocator;
 mod bytestring;
 mod dispatcher;
 mod logger;
 
+/// Sets the log level of messages logged with the [`log`](https://docs.rs/log) crate,
+/// replacing any directives.
+///
+/// Since the `log` crate has no critical level, `LogLevel::Critical` is treated as `LogLevel::Error`.
 pub fn set_log_level(level: types::LogLevel) {
     logger::set_log_level(level);
 }
 
+/// Sets log levels per target with directives in the syntax of
+/// [`env_logger`](https://docs.rs/env_logger), e.g. `my_filter::auth=debug,info`.
+///
+/// A target matches itself and its submodules, and targets without a matching
+/// directive are off unless a default level is given. Directives can be changed
+/// at any time, e.g. from [`RootContext::on_configure`]. Invalid directives are
+/// rejected, keeping the previous ones.
+///
+/// With the `abi-0-2-1` feature, messages below the log level of the host are
+/// discarded before they are formatted. The host level is queried when directives
+/// are set, and again on every [`RootContext::on_configure`] and [`RootContext::on_tick`].
+///
+/// [`RootContext::on_configure`]: traits/trait.RootContext.html#method.on_configure
+/// [`RootContext::on_tick`]: traits/trait.RootContext.html#method.on_tick
+pub fn set_log_directives(directives: &str) -> error::Result<()> {
+    logger::set_log_directives(directives)
+}
+
+/// Sets the format of messages logged with the [`log`](https://docs.rs/log) crate.
+pub fn set_log_format(format: types::LogFormat) {
+    logger::set_log_format(format);
+}
+
+/// Sets the policy applied to errors returned from callbacks, e.g. from
+/// [`HttpContext::try_on_http_request_headers`].
+///
+/// [`HttpContext::try_on_http_request_headers`]: traits/trait.HttpContext.html#method.try_on_http_request_headers
+pub fn set_error_policy(policy: types::ErrorPolicy) {
+    dispatcher::set_error_policy(policy);
+}
+
+/// Sets the policy applied to panics in callbacks of HTTP and TCP stream contexts.
+///
+/// A context that panicked is dropped and receives no further callbacks, while
+/// the root context and other stream contexts keep running.
+///
+/// Only builds with `panic = "unwind"` can isolate panics, so in other builds,
+/// including all `wasm32-unknown-unknown` ones, a warning is logged and panics
+/// keep aborting the VM.
+pub fn set_panic_policy(policy: types::PanicPolicy) {
+    if !cfg!(panic = "unwind") && policy != types::PanicPolicy::Propagate {
+        hostcalls::log(
+            types::LogLevel::Warn,
+            &format!(
+                "panic policy {:?} has no effect in builds with panic = \"abort\"",
+                policy
+            ),
+        )
+        .ok();
+    }
+    dispatcher::set_panic_policy(policy);
+}
+
 pub fn set_root_context<F>(callback: F)
 where
     F: FnMut(u32) -> Box<dyn traits::RootContext> + 'static,
@@ -49,5 +121,12 @@ where
     dispatcher::set_http_context(Box::new(callback));
 }
 
+// Features are additive, so `abi-0-2-1` takes precedence when both are enabled,
+// e.g. by different dependents.
+#[cfg(all(feature = "abi-0-1-0", not(feature = "abi-0-2-1")))]
 #[no_mangle]
 pub extern "C" fn proxy_abi_version_0_1_0() {}
+
+#[cfg(feature = "abi-0-2-1")]
+#[no_mangle]
+pub extern "C" fn proxy_abi_version_0_2_1() {}
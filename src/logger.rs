@@ -12,8 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::dispatcher;
+use crate::error::Result;
 use crate::hostcalls;
-use crate::types::LogLevel;
+use crate::types::{LogFormat, LogLevel};
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
 use std::panic;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -22,7 +26,30 @@ struct Logger;
 static LOGGER: Logger = Logger;
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+thread_local! {
+static FORMAT: Cell<LogFormat> = Cell::new(LogFormat::default());
+static DIRECTIVES: RefCell<Directives> = RefCell::new(Directives::default());
+// Log level of the host, queried when directives are set and refreshed by the dispatcher.
+static HOST_LEVEL: Cell<log::LevelFilter> = const { Cell::new(log::LevelFilter::Trace) };
+}
+
+pub(crate) fn set_log_format(format: LogFormat) {
+    FORMAT.with(|current| current.set(format));
+}
+
 pub(crate) fn set_log_level(level: LogLevel) {
+    set_directives(Directives {
+        default: level_filter(level),
+        targets: Vec::new(),
+    });
+}
+
+pub(crate) fn set_log_directives(directives: &str) -> Result<()> {
+    set_directives(Directives::parse(directives)?);
+    Ok(())
+}
+
+fn set_directives(directives: Directives) {
     if !INITIALIZED.load(Ordering::Relaxed) {
         log::set_logger(&LOGGER).unwrap();
         panic::set_hook(Box::new(|panic_info| {
@@ -30,26 +57,117 @@ pub(crate) fn set_log_level(level: LogLevel) {
         }));
         INITIALIZED.store(true, Ordering::Relaxed);
     }
-    LOGGER.set_log_level(level);
+    DIRECTIVES.with(|current| *current.borrow_mut() = directives);
+    refresh_host_level();
 }
 
-impl Logger {
-    pub fn set_log_level(&self, level: LogLevel) {
-        let filter = match level {
-            LogLevel::Trace => log::LevelFilter::Trace,
-            LogLevel::Debug => log::LevelFilter::Debug,
-            LogLevel::Info => log::LevelFilter::Info,
-            LogLevel::Warn => log::LevelFilter::Warn,
-            LogLevel::Error => log::LevelFilter::Error,
-            LogLevel::Critical => log::LevelFilter::Off,
-        };
-        log::set_max_level(filter);
+/// Queries the log level of the host, so that messages it would discard are not formatted.
+#[cfg(feature = "abi-0-2-1")]
+pub(crate) fn refresh_host_level() {
+    if let Ok(level) = hostcalls::get_log_level() {
+        HOST_LEVEL.with(|host_level| host_level.set(level_filter(level)));
+    }
+    update_max_level();
+}
+
+#[cfg(not(feature = "abi-0-2-1"))]
+pub(crate) fn refresh_host_level() {
+    update_max_level();
+}
+
+fn update_max_level() {
+    if !INITIALIZED.load(Ordering::Relaxed) {
+        return;
+    }
+    let max_level = DIRECTIVES.with(|directives| directives.borrow().max_level());
+    log::set_max_level(max_level.min(HOST_LEVEL.with(Cell::get)));
+}
+
+// The `log` crate has no level above `Error`.
+fn level_filter(level: LogLevel) -> log::LevelFilter {
+    match level {
+        LogLevel::Trace => log::LevelFilter::Trace,
+        LogLevel::Debug => log::LevelFilter::Debug,
+        LogLevel::Info => log::LevelFilter::Info,
+        LogLevel::Warn => log::LevelFilter::Warn,
+        LogLevel::Error => log::LevelFilter::Error,
+        LogLevel::Critical => log::LevelFilter::Error,
+    }
+}
+
+/// Log levels per target, e.g. `my_filter::auth=debug,info`.
+#[derive(Debug, PartialEq)]
+struct Directives {
+    default: log::LevelFilter,
+    // Ordered from the most specific target.
+    targets: Vec<(String, log::LevelFilter)>,
+}
+
+impl Default for Directives {
+    fn default() -> Self {
+        Directives {
+            default: log::LevelFilter::Trace,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl Directives {
+    /// Parses comma-separated directives in the syntax of `env_logger`, i.e.
+    /// `target=level`, `target` for all levels, or `level` for other targets.
+    ///
+    /// Other targets are off unless a level is given for them.
+    fn parse(directives: &str) -> Result<Directives> {
+        let mut default = log::LevelFilter::Off;
+        let mut targets = Vec::new();
+        for directive in directives.split(',').map(str::trim) {
+            let invalid = || format!("invalid log directive \"{}\"", directive);
+            match directive.split_once('=') {
+                _ if directive.is_empty() => {}
+                Some((target, level)) => {
+                    let target = target.trim();
+                    if target.is_empty() {
+                        return Err(invalid().into());
+                    }
+                    let level = level.trim().parse().map_err(|_| invalid())?;
+                    targets.push((target.to_owned(), level));
+                }
+                None => match directive.parse() {
+                    Ok(level) => default = level,
+                    Err(_) => targets.push((directive.to_owned(), log::LevelFilter::Trace)),
+                },
+            }
+        }
+        // Later directives for the same target take precedence.
+        targets.reverse();
+        targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(Directives { default, targets })
+    }
+
+    fn level(&self, target: &str) -> log::LevelFilter {
+        self.targets
+            .iter()
+            .find(|(name, _)| {
+                target
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> log::LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
     }
 }
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        metadata.level()
+            <= DIRECTIVES.with(|directives| directives.borrow().level(metadata.target()))
+            && metadata.level() <= HOST_LEVEL.with(Cell::get)
     }
 
     fn log(&self, record: &log::Record) {
@@ -63,9 +181,174 @@ impl log::Log for Logger {
             log::Level::Warn => LogLevel::Warn,
             log::Level::Error => LogLevel::Error,
         };
-        let message = record.args().to_string();
+        let message = match FORMAT.with(Cell::get) {
+            LogFormat::Plain => record.args().to_string(),
+            format => format_structured(format, record),
+        };
         hostcalls::log(level, &message).unwrap_or(());
     }
 
     fn flush(&self) {}
 }
+
+fn format_structured(format: LogFormat, record: &log::Record) -> String {
+    let mut fields = Fields::default();
+    match dispatcher::active_context_id() {
+        0 => {}
+        context_id => fields.push("context_id", Field::Number(context_id.to_string())),
+    }
+    fields.push("target", Field::String(record.target().to_owned()));
+    if let Some(module) = record
+        .module_path()
+        .filter(|&module| module != record.target())
+    {
+        fields.push("module", Field::String(module.to_owned()));
+    }
+    fields.push("msg", Field::String(record.args().to_string()));
+    log::kv::Source::visit(record.key_values(), &mut fields).ok();
+
+    let mut line = String::new();
+    match format {
+        LogFormat::Json => {
+            line.push('{');
+            for (index, (key, value)) in fields.0.iter().enumerate() {
+                if index > 0 {
+                    line.push(',');
+                }
+                write_json_string(&mut line, key);
+                line.push(':');
+                match value {
+                    Field::Number(number) => line.push_str(number),
+                    Field::String(string) => write_json_string(&mut line, string),
+                }
+            }
+            line.push('}');
+        }
+        _ => {
+            for (index, (key, value)) in fields.0.iter().enumerate() {
+                if index > 0 {
+                    line.push(' ');
+                }
+                line.push_str(key);
+                line.push('=');
+                match value {
+                    Field::Number(number) => line.push_str(number),
+                    Field::String(string) => write_logfmt_string(&mut line, string),
+                }
+            }
+        }
+    }
+    line
+}
+
+enum Field {
+    // Numbers and booleans, which are not quoted in JSON.
+    Number(String),
+    String(String),
+}
+
+#[derive(Default)]
+struct Fields(Vec<(String, Field)>);
+
+impl Fields {
+    fn push(&mut self, key: &str, value: Field) {
+        self.0.push((key.to_owned(), value));
+    }
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for Fields {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> std::result::Result<(), log::kv::Error> {
+        let value = if let Some(value) = value.to_i64() {
+            Field::Number(value.to_string())
+        } else if let Some(value) = value.to_u64() {
+            Field::Number(value.to_string())
+        } else if let Some(value) = value.to_f64().filter(|value| value.is_finite()) {
+            Field::Number(value.to_string())
+        } else if let Some(value) = value.to_bool() {
+            Field::Number(value.to_string())
+        } else {
+            Field::String(value.to_string())
+        };
+        self.push(key.as_str(), value);
+        Ok(())
+    }
+}
+
+fn write_json_string(line: &mut String, value: &str) {
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if c.is_control() => {
+                write!(line, "\\u{:04x}", c as u32).unwrap();
+            }
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+fn write_logfmt_string(line: &mut String, value: &str) {
+    let quote = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c.is_control());
+    if quote {
+        write_json_string(line, value);
+    } else {
+        line.push_str(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_parse_directives() {
+        let directives = Directives::parse("my_filter=info, my_filter::auth=debug,warn").unwrap();
+        assert_eq!(directives.default, LevelFilter::Warn);
+        assert_eq!(directives.level("my_filter"), LevelFilter::Info);
+        assert_eq!(directives.level("my_filter::auth"), LevelFilter::Debug);
+        assert_eq!(directives.level("my_filter::auth::jwt"), LevelFilter::Debug);
+        assert_eq!(directives.level("my_filter_v2"), LevelFilter::Warn);
+        assert_eq!(directives.level("other"), LevelFilter::Warn);
+        assert_eq!(directives.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_parse_target_only() {
+        let directives = Directives::parse("my_filter,my_filter=off,").unwrap();
+        assert_eq!(directives.default, LevelFilter::Off);
+        assert_eq!(directives.level("my_filter"), LevelFilter::Off);
+        assert_eq!(directives.level("other"), LevelFilter::Off);
+        assert_eq!(
+            Directives::parse("my_filter").unwrap().level("my_filter"),
+            LevelFilter::Trace
+        );
+    }
+
+    #[test]
+    fn test_critical_level() {
+        assert_eq!(level_filter(LogLevel::Critical), LevelFilter::Error);
+    }
+
+    #[test]
+    fn test_parse_invalid_directives() {
+        for directives in &["my_filter=verbose", "=info"] {
+            assert_eq!(
+                Directives::parse(directives).unwrap_err().to_string(),
+                format!("invalid log directive \"{}\"", directives)
+            );
+        }
+    }
+}
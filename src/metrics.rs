@@ -0,0 +1,473 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed handles to metrics defined in the host.
+//!
+//! A handle defines its metric lazily, on first use, and caches the metric id
+//! afterwards. Handles are not tied to a particular context and can be used
+//! from any callback.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use proxy_wasm_experimental as proxy_wasm;
+//! use proxy_wasm::metrics::Counter;
+//!
+//! # fn action() -> proxy_wasm::error::Result<()> {
+//! let requests = Counter::new("my_filter.requests_total");
+//! requests.increment(1)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Metrics with labels are declared as a [`MetricFamily`], usually through a
+//! [`Registry`] owned by the root context and shared with its children.
+//! Each unique set of label values is defined in the host as a separate metric
+//! named according to the Envoy tag extraction convention, e.g.
+//! `route.default.status.200.requests_total`.
+//!
+//! ```no_run
+//! # use proxy_wasm_experimental as proxy_wasm;
+//! use proxy_wasm::metrics::Registry;
+//!
+//! # fn action() -> proxy_wasm::error::Result<()> {
+//! let registry = Registry::new();
+//! let requests = registry.counter("requests_total", &["route", "status"])?;
+//! requests.increment(&["default", "200"], 1)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MetricFamily`]: struct.MetricFamily.html
+//! [`Registry`]: struct.Registry.html
+
+use hashbrown::HashMap;
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::MetricType;
+
+#[derive(Debug, Clone)]
+struct Metric {
+    metric_type: MetricType,
+    name: String,
+    id: Cell<Option<u32>>,
+}
+
+impl Metric {
+    fn new(metric_type: MetricType, name: String) -> Self {
+        Metric {
+            metric_type,
+            name,
+            id: Cell::new(None),
+        }
+    }
+
+    fn id(&self) -> Result<u32> {
+        if let Some(id) = self.id.get() {
+            return Ok(id);
+        }
+        let id = hostcalls::define_metric(self.metric_type, &self.name)?;
+        self.id.set(Some(id));
+        Ok(id)
+    }
+}
+
+/// A monotonically increasing counter.
+#[derive(Debug, Clone)]
+pub struct Counter {
+    metric: Metric,
+}
+
+impl Counter {
+    pub fn new<N>(name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        Counter {
+            metric: Metric::new(MetricType::Counter, name.into()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.metric.name
+    }
+
+    /// Returns id of the metric in the host, defining it if necessary.
+    pub fn id(&self) -> Result<u32> {
+        self.metric.id()
+    }
+
+    /// Fails if the offset exceeds `i64::MAX`, the largest increment supported by the host.
+    pub fn increment(&self, offset: u64) -> Result<()> {
+        let offset = i64::try_from(offset)
+            .map_err(|_| format!("counter increment {} exceeds i64::MAX", offset))?;
+        hostcalls::increment_metric(self.id()?, offset)
+    }
+
+    pub fn get(&self) -> Result<u64> {
+        hostcalls::get_metric(self.id()?)
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Debug, Clone)]
+pub struct Gauge {
+    metric: Metric,
+}
+
+impl Gauge {
+    pub fn new<N>(name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        Gauge {
+            metric: Metric::new(MetricType::Gauge, name.into()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.metric.name
+    }
+
+    /// Returns id of the metric in the host, defining it if necessary.
+    pub fn id(&self) -> Result<u32> {
+        self.metric.id()
+    }
+
+    pub fn increment(&self, offset: i64) -> Result<()> {
+        hostcalls::increment_metric(self.id()?, offset)
+    }
+
+    /// Fails if the offset is `i64::MIN`, which cannot be negated.
+    pub fn decrement(&self, offset: i64) -> Result<()> {
+        let offset = offset
+            .checked_neg()
+            .ok_or_else(|| format!("gauge decrement {} overflows", offset))?;
+        hostcalls::increment_metric(self.id()?, offset)
+    }
+
+    pub fn record(&self, value: u64) -> Result<()> {
+        hostcalls::record_metric(self.id()?, value)
+    }
+
+    pub fn get(&self) -> Result<u64> {
+        hostcalls::get_metric(self.id()?)
+    }
+}
+
+/// A distribution of recorded values.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    metric: Metric,
+}
+
+impl Histogram {
+    pub fn new<N>(name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        Histogram {
+            metric: Metric::new(MetricType::Histogram, name.into()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.metric.name
+    }
+
+    /// Returns id of the metric in the host, defining it if necessary.
+    pub fn id(&self) -> Result<u32> {
+        self.metric.id()
+    }
+
+    pub fn record(&self, value: u64) -> Result<()> {
+        hostcalls::record_metric(self.id()?, value)
+    }
+}
+
+/// A group of metrics of the same type and name distinguished by label values.
+#[derive(Debug)]
+pub struct MetricFamily {
+    metric_type: MetricType,
+    name: String,
+    labels: Vec<String>,
+    ids: RefCell<HashMap<Vec<String>, u32>>,
+}
+
+impl MetricFamily {
+    pub fn new<N, L>(metric_type: MetricType, name: N, labels: &[L]) -> Self
+    where
+        N: Into<String>,
+        L: AsRef<str>,
+    {
+        MetricFamily {
+            metric_type,
+            name: name.into(),
+            labels: labels.iter().map(|l| l.as_ref().to_owned()).collect(),
+            ids: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn metric_type(&self) -> MetricType {
+        self.metric_type
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Returns the host metric name for a given set of label values.
+    ///
+    /// Every label is rendered as `<label>.<value>.` in front of the family name,
+    /// which is the format expected by Envoy tag extractors. Dots in label values
+    /// are replaced with underscores so that values cannot be confused with labels.
+    pub fn metric_name<V>(&self, values: &[V]) -> Result<String>
+    where
+        V: AsRef<str>,
+    {
+        if values.len() != self.labels.len() {
+            return Err(format!(
+                "metric \"{}\" expects {} label values, got {}",
+                self.name,
+                self.labels.len(),
+                values.len()
+            )
+            .into());
+        }
+        let mut size = self.name.len();
+        for (label, value) in self.labels.iter().zip(values) {
+            size += label.len() + value.as_ref().len() + 2;
+        }
+        let mut name = String::with_capacity(size);
+        for (label, value) in self.labels.iter().zip(values) {
+            name.push_str(label);
+            name.push('.');
+            name.extend(
+                value
+                    .as_ref()
+                    .chars()
+                    .map(|c| if c == '.' { '_' } else { c }),
+            );
+            name.push('.');
+        }
+        name.push_str(&self.name);
+        Ok(name)
+    }
+
+    /// Returns id of the metric for a given set of label values, defining it if necessary.
+    pub fn id<V>(&self, values: &[V]) -> Result<u32>
+    where
+        V: AsRef<str>,
+    {
+        let key: Vec<String> = values.iter().map(|v| v.as_ref().to_owned()).collect();
+        if let Some(id) = self.ids.borrow().get(&key) {
+            return Ok(*id);
+        }
+        let id = hostcalls::define_metric(self.metric_type, &self.metric_name(values)?)?;
+        self.ids.borrow_mut().insert(key, id);
+        Ok(id)
+    }
+
+    /// Fails for histograms, and for counters if the offset is negative.
+    pub fn increment<V>(&self, values: &[V], offset: i64) -> Result<()>
+    where
+        V: AsRef<str>,
+    {
+        match self.metric_type {
+            MetricType::Counter if offset < 0 => {
+                return Err(format!(
+                    "counter \"{}\" cannot be incremented by {}",
+                    self.name, offset
+                )
+                .into())
+            }
+            MetricType::Histogram => {
+                return Err(format!("histogram \"{}\" cannot be incremented", self.name).into())
+            }
+            _ => {}
+        }
+        hostcalls::increment_metric(self.id(values)?, offset)
+    }
+
+    /// Fails for counters, which can only be incremented.
+    pub fn record<V>(&self, values: &[V], value: u64) -> Result<()>
+    where
+        V: AsRef<str>,
+    {
+        if self.metric_type == MetricType::Counter {
+            return Err(format!("counter \"{}\" cannot be recorded", self.name).into());
+        }
+        hostcalls::record_metric(self.id(values)?, value)
+    }
+
+    pub fn get<V>(&self, values: &[V]) -> Result<u64>
+    where
+        V: AsRef<str>,
+    {
+        hostcalls::get_metric(self.id(values)?)
+    }
+}
+
+/// A set of metric families declared by a root context.
+///
+/// Declaring a family that already exists returns the existing one, so that
+/// child contexts can look families up by name instead of re-defining them.
+#[derive(Debug, Default)]
+pub struct Registry {
+    families: RefCell<HashMap<String, Rc<MetricFamily>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    pub fn counter<L>(&self, name: &str, labels: &[L]) -> Result<Rc<MetricFamily>>
+    where
+        L: AsRef<str>,
+    {
+        self.declare(MetricType::Counter, name, labels)
+    }
+
+    pub fn gauge<L>(&self, name: &str, labels: &[L]) -> Result<Rc<MetricFamily>>
+    where
+        L: AsRef<str>,
+    {
+        self.declare(MetricType::Gauge, name, labels)
+    }
+
+    pub fn histogram<L>(&self, name: &str, labels: &[L]) -> Result<Rc<MetricFamily>>
+    where
+        L: AsRef<str>,
+    {
+        self.declare(MetricType::Histogram, name, labels)
+    }
+
+    /// Returns a previously declared family.
+    pub fn get(&self, name: &str) -> Option<Rc<MetricFamily>> {
+        self.families.borrow().get(name).cloned()
+    }
+
+    fn declare<L>(
+        &self,
+        metric_type: MetricType,
+        name: &str,
+        labels: &[L],
+    ) -> Result<Rc<MetricFamily>>
+    where
+        L: AsRef<str>,
+    {
+        if let Some(family) = self.families.borrow().get(name) {
+            if family.metric_type != metric_type
+                || !family
+                    .labels
+                    .iter()
+                    .map(String::as_str)
+                    .eq(labels.iter().map(L::as_ref))
+            {
+                return Err(format!(
+                    "metric \"{}\" is already declared with a different type or labels",
+                    name
+                )
+                .into());
+            }
+            return Ok(Rc::clone(family));
+        }
+        let family = Rc::new(MetricFamily::new(metric_type, name, labels));
+        self.families
+            .borrow_mut()
+            .insert(name.to_owned(), Rc::clone(&family));
+        Ok(family)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_family_name() {
+        let family = MetricFamily::new(MetricType::Counter, "requests_total", &["route", "status"]);
+
+        assert_eq!(
+            family.metric_name(&["default", "200"]).unwrap(),
+            "route.default.status.200.requests_total"
+        );
+    }
+
+    #[test]
+    fn test_metric_family_name_without_labels() {
+        let family = MetricFamily::new(MetricType::Gauge, "active", &[] as &[&str]);
+
+        assert_eq!(family.metric_name(&[] as &[&str]).unwrap(), "active");
+    }
+
+    #[test]
+    fn test_metric_family_name_escapes_dots() {
+        let family = MetricFamily::new(MetricType::Counter, "requests_total", &["host"]);
+
+        assert_eq!(
+            family.metric_name(&["example.com"]).unwrap(),
+            "host.example_com.requests_total"
+        );
+    }
+
+    #[test]
+    fn test_metric_family_name_label_count_mismatch() {
+        let family = MetricFamily::new(MetricType::Counter, "requests_total", &["route", "status"]);
+
+        assert!(family.metric_name(&["default"]).is_err());
+    }
+
+    #[test]
+    fn test_registry_redeclare() {
+        let registry = Registry::new();
+        let first = registry.counter("requests_total", &["route"]).unwrap();
+        let second = registry.counter("requests_total", &["route"]).unwrap();
+
+        assert!(Rc::ptr_eq(&first, &second));
+        assert!(registry.gauge("requests_total", &["route"]).is_err());
+        assert!(registry.counter("requests_total", &["status"]).is_err());
+    }
+
+    #[test]
+    fn test_metric_family_type_mismatch() {
+        let counter = MetricFamily::new(MetricType::Counter, "requests_total", &["route"]);
+        let histogram = MetricFamily::new(MetricType::Histogram, "latency", &["route"]);
+
+        assert_eq!(
+            counter.increment(&["default"], -1).unwrap_err().to_string(),
+            "counter \"requests_total\" cannot be incremented by -1"
+        );
+        assert_eq!(
+            counter.record(&["default"], 1).unwrap_err().to_string(),
+            "counter \"requests_total\" cannot be recorded"
+        );
+        assert_eq!(
+            histogram
+                .increment(&["default"], 1)
+                .unwrap_err()
+                .to_string(),
+            "histogram \"latency\" cannot be incremented"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryInto;
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::codec::Codec;
+use crate::error::has_status;
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::Status;
+
+const EXPIRY_SIZE: usize = 8;
+
+/// Entries shared between VMs that expire after a time to live.
+///
+/// Entries are stored under `<namespace>/<key>`, prefixed with their expiry
+/// time in nanoseconds since the Unix epoch as 8 little-endian bytes, where `0`
+/// means the entry never expires. Expired entries read as missing and are
+/// deleted on access.
+///
+/// Since the host cannot remove shared data, a deleted entry is stored as an
+/// empty value.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::shared::codec::Raw;
+/// use proxy_wasm::shared::SharedCache;
+/// use std::time::Duration;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let tokens = SharedCache::new("tokens", Raw).with_ttl(Duration::from_secs(300));
+/// let token: Option<String> = tokens.get("upstream")?;
+/// if token.is_none() {
+///     tokens.set("upstream", &"secret".to_string())?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedCache<T, C> {
+    namespace: String,
+    codec: C,
+    ttl: Option<Duration>,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<T, C> SharedCache<T, C>
+where
+    C: Codec<T>,
+{
+    /// Creates a cache whose entries never expire, unless a TTL is set.
+    pub fn new<N>(namespace: N, codec: C) -> Self
+    where
+        N: Into<String>,
+    {
+        SharedCache {
+            namespace: namespace.into(),
+            codec,
+            ttl: None,
+            value_type: PhantomData,
+        }
+    }
+
+    /// Sets the time to live of entries set with `set`.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns an entry, unless it is missing or has expired.
+    pub fn get(&self, key: &str) -> Result<Option<T>> {
+        let key = self.key(key);
+        let (data, cas) = hostcalls::get_shared_data(&key)?;
+        let data = match data {
+            Some(data) if data.as_bytes().len() >= EXPIRY_SIZE => data,
+            _ => return Ok(None),
+        };
+        let (expiry, value) = data.as_bytes().split_at(EXPIRY_SIZE);
+        let expiry = u64::from_le_bytes(expiry.try_into().unwrap());
+        if expiry != 0 && expiry <= nanos_since_epoch(hostcalls::get_current_time()?)? {
+            // Another VM might have replaced the entry in the meantime.
+            match hostcalls::set_shared_data(&key, Some(&[]), cas) {
+                Err(error) if !has_status(&*error, Status::CasMismatch) => return Err(error),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(self.codec.decode(value)?))
+    }
+
+    /// Sets an entry that expires after the TTL of the cache.
+    pub fn set(&self, key: &str, value: &T) -> Result<()> {
+        self.set_entry(key, value, self.ttl)
+    }
+
+    /// Sets an entry that expires after a given TTL.
+    pub fn set_with_ttl(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        self.set_entry(key, value, Some(ttl))
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        hostcalls::set_shared_data(self.key(key), Some(&[]), None)
+    }
+
+    fn set_entry(&self, key: &str, value: &T, ttl: Option<Duration>) -> Result<()> {
+        let expiry = match ttl {
+            Some(ttl) => nanos_since_epoch(hostcalls::get_current_time()? + ttl)?,
+            None => 0,
+        };
+        let mut data = expiry.to_le_bytes().to_vec();
+        data.extend(self.codec.encode(value)?);
+        hostcalls::set_shared_data(self.key(key), Some(data), None)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}/{}", self.namespace, key)
+    }
+}
+
+fn nanos_since_epoch(time: SystemTime) -> Result<u64> {
+    Ok(time.duration_since(UNIX_EPOCH)?.as_nanos() as u64)
+}
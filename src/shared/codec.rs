@@ -0,0 +1,100 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encodings of shared values.
+
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::Result;
+use crate::types::ByteString;
+
+/// Converts values of a given type from and to bytes.
+pub trait Codec<T> {
+    fn encode(&self, value: &T) -> Result<Vec<u8>>;
+
+    fn decode(&self, data: &[u8]) -> Result<T>;
+}
+
+/// Stores bytes and strings as they are.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Raw;
+
+impl Codec<Vec<u8>> for Raw {
+    fn encode(&self, value: &Vec<u8>) -> Result<Vec<u8>> {
+        Ok(value.clone())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+impl Codec<ByteString> for Raw {
+    fn encode(&self, value: &ByteString) -> Result<Vec<u8>> {
+        Ok(value.as_bytes().to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<ByteString> {
+        Ok(data.into())
+    }
+}
+
+impl Codec<String> for Raw {
+    fn encode(&self, value: &String) -> Result<Vec<u8>> {
+        Ok(value.as_bytes().to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<String> {
+        Ok(String::from_utf8(data.to_vec())?)
+    }
+}
+
+/// Encodes values in JSON.
+#[cfg(feature = "serde")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Json;
+
+#[cfg(feature = "serde")]
+impl<T> Codec<T> for Json
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<T> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Encodes values with [`bincode`](https://docs.rs/bincode).
+#[cfg(feature = "serde-bincode")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bincode;
+
+#[cfg(feature = "serde-bincode")]
+impl<T> Codec<T> for Bincode
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<T> {
+        Ok(bincode::deserialize(data)?)
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryInto;
+
+use super::codec::Codec;
+use super::data::SharedData;
+use crate::error::Result;
+
+/// A 64-bit counter shared between VMs.
+///
+/// The value is stored as 8 little-endian bytes. A missing or deleted value
+/// reads as `0`.
+///
+/// Updates of an existing value are atomic, but the host cannot detect concurrent
+/// creation, so VMs racing to create the value can lose each other's updates.
+/// Counters that must not lose any, e.g. for billing, should be created with `set`
+/// before they are shared, such as in `on_vm_start` of the VM that owns them.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::shared::SharedCounter;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let requests = SharedCounter::new("requests");
+/// if requests.fetch_add(1)? >= 100 {
+///     // Rate limited.
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedCounter {
+    data: SharedData<i64, Counter>,
+}
+
+impl SharedCounter {
+    pub fn new<K>(key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        SharedCounter {
+            data: SharedData::new(key, Counter),
+        }
+    }
+
+    /// Sets how many times `fetch_add` tries to write the value before giving up.
+    pub fn with_max_attempts(self, max_attempts: usize) -> Self {
+        SharedCounter {
+            data: self.data.with_max_attempts(max_attempts),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        self.data.key()
+    }
+
+    pub fn get(&self) -> Result<i64> {
+        Ok(self.data.get()?.unwrap_or(0))
+    }
+
+    pub fn set(&self, value: i64) -> Result<()> {
+        self.data.set(&value)
+    }
+
+    /// Adds a given delta to the counter, wrapping around on overflow, and
+    /// returns the previous value.
+    ///
+    /// This is atomic only if the value exists, see the type documentation.
+    pub fn fetch_add(&self, delta: i64) -> Result<i64> {
+        let mut previous = 0;
+        self.data.update(|value| {
+            previous = value.unwrap_or(0);
+            previous.wrapping_add(delta)
+        })?;
+        Ok(previous)
+    }
+
+    /// Subtracts a given delta from the counter and returns the previous value.
+    pub fn fetch_sub(&self, delta: i64) -> Result<i64> {
+        self.fetch_add(delta.wrapping_neg())
+    }
+}
+
+struct Counter;
+
+impl Codec<i64> for Counter {
+    fn encode(&self, value: &i64) -> Result<Vec<u8>> {
+        Ok(value.to_le_bytes().to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<i64> {
+        match data.len() {
+            0 => Ok(0),
+            _ => Ok(i64::from_le_bytes(data.try_into().map_err(|_| {
+                format!("shared counter value has {} bytes, not 8", data.len())
+            })?)),
+        }
+    }
+}
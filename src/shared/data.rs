@@ -0,0 +1,128 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::marker::PhantomData;
+
+use super::codec::Codec;
+use crate::error::has_status;
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::Status;
+
+const DEFAULT_MAX_ATTEMPTS: usize = 10;
+
+/// A typed handle to a value shared between VMs under a given key.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::shared::codec::Raw;
+/// use proxy_wasm::shared::SharedData;
+///
+/// # fn action() -> proxy_wasm::error::Result<()> {
+/// let visitors = SharedData::new("visitors", Raw);
+/// visitors.update(|visitors: Option<String>| {
+///     let mut visitors = visitors.unwrap_or_default();
+///     visitors.push_str("alice\n");
+///     visitors
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedData<T, C> {
+    key: String,
+    codec: C,
+    max_attempts: usize,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<T, C> SharedData<T, C>
+where
+    C: Codec<T>,
+{
+    pub fn new<K>(key: K, codec: C) -> Self
+    where
+        K: Into<String>,
+    {
+        SharedData {
+            key: key.into(),
+            codec,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            value_type: PhantomData,
+        }
+    }
+
+    /// Sets how many times `update` tries to write the value before giving up.
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn get(&self) -> Result<Option<T>> {
+        self.get_with_cas().map(|(value, _)| value)
+    }
+
+    /// Returns the value together with its CAS, to be passed to `set_with_cas`.
+    pub fn get_with_cas(&self) -> Result<(Option<T>, Option<u32>)> {
+        match hostcalls::get_shared_data(&self.key)? {
+            (Some(data), cas) => Ok((Some(self.codec.decode(&data)?), cas)),
+            (None, cas) => Ok((None, cas)),
+        }
+    }
+
+    /// Sets the value unconditionally.
+    pub fn set(&self, value: &T) -> Result<()> {
+        self.set_with_cas(value, None)
+    }
+
+    /// Sets the value, unless it has changed since the given CAS has been read.
+    ///
+    /// A changed value is reported as an error with [`Status::CasMismatch`].
+    ///
+    /// [`Status::CasMismatch`]: ../types/enum.Status.html#variant.CasMismatch
+    pub fn set_with_cas(&self, value: &T, cas: Option<u32>) -> Result<()> {
+        let data = self.codec.encode(value)?;
+        hostcalls::set_shared_data(&self.key, Some(data), cas)
+    }
+
+    /// Replaces the value with the result of a given function, retrying if the value
+    /// is changed concurrently, and returns the new value.
+    ///
+    /// The function can be called multiple times. A value that does not exist yet
+    /// is written without CAS, since the host cannot detect concurrent creation.
+    pub fn update<F>(&self, mut f: F) -> Result<T>
+    where
+        F: FnMut(Option<T>) -> T,
+    {
+        for _ in 0..self.max_attempts {
+            let (value, cas) = self.get_with_cas()?;
+            let value = f(value);
+            match self.set_with_cas(&value, cas) {
+                Ok(()) => return Ok(value),
+                Err(error) if has_status(&*error, Status::CasMismatch) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        Err(format!(
+            "shared data \"{}\" has been changed concurrently {} times in a row",
+            self.key, self.max_attempts
+        )
+        .into())
+    }
+}
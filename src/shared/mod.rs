@@ -0,0 +1,45 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed handles to data shared between VMs.
+//!
+//! Values are encoded with a [`Codec`], e.g. [`Json`] with the `serde` feature,
+//! or [`Bincode`] with the `serde-bincode` feature. [`SharedCounter`] and
+//! [`SharedCache`] build atomic counters and expiring entries on top of them,
+//! and [`QueueSender`] and [`QueueReceiver`] pass typed messages over shared queues,
+//! e.g. between VMs with a [`Publisher`] and [`subscribe`].
+//!
+//! [`Codec`]: codec/trait.Codec.html
+//! [`Json`]: codec/struct.Json.html
+//! [`Bincode`]: codec/struct.Bincode.html
+//! [`SharedCounter`]: struct.SharedCounter.html
+//! [`SharedCache`]: struct.SharedCache.html
+//! [`QueueSender`]: struct.QueueSender.html
+//! [`QueueReceiver`]: struct.QueueReceiver.html
+//! [`Publisher`]: struct.Publisher.html
+//! [`subscribe`]: fn.subscribe.html
+
+pub mod codec;
+
+mod cache;
+mod counter;
+mod data;
+mod pubsub;
+mod queue;
+
+pub use self::cache::SharedCache;
+pub use self::counter::SharedCounter;
+pub use self::data::SharedData;
+pub use self::pubsub::{subscribe, Publisher};
+pub use self::queue::{QueueReceiver, QueueSender};
@@ -0,0 +1,203 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+use super::codec::Codec;
+use super::queue::QueueReceiver;
+use crate::error::has_status;
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::{LogLevel, Status};
+
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Publishes typed messages to a topic, i.e. a shared queue registered by a
+/// subscriber in another VM.
+///
+/// The queue is resolved lazily, so a publisher can be created before the
+/// subscriber has started. Messages that cannot be delivered yet, because the
+/// queue does not exist or the host fails to enqueue them, are kept and retried
+/// on the next `publish` or `flush`. Once more than the capacity of messages is
+/// pending, the oldest ones are dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::shared::codec::Raw;
+/// use proxy_wasm::shared::{Publisher, SharedCounter};
+/// use proxy_wasm::traits::*;
+/// use std::time::Duration;
+///
+/// // A single publisher per VM, kept by the root context, rather than one per stream.
+/// struct Reporter {
+///     requests: SharedCounter,
+///     publisher: Publisher<String, Raw>,
+/// }
+///
+/// impl Context for Reporter {}
+///
+/// impl RootContext for Reporter {
+///     fn on_vm_start(&mut self, _: usize) -> bool {
+///         self.set_tick_period(Duration::from_secs(10));
+///         true
+///     }
+///
+///     fn on_tick(&mut self) {
+///         if let Ok(requests) = self.requests.get() {
+///             self.publisher.publish(&requests.to_string()).ok();
+///         }
+///     }
+/// }
+/// ```
+pub struct Publisher<T, C> {
+    vm_id: String,
+    topic: String,
+    codec: C,
+    queue_id: Option<u32>,
+    pending: VecDeque<Vec<u8>>,
+    capacity: usize,
+    dropped: u64,
+    // Whether delivery has failed since it last succeeded, so the failure is logged once.
+    failing: bool,
+    message_type: PhantomData<fn(T)>,
+}
+
+impl<T, C> Publisher<T, C>
+where
+    C: Codec<T>,
+{
+    /// Creates a publisher to a topic subscribed to by a given VM.
+    pub fn new<V, N>(vm_id: V, topic: N, codec: C) -> Self
+    where
+        V: Into<String>,
+        N: Into<String>,
+    {
+        Publisher {
+            vm_id: vm_id.into(),
+            topic: topic.into(),
+            codec,
+            queue_id: None,
+            pending: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            dropped: 0,
+            failing: false,
+            message_type: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of pending messages.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns the number of messages that have not been delivered yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of messages dropped because too many were pending.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Publishes a message, along with any pending ones.
+    ///
+    /// Fails only if the message cannot be encoded. An undelivered message is
+    /// kept pending instead, and the first of consecutive failures to deliver
+    /// messages is logged as a warning.
+    pub fn publish(&mut self, message: &T) -> Result<()> {
+        self.pending.push_back(self.codec.encode(message)?);
+        match self.flush() {
+            Ok(_) => self.failing = false,
+            Err(error) => {
+                if !self.failing {
+                    hostcalls::log(
+                        LogLevel::Warn,
+                        &format!(
+                            "failed to publish messages to topic \"{}\": {}",
+                            self.topic, error
+                        ),
+                    )
+                    .ok();
+                }
+                self.failing = true;
+            }
+        }
+        while self.pending.len() > self.capacity {
+            self.pending.pop_front();
+            self.dropped += 1;
+        }
+        Ok(())
+    }
+
+    /// Delivers pending messages, returning how many have been delivered.
+    ///
+    /// Returns `Ok(0)` while the topic has no subscriber.
+    pub fn flush(&mut self) -> Result<usize> {
+        let mut delivered = 0;
+        while !self.pending.is_empty() {
+            let queue_id = match self.resolve()? {
+                Some(queue_id) => queue_id,
+                None => break,
+            };
+            match hostcalls::enqueue_shared_queue(queue_id, self.pending.front()) {
+                Ok(()) => {
+                    self.pending.pop_front();
+                    delivered += 1;
+                }
+                // The subscriber might have been restarted, so resolve the queue next time.
+                Err(error) if has_status(&*error, Status::NotFound) => {
+                    self.queue_id = None;
+                    break;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(delivered)
+    }
+
+    fn resolve(&mut self) -> Result<Option<u32>> {
+        if self.queue_id.is_none() {
+            self.queue_id = hostcalls::resolve_shared_queue(&self.vm_id, &self.topic)?;
+        }
+        Ok(self.queue_id)
+    }
+}
+
+/// Subscribes to a topic, passing each decoded message to a given handler.
+///
+/// Registers a shared queue named after the topic, which publishers in other
+/// VMs resolve by the id of this VM. The subscription lives as long as the
+/// current root context.
+pub fn subscribe<T, C, F>(topic: &str, codec: C, mut handler: F) -> Result<()>
+where
+    T: 'static,
+    C: Codec<T> + 'static,
+    F: FnMut(T) + 'static,
+{
+    QueueReceiver::register(topic, codec)?.on_ready(move |messages| {
+        for message in messages {
+            handler(message);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,204 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::marker::PhantomData;
+
+use super::codec::Codec;
+use crate::dispatcher;
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::LogLevel;
+
+/// A handle to enqueue typed messages to a shared queue, possibly of another VM.
+pub struct QueueSender<T, C> {
+    queue_id: u32,
+    codec: C,
+    message_type: PhantomData<fn(T)>,
+}
+
+impl<T, C> QueueSender<T, C>
+where
+    C: Codec<T>,
+{
+    pub fn new(queue_id: u32, codec: C) -> Self {
+        QueueSender {
+            queue_id,
+            codec,
+            message_type: PhantomData,
+        }
+    }
+
+    /// Resolves a shared queue registered by a given VM.
+    pub fn resolve(vm_id: &str, name: &str, codec: C) -> Result<Option<Self>> {
+        Ok(hostcalls::resolve_shared_queue(vm_id, name)?
+            .map(|queue_id| QueueSender::new(queue_id, codec)))
+    }
+
+    pub fn queue_id(&self) -> u32 {
+        self.queue_id
+    }
+
+    pub fn send(&self, message: &T) -> Result<()> {
+        hostcalls::enqueue_shared_queue(self.queue_id, Some(self.codec.encode(message)?))
+    }
+}
+
+/// A handle to dequeue typed messages from a shared queue of this VM.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::shared::codec::Raw;
+/// use proxy_wasm::shared::QueueReceiver;
+/// use proxy_wasm::traits::*;
+///
+/// struct Collector;
+///
+/// impl Context for Collector {}
+///
+/// impl RootContext for Collector {
+///     fn on_vm_start(&mut self, _: usize) -> bool {
+///         match QueueReceiver::register("events", Raw) {
+///             Ok(receiver) => {
+///                 receiver.with_batch_size(100).on_ready(|events: Vec<String>| {
+///                     // Process up to 100 events at once.
+///                 });
+///                 true
+///             }
+///             Err(_) => false,
+///         }
+///     }
+/// }
+/// ```
+pub struct QueueReceiver<T, C> {
+    queue_id: u32,
+    codec: C,
+    batch_size: usize,
+    message_type: PhantomData<fn() -> T>,
+}
+
+impl<T, C> QueueReceiver<T, C>
+where
+    C: Codec<T>,
+{
+    pub fn new(queue_id: u32, codec: C) -> Self {
+        QueueReceiver {
+            queue_id,
+            codec,
+            batch_size: usize::MAX,
+            message_type: PhantomData,
+        }
+    }
+
+    /// Registers a shared queue of this VM, or returns the one already registered.
+    pub fn register(name: &str, codec: C) -> Result<Self> {
+        Ok(QueueReceiver::new(
+            hostcalls::register_shared_queue(name)?,
+            codec,
+        ))
+    }
+
+    /// Sets the maximum number of messages passed to the handler at once.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn queue_id(&self) -> u32 {
+        self.queue_id
+    }
+
+    /// Dequeues a message, if any.
+    pub fn recv(&self) -> Result<Option<T>> {
+        match hostcalls::dequeue_shared_queue(self.queue_id)? {
+            Some(data) => Ok(Some(self.codec.decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Dequeues up to the batch size of messages.
+    ///
+    /// Messages that cannot be decoded are logged at [`LogLevel::Warn`] and dropped.
+    ///
+    /// [`LogLevel::Warn`]: ../types/enum.LogLevel.html#variant.Warn
+    pub fn drain(&self) -> Result<Vec<T>> {
+        let mut messages = Vec::new();
+        while messages.len() < self.batch_size {
+            let data = match hostcalls::dequeue_shared_queue(self.queue_id)? {
+                Some(data) => data,
+                None => break,
+            };
+            match self.codec.decode(&data) {
+                Ok(message) => messages.push(message),
+                Err(error) => {
+                    hostcalls::log(
+                        LogLevel::Warn,
+                        &format!(
+                            "dropping message from shared queue {}: {}",
+                            self.queue_id, error
+                        ),
+                    )
+                    .ok();
+                }
+            }
+        }
+        Ok(messages)
+    }
+}
+
+impl<T, C> QueueReceiver<T, C>
+where
+    T: 'static,
+    C: Codec<T> + 'static,
+{
+    /// Sets a handler that is passed the queued messages, in batches, whenever
+    /// the queue has items.
+    ///
+    /// The handler replaces [`RootContext::on_queue_ready`] for this queue, and
+    /// lives as long as the root context of the context that has set it. Like a
+    /// [`timer`], it runs on behalf of a child context that has set it while that exists.
+    ///
+    /// [`RootContext::on_queue_ready`]: ../traits/trait.RootContext.html#method.on_queue_ready
+    /// [`timer`]: ../timer/index.html
+    pub fn on_ready<F>(self, mut handler: F)
+    where
+        F: FnMut(Vec<T>) + 'static,
+    {
+        let queue_id = self.queue_id;
+        dispatcher::set_queue_handler(
+            queue_id,
+            Box::new(move || loop {
+                match self.drain() {
+                    Ok(messages) if messages.is_empty() => break,
+                    Ok(messages) => {
+                        let last = messages.len() < self.batch_size;
+                        handler(messages);
+                        if last {
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        hostcalls::log(
+                            LogLevel::Error,
+                            &format!("failed to drain shared queue {}: {}", queue_id, error),
+                        )
+                        .ok();
+                        break;
+                    }
+                }
+            }),
+        );
+    }
+}
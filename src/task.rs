@@ -0,0 +1,173 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Async/await support for HTTP callouts.
+//!
+//! A future spawned with [`spawn_local`] belongs to the context whose callback
+//! spawned it. It is polled right away, and then every time an HTTP call
+//! dispatched with [`dispatch_http_call_async`] completes for that context.
+//! Futures are dropped together with their context, and responses to calls
+//! of a context that no longer exists are discarded.
+//!
+//! Wakers are not used, so only futures built from the futures of this module
+//! make progress.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use proxy_wasm_experimental as proxy_wasm;
+//! use proxy_wasm::hostcalls;
+//! use proxy_wasm::task;
+//! use proxy_wasm::traits::*;
+//! use proxy_wasm::types::*;
+//! use std::time::Duration;
+//!
+//! struct Auth;
+//!
+//! impl Context for Auth {}
+//!
+//! impl HttpContext for Auth {
+//!     fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+//!         task::spawn_local(async {
+//!             let headers = vec![(":method", "GET"), (":path", "/auth"), (":authority", "auth")];
+//!             let response = task::dispatch_http_call_async(
+//!                 "auth",
+//!                 &headers,
+//!                 hostcalls::NO_BODY,
+//!                 hostcalls::NO_TRAILERS,
+//!                 Duration::from_secs(1),
+//!             )
+//!             .unwrap()
+//!             .await;
+//!             if response.ok().and_then(|response| response.status_code()) == Some(200) {
+//!                 hostcalls::continue_stream(StreamType::Request).unwrap();
+//!             } else {
+//!                 hostcalls::send_http_response(403, hostcalls::NO_HEADERS, hostcalls::NO_BODY)
+//!                     .unwrap();
+//!             }
+//!         });
+//!         Action::Pause
+//!     }
+//! }
+//! ```
+//!
+//! [`spawn_local`]: fn.spawn_local.html
+//! [`dispatch_http_call_async`]: fn.dispatch_http_call_async.html
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::dispatcher;
+use crate::error::Result;
+use crate::hostcalls;
+use crate::types::{BufferType, ByteString, MapType};
+
+/// Spawns a future on the current context.
+pub fn spawn_local<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    dispatcher::spawn(Box::pin(future));
+}
+
+/// Dispatches an HTTP call and returns a future that resolves to its response.
+pub fn dispatch_http_call_async<K1, V1, K2, V2, B>(
+    upstream: &str,
+    headers: &[(K1, V1)],
+    body: Option<B>,
+    trailers: &[(K2, V2)],
+    timeout: Duration,
+) -> Result<HttpCallFuture>
+where
+    K1: AsRef<[u8]>,
+    V1: AsRef<[u8]>,
+    K2: AsRef<[u8]>,
+    V2: AsRef<[u8]>,
+    B: AsRef<[u8]>,
+{
+    let token_id = hostcalls::dispatch_http_call(upstream, headers, body, trailers, timeout)?;
+    let response = Rc::new(RefCell::new(None));
+    dispatcher::register_async_callout(token_id, Rc::clone(&response));
+    Ok(HttpCallFuture { token_id, response })
+}
+
+/// Response to an HTTP call.
+///
+/// A call that has failed, e.g. timed out, has no headers.
+#[derive(Debug, Clone, Default)]
+pub struct HttpCallResponse {
+    pub headers: Vec<(ByteString, ByteString)>,
+    pub body: Option<ByteString>,
+    pub trailers: Vec<(ByteString, ByteString)>,
+}
+
+impl HttpCallResponse {
+    pub(crate) fn load(num_headers: usize, body_size: usize, num_trailers: usize) -> Result<Self> {
+        Ok(HttpCallResponse {
+            headers: match num_headers {
+                0 => Vec::new(),
+                _ => hostcalls::get_map(MapType::HttpCallResponseHeaders)?,
+            },
+            body: match body_size {
+                0 => None,
+                _ => hostcalls::get_buffer(BufferType::HttpCallResponseBody, 0, body_size)?,
+            },
+            trailers: match num_trailers {
+                0 => Vec::new(),
+                _ => hostcalls::get_map(MapType::HttpCallResponseTrailers)?,
+            },
+        })
+    }
+
+    /// Returns value of the `:status` pseudo-header.
+    pub fn status_code(&self) -> Option<u32> {
+        self.headers
+            .iter()
+            .find(|(name, _)| *name == ":status")
+            .and_then(|(_, value)| std::str::from_utf8(value).ok())
+            .and_then(|value| value.parse().ok())
+    }
+}
+
+/// Slot through which the dispatcher hands the outcome of an HTTP call to its future.
+pub(crate) type HttpCallSlot = Rc<RefCell<Option<Result<HttpCallResponse>>>>;
+
+/// A future that resolves to the response to an HTTP call, or to an error if the response
+/// cannot be read from the host.
+#[derive(Debug)]
+pub struct HttpCallFuture {
+    token_id: u32,
+    response: HttpCallSlot,
+}
+
+impl HttpCallFuture {
+    pub fn token_id(&self) -> u32 {
+        self.token_id
+    }
+}
+
+impl Future for HttpCallFuture {
+    type Output = Result<HttpCallResponse>;
+
+    fn poll(self: Pin<&mut Self>, _: &mut Context) -> Poll<Self::Output> {
+        match self.response.borrow_mut().take() {
+            Some(response) => Poll::Ready(response),
+            None => Poll::Pending,
+        }
+    }
+}
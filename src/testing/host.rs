@@ -0,0 +1,1231 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hashbrown::HashMap;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::allocator;
+use crate::hostcalls::utils;
+use crate::types::*;
+
+thread_local! {
+static HOST: RefCell<Host> = RefCell::new(Host::default());
+}
+
+/// Runs a given function against the mock host state of the current thread.
+pub fn with_host<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Host) -> R,
+{
+    HOST.with(|host| f(&mut host.borrow_mut()))
+}
+
+/// Resets the mock host state of the current thread.
+pub fn reset() {
+    with_host(|host| *host = Host::default());
+}
+
+/// Returns messages logged on the mock host of the current thread, in order.
+pub fn logs() -> Vec<(LogLevel, String)> {
+    with_host(|host| host.logs().to_vec())
+}
+
+/// Returns whether a given message has been logged at any level.
+pub fn logged(message: &str) -> bool {
+    with_host(|host| host.logs().iter().any(|(_, logged)| logged == message))
+}
+
+/// A local response sent by the Wasm module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalResponse {
+    pub status_code: u32,
+    pub status_code_details: Option<String>,
+    pub headers: Vec<(ByteString, ByteString)>,
+    pub body: Option<ByteString>,
+    pub grpc_status: i32,
+}
+
+/// An HTTP call dispatched by the Wasm module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpCall {
+    pub token_id: u32,
+    pub upstream: String,
+    pub headers: Vec<(ByteString, ByteString)>,
+    pub body: Option<ByteString>,
+    pub trailers: Vec<(ByteString, ByteString)>,
+    pub timeout: Duration,
+}
+
+/// A unary gRPC call dispatched by the Wasm module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcCall {
+    pub token_id: u32,
+    pub upstream: String,
+    pub service_name: String,
+    pub method_name: String,
+    pub initial_metadata: Vec<(ByteString, ByteString)>,
+    pub message: Option<ByteString>,
+    pub timeout: Duration,
+    pub cancelled: bool,
+}
+
+/// A gRPC stream opened by the Wasm module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcStream {
+    pub token_id: u32,
+    pub upstream: String,
+    pub service_name: String,
+    pub method_name: String,
+    pub initial_metadata: Vec<(ByteString, ByteString)>,
+    pub messages: Vec<ByteString>,
+    pub closed: bool,
+    pub cancelled: bool,
+}
+
+#[derive(Debug)]
+struct Metric {
+    metric_type: MetricType,
+    name: String,
+    value: u64,
+    samples: Vec<u64>,
+}
+
+#[derive(Debug)]
+struct SharedQueue {
+    vm_id: String,
+    name: String,
+    items: VecDeque<ByteString>,
+}
+
+#[cfg(feature = "abi-0-2-1")]
+type ForeignFunction = Box<dyn FnMut(&[u8]) -> Option<Vec<u8>>>;
+
+/// In-memory state of the mock host.
+///
+/// Every `proxy_*` function imported by the SDK reads and mutates this state
+/// instead of calling into a real proxy.
+pub struct Host {
+    log_level: LogLevel,
+    logs: Vec<(LogLevel, String)>,
+    current_time: SystemTime,
+    tick_period: Duration,
+    buffers: HashMap<BufferType, ByteString>,
+    maps: HashMap<MapType, Vec<(ByteString, ByteString)>>,
+    properties: HashMap<Vec<u8>, ByteString>,
+    shared_data: HashMap<String, (ByteString, u32)>,
+    vm_id: String,
+    queues: Vec<SharedQueue>,
+    continued_streams: Vec<StreamType>,
+    closed_streams: Vec<StreamType>,
+    local_responses: Vec<LocalResponse>,
+    next_token_id: u32,
+    http_calls: Vec<HttpCall>,
+    grpc_calls: Vec<GrpcCall>,
+    grpc_streams: Vec<GrpcStream>,
+    metrics: Vec<Metric>,
+    effective_context: Option<u32>,
+    done_count: usize,
+    failures: HashMap<String, Status>,
+    #[cfg(feature = "abi-0-2-1")]
+    foreign_functions: HashMap<String, ForeignFunction>,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host {
+            log_level: LogLevel::Trace,
+            logs: Vec::new(),
+            current_time: UNIX_EPOCH,
+            tick_period: Duration::from_millis(0),
+            buffers: HashMap::new(),
+            maps: HashMap::new(),
+            properties: HashMap::new(),
+            shared_data: HashMap::new(),
+            vm_id: String::new(),
+            queues: Vec::new(),
+            continued_streams: Vec::new(),
+            closed_streams: Vec::new(),
+            local_responses: Vec::new(),
+            next_token_id: 1,
+            http_calls: Vec::new(),
+            grpc_calls: Vec::new(),
+            grpc_streams: Vec::new(),
+            metrics: Vec::new(),
+            effective_context: None,
+            done_count: 0,
+            failures: HashMap::new(),
+            #[cfg(feature = "abi-0-2-1")]
+            foreign_functions: HashMap::new(),
+        }
+    }
+}
+
+impl Host {
+    /// Makes the next call to a given host function fail with a given status.
+    ///
+    /// Functions are identified by their ABI name, e.g. `proxy_http_call`.
+    pub fn fail_next(&mut self, function: &str, status: Status) {
+        self.failures.insert(function.to_owned(), status);
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    /// Sets the log level reported to the module by `proxy_get_log_level`.
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level = level;
+    }
+
+    pub fn logs(&self) -> &[(LogLevel, String)] {
+        &self.logs
+    }
+
+    pub fn clear_logs(&mut self) {
+        self.logs.clear();
+    }
+
+    pub fn current_time(&self) -> SystemTime {
+        self.current_time
+    }
+
+    pub fn set_current_time(&mut self, time: SystemTime) {
+        self.current_time = time;
+    }
+
+    pub fn advance_time(&mut self, duration: Duration) {
+        self.current_time += duration;
+    }
+
+    /// Returns the tick period requested by the module, if any.
+    pub fn tick_period(&self) -> Option<Duration> {
+        if self.tick_period == Duration::from_millis(0) {
+            None
+        } else {
+            Some(self.tick_period)
+        }
+    }
+
+    pub fn buffer(&self, buffer_type: BufferType) -> Option<&ByteString> {
+        self.buffers.get(&buffer_type)
+    }
+
+    pub fn set_buffer<B>(&mut self, buffer_type: BufferType, value: Option<B>)
+    where
+        B: AsRef<[u8]>,
+    {
+        match value {
+            Some(value) => {
+                self.buffers
+                    .insert(buffer_type, value.as_ref().to_vec().into());
+            }
+            None => {
+                self.buffers.remove(&buffer_type);
+            }
+        }
+    }
+
+    pub fn map(&self, map_type: MapType) -> Option<&[(ByteString, ByteString)]> {
+        self.maps.get(&map_type).map(Vec::as_slice)
+    }
+
+    pub fn map_value<K>(&self, map_type: MapType, key: K) -> Option<&ByteString>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.maps.get(&map_type).and_then(|map| {
+            map.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key.as_ref()))
+                .map(|(_, value)| value)
+        })
+    }
+
+    pub fn set_map<K, V>(&mut self, map_type: MapType, map: &[(K, V)])
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.maps.insert(
+            map_type,
+            map.iter()
+                .map(|(name, value)| {
+                    (
+                        name.as_ref().to_vec().into(),
+                        value.as_ref().to_vec().into(),
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    pub fn remove_map(&mut self, map_type: MapType) {
+        self.maps.remove(&map_type);
+    }
+
+    pub fn property<P>(&self, path: &[P]) -> Option<&ByteString>
+    where
+        P: AsRef<str>,
+    {
+        self.properties.get(&utils::serialize_property_path(path))
+    }
+
+    pub fn set_property<P, V>(&mut self, path: &[P], value: Option<V>)
+    where
+        P: AsRef<str>,
+        V: AsRef<[u8]>,
+    {
+        let path = utils::serialize_property_path(path);
+        match value {
+            Some(value) => {
+                self.properties.insert(path, value.as_ref().to_vec().into());
+            }
+            None => {
+                self.properties.remove(&path);
+            }
+        }
+    }
+
+    /// Returns shared data by key together with its CAS value.
+    pub fn shared_data(&self, key: &str) -> Option<(&ByteString, u32)> {
+        self.shared_data.get(key).map(|(value, cas)| (value, *cas))
+    }
+
+    pub fn set_shared_data<V>(&mut self, key: &str, value: V)
+    where
+        V: AsRef<[u8]>,
+    {
+        let cas = self.shared_data.get(key).map_or(1, |(_, cas)| cas + 1);
+        self.shared_data
+            .insert(key.to_owned(), (value.as_ref().to_vec().into(), cas));
+    }
+
+    pub fn vm_id(&self) -> &str {
+        &self.vm_id
+    }
+
+    /// Sets the VM id used to register shared queues.
+    pub fn set_vm_id(&mut self, vm_id: &str) {
+        self.vm_id = vm_id.to_owned();
+    }
+
+    /// Registers a shared queue on behalf of another VM.
+    pub fn register_shared_queue(&mut self, vm_id: &str, name: &str) -> u32 {
+        if let Some(queue_id) = self.find_shared_queue(vm_id, name) {
+            return queue_id;
+        }
+        self.queues.push(SharedQueue {
+            vm_id: vm_id.to_owned(),
+            name: name.to_owned(),
+            items: VecDeque::new(),
+        });
+        self.queues.len() as u32
+    }
+
+    pub fn shared_queue(&self, queue_id: u32) -> Option<&VecDeque<ByteString>> {
+        self.queue_index(queue_id)
+            .map(|index| &self.queues[index].items)
+    }
+
+    pub fn enqueue_shared_queue<V>(&mut self, queue_id: u32, value: V) -> bool
+    where
+        V: AsRef<[u8]>,
+    {
+        if let Some(index) = self.queue_index(queue_id) {
+            self.queues[index]
+                .items
+                .push_back(value.as_ref().to_vec().into());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns streams resumed by the module, in order.
+    pub fn continued_streams(&self) -> &[StreamType] {
+        &self.continued_streams
+    }
+
+    /// Returns streams closed by the module, in order.
+    pub fn closed_streams(&self) -> &[StreamType] {
+        &self.closed_streams
+    }
+
+    pub fn local_responses(&self) -> &[LocalResponse] {
+        &self.local_responses
+    }
+
+    pub fn http_calls(&self) -> &[HttpCall] {
+        &self.http_calls
+    }
+
+    pub fn grpc_calls(&self) -> &[GrpcCall] {
+        &self.grpc_calls
+    }
+
+    pub fn grpc_streams(&self) -> &[GrpcStream] {
+        &self.grpc_streams
+    }
+
+    /// Returns value of a given counter or gauge.
+    pub fn metric(&self, name: &str) -> Option<u64> {
+        self.metrics
+            .iter()
+            .find(|metric| metric.name == name)
+            .map(|metric| metric.value)
+    }
+
+    /// Returns values recorded into a given histogram.
+    pub fn histogram(&self, name: &str) -> Option<&[u64]> {
+        self.metrics
+            .iter()
+            .find(|metric| metric.name == name && metric.metric_type == MetricType::Histogram)
+            .map(|metric| metric.samples.as_slice())
+    }
+
+    /// Returns the context last made effective by the module.
+    pub fn effective_context(&self) -> Option<u32> {
+        self.effective_context
+    }
+
+    /// Returns the number of times the module has called `proxy_done`.
+    pub fn done_count(&self) -> usize {
+        self.done_count
+    }
+
+    /// Registers a function the module can call by name via `proxy_call_foreign_function`.
+    #[cfg(feature = "abi-0-2-1")]
+    pub fn register_foreign_function<F>(&mut self, name: &str, f: F)
+    where
+        F: FnMut(&[u8]) -> Option<Vec<u8>> + 'static,
+    {
+        self.foreign_functions.insert(name.to_owned(), Box::new(f));
+    }
+
+    fn take_failure(&mut self, function: &str) -> Option<Status> {
+        self.failures.remove(function)
+    }
+
+    fn next_token_id(&mut self) -> u32 {
+        let token_id = self.next_token_id;
+        self.next_token_id += 1;
+        token_id
+    }
+
+    fn find_shared_queue(&self, vm_id: &str, name: &str) -> Option<u32> {
+        self.queues
+            .iter()
+            .position(|queue| queue.vm_id == vm_id && queue.name == name)
+            .map(|index| index as u32 + 1)
+    }
+
+    fn queue_index(&self, queue_id: u32) -> Option<usize> {
+        match queue_id as usize {
+            0 => None,
+            id if id <= self.queues.len() => Some(id - 1),
+            _ => None,
+        }
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    if data.is_null() || size == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, size)
+    }
+}
+
+unsafe fn string(data: *const u8, size: usize) -> String {
+    String::from_utf8_lossy(bytes(data, size)).into_owned()
+}
+
+unsafe fn optional_bytes(data: *const u8, size: usize) -> Option<ByteString> {
+    if data.is_null() {
+        None
+    } else {
+        Some(bytes(data, size).to_vec().into())
+    }
+}
+
+unsafe fn map(data: *const u8, size: usize) -> Option<Vec<(ByteString, ByteString)>> {
+    utils::deserialize_map(bytes(data, size)).ok()
+}
+
+/// Hands over a copy of given bytes to the module the same way a real host does,
+/// i.e. in memory allocated by the module itself.
+unsafe fn return_bytes(
+    value: &[u8],
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) {
+    let data = allocator::proxy_on_memory_allocate(value.len());
+    std::ptr::copy_nonoverlapping(value.as_ptr(), data, value.len());
+    *return_value_data = data;
+    *return_value_size = value.len();
+}
+
+macro_rules! fail_if_requested {
+    ($host:expr, $function:expr) => {
+        if let Some(status) = $host.take_failure($function) {
+            return status;
+        }
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_log(
+    level: LogLevel,
+    message_data: *const u8,
+    message_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_log");
+        host.logs.push((level, string(message_data, message_size)));
+        Status::Ok
+    })
+}
+
+#[cfg(feature = "abi-0-2-1")]
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_log_level(return_level: *mut u32) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_log_level");
+        *return_level = host.log_level as u32;
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_current_time_nanoseconds(return_time: *mut u64) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_current_time_nanoseconds");
+        *return_time = host
+            .current_time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_tick_period_milliseconds(period: u32) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_set_tick_period_milliseconds");
+        host.tick_period = Duration::from_millis(u64::from(period));
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_buffer_bytes(
+    buffer_type: BufferType,
+    start: usize,
+    max_size: usize,
+    return_buffer_data: *mut *mut u8,
+    return_buffer_size: *mut usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_buffer_bytes");
+        match host.buffers.get(&buffer_type) {
+            Some(buffer) => {
+                let start = start.min(buffer.len());
+                let end = start.saturating_add(max_size).min(buffer.len());
+                return_bytes(&buffer[start..end], return_buffer_data, return_buffer_size);
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_buffer_bytes(
+    buffer_type: BufferType,
+    start: usize,
+    size: usize,
+    buffer_data: *const u8,
+    buffer_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_set_buffer_bytes");
+        // As with a real host, only existing buffers can be modified.
+        let buffer = match host.buffers.get_mut(&buffer_type) {
+            Some(buffer) => buffer,
+            None => return Status::NotFound,
+        };
+        let mut content = std::mem::take(buffer).into_bytes();
+        let start = start.min(content.len());
+        let end = start.saturating_add(size).min(content.len());
+        content.splice(start..end, bytes(buffer_data, buffer_size).iter().cloned());
+        *buffer = content.into();
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_header_map_pairs(
+    map_type: MapType,
+    return_map_data: *mut *mut u8,
+    return_map_size: *mut usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_header_map_pairs");
+        let serialized_map = utils::serialize_map(host.map(map_type).unwrap_or(&[]));
+        return_bytes(&serialized_map, return_map_data, return_map_size);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_header_map_pairs(
+    map_type: MapType,
+    map_data: *const u8,
+    map_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_set_header_map_pairs");
+        match map(map_data, map_size) {
+            Some(map) => {
+                host.maps.insert(map_type, map);
+                Status::Ok
+            }
+            None => Status::BadArgument,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_header_map_value");
+        match host.map_value(map_type, bytes(key_data, key_size)) {
+            Some(value) => {
+                return_bytes(value, return_value_data, return_value_size);
+                Status::Ok
+            }
+            None => Status::Ok,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_replace_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_replace_header_map_value");
+        let key = bytes(key_data, key_size);
+        let value: ByteString = bytes(value_data, value_size).to_vec().into();
+        let map = host.maps.entry(map_type).or_insert_with(Vec::new);
+        match map
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(key))
+        {
+            Some(index) => {
+                map[index].1 = value;
+                let mut seen = false;
+                map.retain(|(name, _)| {
+                    if !name.eq_ignore_ascii_case(key) {
+                        return true;
+                    }
+                    let first = !seen;
+                    seen = true;
+                    first
+                });
+            }
+            None => map.push((key.to_vec().into(), value)),
+        }
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_remove_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_remove_header_map_value");
+        let key = bytes(key_data, key_size);
+        if let Some(map) = host.maps.get_mut(&map_type) {
+            map.retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+        }
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_add_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_add_header_map_value");
+        host.maps.entry(map_type).or_insert_with(Vec::new).push((
+            bytes(key_data, key_size).to_vec().into(),
+            bytes(value_data, value_size).to_vec().into(),
+        ));
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_property(
+    path_data: *const u8,
+    path_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_property");
+        match host.properties.get(bytes(path_data, path_size)) {
+            Some(value) => {
+                return_bytes(value, return_value_data, return_value_size);
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_property(
+    path_data: *const u8,
+    path_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_set_property");
+        let path = bytes(path_data, path_size).to_vec();
+        match optional_bytes(value_data, value_size) {
+            Some(value) => {
+                host.properties.insert(path, value);
+            }
+            None => {
+                host.properties.remove(&path);
+            }
+        }
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_shared_data(
+    key_data: *const u8,
+    key_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+    return_cas: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_shared_data");
+        match host.shared_data.get(&string(key_data, key_size)) {
+            Some((value, cas)) => {
+                return_bytes(value, return_value_data, return_value_size);
+                *return_cas = *cas;
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_shared_data(
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+    cas: u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_set_shared_data");
+        let key = string(key_data, key_size);
+        if let Some((_, current_cas)) = host.shared_data.get(&key) {
+            if cas != 0 && cas != *current_cas {
+                return Status::CasMismatch;
+            }
+        }
+        host.set_shared_data(&key, bytes(value_data, value_size));
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_register_shared_queue(
+    name_data: *const u8,
+    name_size: usize,
+    return_id: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_register_shared_queue");
+        let vm_id = host.vm_id.clone();
+        *return_id = host.register_shared_queue(&vm_id, &string(name_data, name_size));
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_resolve_shared_queue(
+    vm_id_data: *const u8,
+    vm_id_size: usize,
+    name_data: *const u8,
+    name_size: usize,
+    return_id: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_resolve_shared_queue");
+        match host.find_shared_queue(
+            &string(vm_id_data, vm_id_size),
+            &string(name_data, name_size),
+        ) {
+            Some(queue_id) => {
+                *return_id = queue_id;
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_dequeue_shared_queue(
+    queue_id: u32,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_dequeue_shared_queue");
+        match host.queue_index(queue_id) {
+            Some(index) => match host.queues[index].items.pop_front() {
+                Some(value) => {
+                    return_bytes(&value, return_value_data, return_value_size);
+                    Status::Ok
+                }
+                None => Status::Empty,
+            },
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_enqueue_shared_queue(
+    queue_id: u32,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_enqueue_shared_queue");
+        if host.enqueue_shared_queue(queue_id, bytes(value_data, value_size)) {
+            Status::Ok
+        } else {
+            Status::NotFound
+        }
+    })
+}
+
+#[cfg(feature = "abi-0-2-1")]
+#[no_mangle]
+pub unsafe extern "C" fn proxy_continue_stream(stream: StreamType) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_continue_stream");
+        host.continued_streams.push(stream);
+        Status::Ok
+    })
+}
+
+#[cfg(not(feature = "abi-0-2-1"))]
+#[no_mangle]
+pub unsafe extern "C" fn proxy_continue_request() -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_continue_request");
+        host.continued_streams.push(StreamType::Request);
+        Status::Ok
+    })
+}
+
+#[cfg(not(feature = "abi-0-2-1"))]
+#[no_mangle]
+pub unsafe extern "C" fn proxy_continue_response() -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_continue_response");
+        host.continued_streams.push(StreamType::Response);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_close_stream(stream: StreamType) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_close_stream");
+        host.closed_streams.push(stream);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_send_local_response(
+    status_code: u32,
+    status_code_details_data: *const u8,
+    status_code_details_size: usize,
+    body_data: *const u8,
+    body_size: usize,
+    headers_data: *const u8,
+    headers_size: usize,
+    grpc_status: i32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_send_local_response");
+        let headers = match map(headers_data, headers_size) {
+            Some(headers) => headers,
+            None => return Status::BadArgument,
+        };
+        let status_code_details = if status_code_details_data.is_null() {
+            None
+        } else {
+            Some(string(status_code_details_data, status_code_details_size))
+        };
+        host.local_responses.push(LocalResponse {
+            status_code,
+            status_code_details,
+            headers,
+            body: optional_bytes(body_data, body_size),
+            grpc_status,
+        });
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_http_call(
+    upstream_data: *const u8,
+    upstream_size: usize,
+    headers_data: *const u8,
+    headers_size: usize,
+    body_data: *const u8,
+    body_size: usize,
+    trailers_data: *const u8,
+    trailers_size: usize,
+    timeout: u32,
+    return_token: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_http_call");
+        let (headers, trailers) = match (
+            map(headers_data, headers_size),
+            map(trailers_data, trailers_size),
+        ) {
+            (Some(headers), Some(trailers)) => (headers, trailers),
+            _ => return Status::BadArgument,
+        };
+        let token_id = host.next_token_id();
+        host.http_calls.push(HttpCall {
+            token_id,
+            upstream: string(upstream_data, upstream_size),
+            headers,
+            body: optional_bytes(body_data, body_size),
+            trailers,
+            timeout: Duration::from_millis(u64::from(timeout)),
+        });
+        *return_token = token_id;
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_grpc_call(
+    upstream_data: *const u8,
+    upstream_size: usize,
+    service_name_data: *const u8,
+    service_name_size: usize,
+    method_name_data: *const u8,
+    method_name_size: usize,
+    initial_metadata_data: *const u8,
+    initial_metadata_size: usize,
+    message_data: *const u8,
+    message_size: usize,
+    timeout: u32,
+    return_token: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_grpc_call");
+        let initial_metadata = match map(initial_metadata_data, initial_metadata_size) {
+            Some(initial_metadata) => initial_metadata,
+            None => return Status::BadArgument,
+        };
+        let token_id = host.next_token_id();
+        host.grpc_calls.push(GrpcCall {
+            token_id,
+            upstream: string(upstream_data, upstream_size),
+            service_name: string(service_name_data, service_name_size),
+            method_name: string(method_name_data, method_name_size),
+            initial_metadata,
+            message: optional_bytes(message_data, message_size),
+            timeout: Duration::from_millis(u64::from(timeout)),
+            cancelled: false,
+        });
+        *return_token = token_id;
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_grpc_stream(
+    upstream_data: *const u8,
+    upstream_size: usize,
+    service_name_data: *const u8,
+    service_name_size: usize,
+    method_name_data: *const u8,
+    method_name_size: usize,
+    initial_metadata_data: *const u8,
+    initial_metadata_size: usize,
+    return_token: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_grpc_stream");
+        let initial_metadata = match map(initial_metadata_data, initial_metadata_size) {
+            Some(initial_metadata) => initial_metadata,
+            None => return Status::BadArgument,
+        };
+        let token_id = host.next_token_id();
+        host.grpc_streams.push(GrpcStream {
+            token_id,
+            upstream: string(upstream_data, upstream_size),
+            service_name: string(service_name_data, service_name_size),
+            method_name: string(method_name_data, method_name_size),
+            initial_metadata,
+            messages: Vec::new(),
+            closed: false,
+            cancelled: false,
+        });
+        *return_token = token_id;
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_grpc_send(
+    token: u32,
+    message_data: *const u8,
+    message_size: usize,
+    end_stream: bool,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_grpc_send");
+        match host
+            .grpc_streams
+            .iter_mut()
+            .find(|stream| stream.token_id == token)
+        {
+            Some(stream) => {
+                stream
+                    .messages
+                    .push(bytes(message_data, message_size).to_vec().into());
+                stream.closed |= end_stream;
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_grpc_cancel(token: u32) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_grpc_cancel");
+        if let Some(call) = host
+            .grpc_calls
+            .iter_mut()
+            .find(|call| call.token_id == token)
+        {
+            call.cancelled = true;
+            Status::Ok
+        } else if let Some(stream) = host
+            .grpc_streams
+            .iter_mut()
+            .find(|stream| stream.token_id == token)
+        {
+            stream.cancelled = true;
+            Status::Ok
+        } else {
+            Status::NotFound
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_grpc_close(token: u32) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_grpc_close");
+        match host
+            .grpc_streams
+            .iter_mut()
+            .find(|stream| stream.token_id == token)
+        {
+            Some(stream) => {
+                stream.closed = true;
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_set_effective_context(context_id: u32) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_set_effective_context");
+        host.effective_context = Some(context_id);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_done() -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_done");
+        host.done_count += 1;
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_define_metric(
+    metric_type: MetricType,
+    name_data: *const u8,
+    name_size: usize,
+    return_id: *mut u32,
+) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_define_metric");
+        let name = string(name_data, name_size);
+        let index = match host.metrics.iter().position(|metric| metric.name == name) {
+            Some(index) if host.metrics[index].metric_type == metric_type => index,
+            Some(_) => return Status::BadArgument,
+            None => {
+                host.metrics.push(Metric {
+                    metric_type,
+                    name,
+                    value: 0,
+                    samples: Vec::new(),
+                });
+                host.metrics.len() - 1
+            }
+        };
+        *return_id = index as u32;
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_increment_metric(metric_id: u32, offset: i64) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_increment_metric");
+        match host.metrics.get_mut(metric_id as usize) {
+            Some(metric) if metric.metric_type == MetricType::Counter && offset < 0 => {
+                Status::BadArgument
+            }
+            Some(metric) if metric.metric_type != MetricType::Histogram => {
+                metric.value = (metric.value as i64).wrapping_add(offset) as u64;
+                Status::Ok
+            }
+            Some(_) => Status::BadArgument,
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_record_metric(metric_id: u32, value: u64) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_record_metric");
+        match host.metrics.get_mut(metric_id as usize) {
+            Some(metric) => {
+                match metric.metric_type {
+                    MetricType::Counter => metric.value = metric.value.wrapping_add(value),
+                    MetricType::Gauge => metric.value = value,
+                    MetricType::Histogram => metric.samples.push(value),
+                }
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn proxy_get_metric(metric_id: u32, return_value: *mut u64) -> Status {
+    with_host(|host| {
+        fail_if_requested!(host, "proxy_get_metric");
+        match host.metrics.get(metric_id as usize) {
+            Some(metric) if metric.metric_type != MetricType::Histogram => {
+                *return_value = metric.value;
+                Status::Ok
+            }
+            Some(_) => Status::BadArgument,
+            None => Status::NotFound,
+        }
+    })
+}
+
+#[cfg(feature = "abi-0-2-1")]
+#[no_mangle]
+pub unsafe extern "C" fn proxy_call_foreign_function(
+    function_name_data: *const u8,
+    function_name_size: usize,
+    arguments_data: *const u8,
+    arguments_size: usize,
+    return_results_data: *mut *mut u8,
+    return_results_size: *mut usize,
+) -> Status {
+    let name = string(function_name_data, function_name_size);
+    // The function is taken out of the host, so that it can make hostcalls itself.
+    let f = with_host(
+        |host| match host.take_failure("proxy_call_foreign_function") {
+            Some(status) => Err(status),
+            None => host.foreign_functions.remove(&name).ok_or(Status::NotFound),
+        },
+    );
+    let mut f = match f {
+        Ok(f) => f,
+        Err(status) => return status,
+    };
+    let results = f(bytes(arguments_data, arguments_size));
+    // Unless the function has been replaced in the meantime.
+    with_host(|host| {
+        host.foreign_functions.entry(name).or_insert(f);
+    });
+    if let Some(results) = results {
+        return_bytes(&results, return_results_data, return_results_size);
+    }
+    Status::Ok
+}
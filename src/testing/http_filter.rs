@@ -0,0 +1,575 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use super::host::{with_host, LocalResponse};
+use crate::dispatcher;
+use crate::traits::{HttpContext, RootContext};
+use crate::types::*;
+
+// Context ids are unique per process, since the dispatcher outlives a single test.
+static NEXT_CONTEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+fn next_context_id() -> u32 {
+    NEXT_CONTEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Drives an HTTP filter through the lifecycle callbacks in the order a proxy would.
+///
+/// Every step is executed immediately, against the mock host of the current thread,
+/// and the `expect_*` methods assert on its outcome. The root context and the HTTP
+/// context are created by the constructors registered with [`set_root_context`] and
+/// [`set_http_context`], so these must be called before the first step.
+///
+/// If neither [`vm_config`] nor [`plugin_config`] is called before the first HTTP
+/// event, the root context is started with empty configuration.
+///
+/// # Examples
+///
+/// ```
+/// # use proxy_wasm_experimental as proxy_wasm;
+/// use proxy_wasm::testing::HttpFilterTest;
+/// use proxy_wasm::traits::*;
+/// use proxy_wasm::types::*;
+///
+/// struct DenyAll;
+///
+/// impl Context for DenyAll {}
+///
+/// impl HttpContext for DenyAll {
+///     fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+///         self.send_http_response(403, vec![], Some(b"Access forbidden.\n"));
+///         Action::Pause
+///     }
+/// }
+///
+/// proxy_wasm::set_http_context(|_, _| -> Box<dyn HttpContext> { Box::new(DenyAll) });
+///
+/// HttpFilterTest::new()
+///     .request_headers(&[(":method", "GET"), (":path", "/")], true)
+///     .expect_action(Action::Pause)
+///     .expect_local_response(403)
+///     .complete();
+/// ```
+///
+/// [`set_root_context`]: ../fn.set_root_context.html
+/// [`set_http_context`]: ../fn.set_http_context.html
+/// [`vm_config`]: #method.vm_config
+/// [`plugin_config`]: #method.plugin_config
+pub struct HttpFilterTest {
+    root_context_id: u32,
+    http_context_id: u32,
+    root_created: bool,
+    vm_started: bool,
+    configured: bool,
+    http_created: bool,
+    last_action: Option<Action>,
+    last_result: Option<bool>,
+    answered_http_calls: usize,
+}
+
+impl Default for HttpFilterTest {
+    fn default() -> Self {
+        HttpFilterTest::new()
+    }
+}
+
+impl HttpFilterTest {
+    pub fn new() -> Self {
+        let root_context_id = next_context_id();
+        HttpFilterTest {
+            root_context_id,
+            http_context_id: next_context_id(),
+            root_created: false,
+            vm_started: false,
+            configured: false,
+            http_created: false,
+            last_action: None,
+            last_result: None,
+            answered_http_calls: 0,
+        }
+    }
+
+    /// Registers a given constructor of root contexts and starts a new test.
+    ///
+    /// A shorthand for [`set_root_context`] followed by [`new`].
+    ///
+    /// [`set_root_context`]: ../fn.set_root_context.html
+    /// [`new`]: #method.new
+    pub fn with_root_context<F, C>(mut constructor: F) -> Self
+    where
+        F: FnMut(u32) -> C + 'static,
+        C: RootContext + 'static,
+    {
+        crate::set_root_context(move |context_id| -> Box<dyn RootContext> {
+            Box::new(constructor(context_id))
+        });
+        HttpFilterTest::new()
+    }
+
+    /// Registers a given constructor of HTTP contexts and starts a new test.
+    ///
+    /// A shorthand for [`set_http_context`] followed by [`new`].
+    ///
+    /// [`set_http_context`]: ../fn.set_http_context.html
+    /// [`new`]: #method.new
+    pub fn with_http_context<F, C>(mut constructor: F) -> Self
+    where
+        F: FnMut(u32, u32) -> C + 'static,
+        C: HttpContext + 'static,
+    {
+        crate::set_http_context(move |context_id, root_context_id| -> Box<dyn HttpContext> {
+            Box::new(constructor(context_id, root_context_id))
+        });
+        HttpFilterTest::new()
+    }
+
+    pub fn root_context_id(&self) -> u32 {
+        self.root_context_id
+    }
+
+    pub fn http_context_id(&self) -> u32 {
+        self.http_context_id
+    }
+
+    /// Starts the VM with a given configuration.
+    pub fn vm_config<B>(mut self, config: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        self.create_root();
+        let size = config.as_ref().len();
+        with_host(|host| host.set_buffer(BufferType::VmConfiguration, Some(config)));
+        self.last_result = Some(dispatcher::proxy_on_vm_start(self.root_context_id, size));
+        self.vm_started = true;
+        self
+    }
+
+    /// Configures the plugin with a given configuration.
+    pub fn plugin_config<B>(mut self, config: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        if !self.vm_started {
+            self = self.vm_config(b"");
+        }
+        let size = config.as_ref().len();
+        with_host(|host| host.set_buffer(BufferType::PluginConfiguration, Some(config)));
+        self.last_result = Some(dispatcher::proxy_on_configure(self.root_context_id, size));
+        self.configured = true;
+        self
+    }
+
+    /// Fires the timer of the root context.
+    pub fn tick(mut self) -> Self {
+        self.create_root();
+        dispatcher::proxy_on_tick(self.root_context_id);
+        self
+    }
+
+    /// Notifies the root context that a given shared queue has items.
+    pub fn queue_ready(mut self, queue_id: u32) -> Self {
+        self.create_root();
+        dispatcher::proxy_on_queue_ready(self.root_context_id, queue_id);
+        self
+    }
+
+    /// Calls a function of the root context with given arguments.
+    #[cfg(feature = "abi-0-2-1")]
+    pub fn foreign_function<B>(mut self, function_id: u32, arguments: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        self.create_root();
+        let size = arguments.as_ref().len();
+        with_host(|host| host.set_buffer(BufferType::CallData, Some(arguments)));
+        dispatcher::proxy_on_foreign_function(self.root_context_id, function_id, size);
+        self
+    }
+
+    /// Advances the clock of the mock host.
+    pub fn advance_time(self, duration: Duration) -> Self {
+        with_host(|host| host.advance_time(duration));
+        self
+    }
+
+    /// On ABI 0.1.0, `end_of_stream` is not passed to the HTTP context, as with a real host.
+    pub fn request_headers(mut self, headers: &[(&str, &str)], end_of_stream: bool) -> Self {
+        self.create_http();
+        with_host(|host| host.set_map(MapType::HttpRequestHeaders, headers));
+        #[cfg(feature = "abi-0-2-1")]
+        let action = dispatcher::proxy_on_request_headers(
+            self.http_context_id,
+            headers.len(),
+            end_of_stream,
+        );
+        #[cfg(not(feature = "abi-0-2-1"))]
+        let action = {
+            let _ = end_of_stream;
+            dispatcher::proxy_on_request_headers(self.http_context_id, headers.len())
+        };
+        self.last_action = Some(action);
+        self
+    }
+
+    pub fn request_body<B>(mut self, body: B, end_of_stream: bool) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        self.create_http();
+        let size = body.as_ref().len();
+        with_host(|host| host.set_buffer(BufferType::HttpRequestBody, Some(body)));
+        self.last_action = Some(dispatcher::proxy_on_request_body(
+            self.http_context_id,
+            size,
+            end_of_stream,
+        ));
+        self
+    }
+
+    pub fn request_trailers(mut self, trailers: &[(&str, &str)]) -> Self {
+        self.create_http();
+        with_host(|host| host.set_map(MapType::HttpRequestTrailers, trailers));
+        self.last_action = Some(dispatcher::proxy_on_request_trailers(
+            self.http_context_id,
+            trailers.len(),
+        ));
+        self
+    }
+
+    /// On ABI 0.1.0, `end_of_stream` is not passed to the HTTP context, as with a real host.
+    pub fn response_headers(mut self, headers: &[(&str, &str)], end_of_stream: bool) -> Self {
+        self.create_http();
+        with_host(|host| host.set_map(MapType::HttpResponseHeaders, headers));
+        #[cfg(feature = "abi-0-2-1")]
+        let action = dispatcher::proxy_on_response_headers(
+            self.http_context_id,
+            headers.len(),
+            end_of_stream,
+        );
+        #[cfg(not(feature = "abi-0-2-1"))]
+        let action = {
+            let _ = end_of_stream;
+            dispatcher::proxy_on_response_headers(self.http_context_id, headers.len())
+        };
+        self.last_action = Some(action);
+        self
+    }
+
+    pub fn response_body<B>(mut self, body: B, end_of_stream: bool) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        self.create_http();
+        let size = body.as_ref().len();
+        with_host(|host| host.set_buffer(BufferType::HttpResponseBody, Some(body)));
+        self.last_action = Some(dispatcher::proxy_on_response_body(
+            self.http_context_id,
+            size,
+            end_of_stream,
+        ));
+        self
+    }
+
+    pub fn response_trailers(mut self, trailers: &[(&str, &str)]) -> Self {
+        self.create_http();
+        with_host(|host| host.set_map(MapType::HttpResponseTrailers, trailers));
+        self.last_action = Some(dispatcher::proxy_on_response_trailers(
+            self.http_context_id,
+            trailers.len(),
+        ));
+        self
+    }
+
+    /// Delivers a response to the oldest HTTP call that hasn't been answered yet.
+    pub fn http_call_response(
+        mut self,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+        trailers: &[(&str, &str)],
+    ) -> Self {
+        let token_id = with_host(|host| {
+            host.http_calls()
+                .get(self.answered_http_calls)
+                .map(|call| call.token_id)
+        })
+        .expect("no pending HTTP call");
+        self.answered_http_calls += 1;
+        let body_size = body.map_or(0, <[u8]>::len);
+        with_host(|host| {
+            host.set_map(MapType::HttpCallResponseHeaders, headers);
+            host.set_buffer(BufferType::HttpCallResponseBody, body);
+            host.set_map(MapType::HttpCallResponseTrailers, trailers);
+        });
+        dispatcher::proxy_on_http_call_response(
+            self.http_context_id,
+            token_id,
+            headers.len(),
+            body_size,
+            trailers.len(),
+        );
+        self
+    }
+
+    /// Delivers initial metadata of a given gRPC stream.
+    pub fn grpc_initial_metadata(self, token_id: u32, metadata: &[(&str, &str)]) -> Self {
+        with_host(|host| host.set_map(MapType::GrpcReceiveInitialMetadata, metadata));
+        dispatcher::proxy_on_grpc_receive_initial_metadata(
+            self.http_context_id,
+            token_id,
+            metadata.len(),
+        );
+        self
+    }
+
+    /// Delivers a message of a given gRPC call or stream.
+    pub fn grpc_message<B>(self, token_id: u32, message: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        let message = message.as_ref();
+        with_host(|host| host.set_buffer(BufferType::GrpcReceiveBuffer, Some(message)));
+        dispatcher::proxy_on_grpc_receive(self.http_context_id, token_id, message.len());
+        self
+    }
+
+    /// Delivers trailing metadata of a given gRPC stream.
+    pub fn grpc_trailing_metadata(self, token_id: u32, metadata: &[(&str, &str)]) -> Self {
+        with_host(|host| host.set_map(MapType::GrpcReceiveTrailingMetadata, metadata));
+        dispatcher::proxy_on_grpc_receive_trailing_metadata(
+            self.http_context_id,
+            token_id,
+            metadata.len(),
+        );
+        self
+    }
+
+    /// Closes a given gRPC call or stream with a given status code.
+    pub fn grpc_close(self, token_id: u32, status_code: u32) -> Self {
+        dispatcher::proxy_on_grpc_close(self.http_context_id, token_id, status_code);
+        self
+    }
+
+    /// Completes the HTTP stream, i.e. calls `proxy_on_done`, `proxy_on_log` and
+    /// `proxy_on_delete` on the HTTP context.
+    pub fn complete(mut self) -> Self {
+        if self.http_created {
+            dispatcher::proxy_on_done(self.http_context_id);
+            dispatcher::proxy_on_log(self.http_context_id);
+            dispatcher::proxy_on_delete(self.http_context_id);
+            self.http_created = false;
+        }
+        self
+    }
+
+    /// Runs a given function against the mock host, e.g. to make custom assertions.
+    pub fn inspect<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut super::Host),
+    {
+        with_host(f);
+        self
+    }
+
+    /// Asserts the action returned by the last HTTP callback.
+    pub fn expect_action(self, action: Action) -> Self {
+        assert_eq!(self.last_action, Some(action), "unexpected action");
+        self
+    }
+
+    /// Asserts the result of the last `proxy_on_vm_start` or `proxy_on_configure` call.
+    pub fn expect_config_accepted(self, accepted: bool) -> Self {
+        assert_eq!(
+            self.last_result,
+            Some(accepted),
+            "unexpected configuration result"
+        );
+        self
+    }
+
+    pub fn expect_local_response(self, status_code: u32) -> Self {
+        let response = self.last_local_response().expect("no local response sent");
+        assert_eq!(
+            response.status_code, status_code,
+            "unexpected local response status"
+        );
+        self
+    }
+
+    pub fn expect_local_response_body<B>(self, body: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        let response = self.last_local_response().expect("no local response sent");
+        assert_eq!(
+            response.body.as_ref().map(|body| body.as_bytes()),
+            Some(body.as_ref()),
+            "unexpected local response body"
+        );
+        self
+    }
+
+    pub fn expect_no_local_response(self) -> Self {
+        assert_eq!(
+            self.last_local_response(),
+            None,
+            "unexpected local response"
+        );
+        self
+    }
+
+    /// Asserts the current value of a request header, `None` meaning absence.
+    pub fn expect_request_header(self, name: &str, value: Option<&str>) -> Self {
+        self.expect_map_value(MapType::HttpRequestHeaders, name, value)
+    }
+
+    /// Asserts the current value of a response header, `None` meaning absence.
+    pub fn expect_response_header(self, name: &str, value: Option<&str>) -> Self {
+        self.expect_map_value(MapType::HttpResponseHeaders, name, value)
+    }
+
+    /// Asserts the current content of the request body buffer.
+    pub fn expect_request_body<B>(self, body: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        self.expect_buffer(BufferType::HttpRequestBody, body.as_ref())
+    }
+
+    /// Asserts the current content of the response body buffer.
+    pub fn expect_response_body<B>(self, body: B) -> Self
+    where
+        B: AsRef<[u8]>,
+    {
+        self.expect_buffer(BufferType::HttpResponseBody, body.as_ref())
+    }
+
+    /// Asserts the upstream of the oldest HTTP call that hasn't been answered yet.
+    pub fn expect_http_call(self, upstream: &str) -> Self {
+        let call_upstream = with_host(|host| {
+            host.http_calls()
+                .get(self.answered_http_calls)
+                .map(|call| call.upstream.clone())
+        })
+        .expect("no pending HTTP call");
+        assert_eq!(call_upstream, upstream, "unexpected HTTP call upstream");
+        self
+    }
+
+    pub fn expect_no_http_call(self) -> Self {
+        let pending = with_host(|host| host.http_calls().len()) - self.answered_http_calls;
+        assert_eq!(pending, 0, "unexpected HTTP call");
+        self
+    }
+
+    /// Asserts that a given stream has been resumed, e.g. via `proxy_continue_stream`.
+    pub fn expect_resumed(self, stream_type: StreamType) -> Self {
+        assert!(
+            with_host(|host| host.continued_streams().contains(&stream_type)),
+            "{:?} stream has not been resumed",
+            stream_type
+        );
+        self
+    }
+
+    /// Asserts that a given stream has been closed via `proxy_close_stream`.
+    pub fn expect_closed(self, stream_type: StreamType) -> Self {
+        assert!(
+            with_host(|host| host.closed_streams().contains(&stream_type)),
+            "{:?} stream has not been closed",
+            stream_type
+        );
+        self
+    }
+
+    /// Asserts that a message has been logged at a given level.
+    pub fn expect_log(self, level: LogLevel, message: &str) -> Self {
+        assert!(
+            with_host(|host| host.logs().iter().any(|(l, m)| *l == level && m == message)),
+            "message {:?} has not been logged at level {:?}",
+            message,
+            level
+        );
+        self
+    }
+
+    /// Asserts that the last message has been logged at [`LogLevel::Error`] and contains
+    /// a given text.
+    ///
+    /// [`LogLevel::Error`]: ../types/enum.LogLevel.html#variant.Error
+    pub fn expect_error(self, text: &str) -> Self {
+        let last = with_host(|host| host.logs().last().cloned());
+        assert!(
+            last.as_ref()
+                .is_some_and(|(level, message)| *level == LogLevel::Error && message.contains(text)),
+            "last message {:?} is not an error containing {:?}",
+            last,
+            text
+        );
+        self
+    }
+
+    fn expect_map_value(self, map_type: MapType, name: &str, value: Option<&str>) -> Self {
+        let actual = with_host(|host| host.map_value(map_type, name).cloned());
+        assert_eq!(
+            actual.as_ref().map(|value| value.as_bytes()),
+            value.map(str::as_bytes),
+            "unexpected value of {:?} {:?}",
+            map_type,
+            name
+        );
+        self
+    }
+
+    fn expect_buffer(self, buffer_type: BufferType, content: &[u8]) -> Self {
+        let actual = with_host(|host| host.buffer(buffer_type).cloned());
+        assert_eq!(
+            actual.as_ref().map(|value| value.as_bytes()),
+            Some(content),
+            "unexpected content of {:?}",
+            buffer_type
+        );
+        self
+    }
+
+    fn last_local_response(&self) -> Option<LocalResponse> {
+        with_host(|host| host.local_responses().last().cloned())
+    }
+
+    fn create_root(&mut self) {
+        if !self.root_created {
+            dispatcher::proxy_on_context_create(self.root_context_id, 0);
+            self.root_created = true;
+        }
+    }
+
+    fn create_http(&mut self) {
+        if self.http_created {
+            return;
+        }
+        self.create_root();
+        if !self.vm_started {
+            self.last_result = Some(dispatcher::proxy_on_vm_start(self.root_context_id, 0));
+            self.vm_started = true;
+        }
+        if !self.configured {
+            self.last_result = Some(dispatcher::proxy_on_configure(self.root_context_id, 0));
+            self.configured = true;
+        }
+        dispatcher::proxy_on_context_create(self.http_context_id, self.root_context_id);
+        self.http_created = true;
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An in-process mock host for unit testing filters natively.
+//!
+//! With feature `proxy-wasm-test` enabled, the SDK provides its own implementation
+//! of every `proxy_*` function it imports from the host. State of the mock host,
+//! e.g. header maps, buffers, properties and shared data, is kept per thread,
+//! so that tests running in parallel do not interfere with each other.
+//!
+//! The feature is meant for `cargo test` on the build machine and has no effect
+//! when compiling to `wasm32` targets.
+//!
+//! # Examples
+//!
+//! ```
+//! # use proxy_wasm_experimental as proxy_wasm;
+//! use proxy_wasm::testing;
+//! use proxy_wasm::traits::*;
+//! use proxy_wasm::types::*;
+//!
+//! struct AddHeader;
+//!
+//! impl Context for AddHeader {}
+//!
+//! impl HttpContext for AddHeader {
+//!     fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+//!         self.set_http_request_header("x-powered-by", Some("proxy-wasm"));
+//!         Action::Continue
+//!     }
+//! }
+//!
+//! testing::with_host(|host| {
+//!     host.set_map(MapType::HttpRequestHeaders, &[(":path", "/")]);
+//! });
+//!
+//! assert_eq!(AddHeader.on_http_request_headers(1, true), Action::Continue);
+//!
+//! testing::with_host(|host| {
+//!     assert_eq!(
+//!         host.map_value(MapType::HttpRequestHeaders, "x-powered-by").unwrap(),
+//!         "proxy-wasm"
+//!     );
+//! });
+//! ```
+
+mod host;
+mod http_filter;
+
+pub use self::host::{
+    logged, logs, reset, with_host, GrpcCall, GrpcStream, Host, HttpCall, LocalResponse,
+};
+pub use self::http_filter::HttpFilterTest;
@@ -0,0 +1,291 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timers multiplexed on the tick of a root context.
+//!
+//! A timer belongs to the root context of the context that scheduled it, and
+//! outlives child contexts. While a child context that scheduled a timer exists,
+//! the timer runs on its behalf, i.e. with it as the effective context, and a
+//! panic in it fails the child context as set with [`set_panic_policy`], which
+//! also cancels the rest of its timers. While any timer is scheduled, the tick period of
+//! the root context is the greatest common divisor of the intervals of its
+//! timers and of the period set with [`RootContext::set_tick_period`], and
+//! timers that are due are fired right after [`RootContext::on_tick`], which
+//! is still called only once per the latter period.
+//!
+//! Intervals are rounded up to whole milliseconds, and the tick period is kept
+//! at or above the timer resolution, 10 ms unless set with [`set_resolution`],
+//! so timers with shorter or coprime intervals fire late rather than making the
+//! host tick every millisecond. As timers only fire on ticks, a timer scheduled
+//! partway through a tick period can also fire up to one tick late.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use proxy_wasm_experimental as proxy_wasm;
+//! use proxy_wasm::timer;
+//! use proxy_wasm::traits::*;
+//! use proxy_wasm::types::*;
+//! use std::time::Duration;
+//!
+//! struct Root;
+//!
+//! impl Context for Root {}
+//!
+//! impl RootContext for Root {
+//!     fn on_vm_start(&mut self, _: usize) -> bool {
+//!         timer::schedule_every(Duration::from_secs(10), || {
+//!             proxy_wasm::hostcalls::log(LogLevel::Info, "refreshing").unwrap();
+//!         })
+//!         .is_ok()
+//!     }
+//! }
+//! ```
+//!
+//! [`set_panic_policy`]: ../fn.set_panic_policy.html
+//! [`RootContext::set_tick_period`]: ../traits/trait.RootContext.html#method.set_tick_period
+//! [`RootContext::on_tick`]: ../traits/trait.RootContext.html#method.on_tick
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use crate::dispatcher;
+use crate::error::Result;
+
+/// Schedules a callback to be called once after a given delay.
+pub fn schedule_after<F>(delay: Duration, callback: F) -> Result<TimerHandle>
+where
+    F: FnOnce() + 'static,
+{
+    dispatcher::schedule_timer(delay, None, Callback::Once(Box::new(callback)))
+}
+
+/// Schedules a callback to be called every given period.
+pub fn schedule_every<F>(period: Duration, callback: F) -> Result<TimerHandle>
+where
+    F: FnMut() + 'static,
+{
+    dispatcher::schedule_timer(period, Some(period), Callback::Every(Box::new(callback)))
+}
+
+/// Sets the resolution of timers, i.e. the shortest tick period used for them.
+///
+/// It takes effect the next time a timer is scheduled, cancelled or fired. The
+/// tick period set with [`RootContext::set_tick_period`] is never lengthened for it.
+///
+/// [`RootContext::set_tick_period`]: ../traits/trait.RootContext.html#method.set_tick_period
+pub fn set_resolution(resolution: Duration) {
+    dispatcher::set_timer_resolution(round_up(resolution));
+}
+
+/// Cancels a timer.
+///
+/// Cancelling a timer that has already fired or has been cancelled is a no-op.
+pub fn cancel(handle: TimerHandle) -> Result<()> {
+    dispatcher::cancel_timer(handle)
+}
+
+/// Default resolution of timers.
+pub(crate) const DEFAULT_RESOLUTION: Duration = Duration::from_millis(10);
+
+/// Identifies a scheduled timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle {
+    pub(crate) root_context_id: u32,
+    pub(crate) timer_id: u64,
+}
+
+pub(crate) enum Callback {
+    Once(Box<dyn FnOnce()>),
+    Every(Box<dyn FnMut()>),
+}
+
+struct Timer {
+    // Context that has scheduled the timer.
+    context_id: u32,
+    deadline: SystemTime,
+    interval: Duration,
+    periodic: bool,
+    // Taken out while the callback runs.
+    callback: Option<Callback>,
+}
+
+/// Timers of a single root context.
+#[derive(Default)]
+pub(crate) struct Timers {
+    next_id: u64,
+    timers: BTreeMap<u64, Timer>,
+    tick_period: Duration,
+    // Period set by the root context itself, and when `on_tick` is due next.
+    user_period: Duration,
+    next_user_tick: Option<SystemTime>,
+}
+
+impl Timers {
+    pub(crate) fn insert(
+        &mut self,
+        now: SystemTime,
+        context_id: u32,
+        delay: Duration,
+        period: Option<Duration>,
+        callback: Callback,
+    ) -> u64 {
+        self.next_id += 1;
+        self.timers.insert(
+            self.next_id,
+            Timer {
+                context_id,
+                deadline: now + round_up(delay),
+                interval: round_up(period.unwrap_or(delay)),
+                periodic: period.is_some(),
+                callback: Some(callback),
+            },
+        );
+        self.next_id
+    }
+
+    pub(crate) fn remove(&mut self, timer_id: u64) -> bool {
+        self.timers.remove(&timer_id).is_some()
+    }
+
+    /// Removes the timers scheduled by a given context.
+    pub(crate) fn remove_context(&mut self, context_id: u32) {
+        self.timers
+            .retain(|_, timer| timer.context_id != context_id);
+    }
+
+    /// Returns whether there are neither timers nor a tick period of the root context.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.timers.is_empty() && self.user_period == Duration::ZERO
+    }
+
+    /// Sets the tick period of the root context, returning the previous one.
+    pub(crate) fn set_user_period(&mut self, now: SystemTime, period: Duration) -> Duration {
+        self.next_user_tick = Some(now + period).filter(|_| period > Duration::ZERO);
+        std::mem::replace(&mut self.user_period, period)
+    }
+
+    /// Returns whether `on_tick` of the root context is due, and if so, schedules the next one.
+    ///
+    /// Without timers, every tick is one of the root context.
+    pub(crate) fn take_user_tick(&mut self, now: SystemTime) -> bool {
+        if self.timers.is_empty() {
+            self.set_user_period(now, self.user_period);
+            return true;
+        }
+        match self.next_user_tick {
+            Some(next) if next <= now => {
+                let next = next + self.user_period;
+                self.next_user_tick = Some(if next <= now {
+                    now + self.user_period
+                } else {
+                    next
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes callbacks of the timers that are due, in the order of their deadlines,
+    /// along with the contexts that have scheduled them.
+    pub(crate) fn take_expired(&mut self, now: SystemTime) -> Vec<(u64, u32, Callback)> {
+        let mut expired: Vec<(SystemTime, u64)> = self
+            .timers
+            .iter()
+            .filter(|(_, timer)| timer.deadline <= now && timer.callback.is_some())
+            .map(|(timer_id, timer)| (timer.deadline, *timer_id))
+            .collect();
+        expired.sort();
+        expired
+            .into_iter()
+            .filter_map(|(_, timer_id)| {
+                let timer = self.timers.get_mut(&timer_id)?;
+                Some((timer_id, timer.context_id, timer.callback.take()?))
+            })
+            .collect()
+    }
+
+    /// Reschedules a periodic timer after its callback has run, unless it has been cancelled.
+    pub(crate) fn restore(&mut self, timer_id: u64, callback: Option<Callback>, now: SystemTime) {
+        let timer = match self.timers.get_mut(&timer_id) {
+            Some(timer) => timer,
+            None => return,
+        };
+        match callback {
+            Some(callback) if timer.periodic => {
+                timer.deadline += timer.interval;
+                if timer.deadline <= now {
+                    // Missed ticks are skipped rather than fired in a burst.
+                    timer.deadline = now + timer.interval;
+                }
+                timer.callback = Some(callback);
+            }
+            _ => {
+                self.timers.remove(&timer_id);
+            }
+        }
+    }
+
+    /// Updates the tick period, returning the new one if it has changed.
+    ///
+    /// With timers, the tick period is no shorter than a given resolution, unless the
+    /// tick period of the root context itself is.
+    pub(crate) fn update_tick_period(&mut self, resolution: Duration) -> Option<Duration> {
+        let period = self
+            .timers
+            .values()
+            .map(|timer| timer.interval.as_millis() as u64)
+            .fold(self.user_period.as_millis() as u64, gcd);
+        let mut period = Duration::from_millis(period);
+        if !self.timers.is_empty() {
+            let floor = match self.user_period {
+                Duration::ZERO => resolution,
+                user_period => resolution.min(user_period),
+            };
+            period = period.max(floor);
+        }
+        if period == self.tick_period {
+            return None;
+        }
+        self.tick_period = period;
+        Some(period)
+    }
+
+    /// Reverts the tick period, e.g. after the host has failed to set it.
+    pub(crate) fn reset_tick_period(&mut self, period: Duration) {
+        self.tick_period = period;
+    }
+
+    pub(crate) fn tick_period(&self) -> Duration {
+        self.tick_period
+    }
+}
+
+fn round_up(duration: Duration) -> Duration {
+    let millis = duration.as_millis() as u64;
+    if millis == 0 || Duration::from_millis(millis) < duration {
+        Duration::from_millis(millis + 1)
+    } else {
+        duration
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
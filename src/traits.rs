@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::dispatcher;
 use crate::hostcalls;
+use crate::task::{self, HttpCallFuture};
+use crate::timer::{self, TimerHandle};
 use crate::types::*;
 use std::time::{Duration, SystemTime};
 
@@ -20,19 +23,61 @@ use crate::error::Result;
 
 pub trait Context {
     fn get_current_time(&self) -> SystemTime {
-        hostcalls::get_current_time().unwrap()
+        self.try_get_current_time().unwrap()
+    }
+
+    fn try_get_current_time(&self) -> Result<SystemTime> {
+        hostcalls::get_current_time()
+    }
+
+    /// Schedules a callback to be called once after a given delay.
+    ///
+    /// The timer belongs to the root context, see the [`timer`](../timer/index.html) module.
+    fn schedule_after<F>(&self, delay: Duration, callback: F) -> Result<TimerHandle>
+    where
+        Self: Sized,
+        F: FnOnce() + 'static,
+    {
+        timer::schedule_after(delay, callback)
+    }
+
+    /// Schedules a callback to be called every given period.
+    ///
+    /// The timer belongs to the root context, see the [`timer`](../timer/index.html) module.
+    fn schedule_every<F>(&self, period: Duration, callback: F) -> Result<TimerHandle>
+    where
+        Self: Sized,
+        F: FnMut() + 'static,
+    {
+        timer::schedule_every(period, callback)
+    }
+
+    fn cancel_timer(&self, handle: TimerHandle) -> Result<()> {
+        timer::cancel(handle)
     }
 
     fn get_property(&self, path: Vec<&str>) -> Option<ByteString> {
-        hostcalls::get_property(&path).unwrap()
+        self.try_get_property(path).unwrap()
+    }
+
+    fn try_get_property(&self, path: Vec<&str>) -> Result<Option<ByteString>> {
+        hostcalls::get_property(&path)
     }
 
     fn set_property(&self, path: Vec<&str>, value: Option<&[u8]>) {
-        hostcalls::set_property(&path, value).unwrap()
+        self.try_set_property(path, value).unwrap()
+    }
+
+    fn try_set_property(&self, path: Vec<&str>, value: Option<&[u8]>) -> Result<()> {
+        hostcalls::set_property(&path, value)
     }
 
     fn get_shared_data(&self, key: &str) -> (Option<ByteString>, Option<u32>) {
-        hostcalls::get_shared_data(key).unwrap()
+        self.try_get_shared_data(key).unwrap()
+    }
+
+    fn try_get_shared_data(&self, key: &str) -> Result<(Option<ByteString>, Option<u32>)> {
+        hostcalls::get_shared_data(key)
     }
 
     fn set_shared_data(&self, key: &str, value: Option<&[u8]>, cas: Option<u32>) -> Result<()> {
@@ -40,11 +85,19 @@ pub trait Context {
     }
 
     fn register_shared_queue(&self, name: &str) -> u32 {
-        hostcalls::register_shared_queue(name).unwrap()
+        self.try_register_shared_queue(name).unwrap()
+    }
+
+    fn try_register_shared_queue(&self, name: &str) -> Result<u32> {
+        hostcalls::register_shared_queue(name)
     }
 
     fn resolve_shared_queue(&self, vm_id: &str, name: &str) -> Option<u32> {
-        hostcalls::resolve_shared_queue(vm_id, name).unwrap()
+        self.try_resolve_shared_queue(vm_id, name).unwrap()
+    }
+
+    fn try_resolve_shared_queue(&self, vm_id: &str, name: &str) -> Result<Option<u32>> {
+        hostcalls::resolve_shared_queue(vm_id, name)
     }
 
     fn dequeue_shared_queue(&self, queue_id: u32) -> Result<Option<ByteString>> {
@@ -55,6 +108,22 @@ pub trait Context {
         hostcalls::enqueue_shared_queue(queue_id, value)
     }
 
+    fn define_metric(&self, metric_type: MetricType, name: &str) -> Result<u32> {
+        hostcalls::define_metric(metric_type, name)
+    }
+
+    fn increment_metric(&self, metric_id: u32, offset: i64) -> Result<()> {
+        hostcalls::increment_metric(metric_id, offset)
+    }
+
+    fn record_metric(&self, metric_id: u32, value: u64) -> Result<()> {
+        hostcalls::record_metric(metric_id, value)
+    }
+
+    fn get_metric(&self, metric_id: u32) -> Result<u64> {
+        hostcalls::get_metric(metric_id)
+    }
+
     fn dispatch_http_call(
         &self,
         upstream: &str,
@@ -66,6 +135,17 @@ pub trait Context {
         hostcalls::dispatch_http_call(upstream, &headers, body, &trailers, timeout)
     }
 
+    fn dispatch_http_call_async(
+        &self,
+        upstream: &str,
+        headers: Vec<(&str, &str)>,
+        body: Option<&[u8]>,
+        trailers: Vec<(&str, &str)>,
+        timeout: Duration,
+    ) -> Result<HttpCallFuture> {
+        task::dispatch_http_call_async(upstream, &headers, body, &trailers, timeout)
+    }
+
     fn on_http_call_response(
         &mut self,
         _token_id: u32,
@@ -75,24 +155,189 @@ pub trait Context {
     ) {
     }
 
+    /// Fallible variant of `on_http_call_response`, which it calls by default.
+    ///
+    /// An error is logged and, under [`ErrorPolicy::FailClosed`], rejects the stream
+    /// of a stream context. There is no action to fail open with, so that policy only logs it.
+    ///
+    /// [`ErrorPolicy::FailClosed`]: ../types/enum.ErrorPolicy.html#variant.FailClosed
+    fn try_on_http_call_response(
+        &mut self,
+        token_id: u32,
+        num_headers: usize,
+        body_size: usize,
+        num_trailers: usize,
+    ) -> Result<()> {
+        self.on_http_call_response(token_id, num_headers, body_size, num_trailers);
+        Ok(())
+    }
+
     fn get_http_call_response_headers(&self) -> Vec<(ByteString, ByteString)> {
-        hostcalls::get_map(MapType::HttpCallResponseHeaders).unwrap()
+        self.try_get_http_call_response_headers().unwrap()
+    }
+
+    fn try_get_http_call_response_headers(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::HttpCallResponseHeaders)
+    }
+
+    /// Returns the headers of the HTTP call response as a [`HeaderMap`].
+    ///
+    /// [`HeaderMap`]: ../types/struct.HeaderMap.html
+    fn get_http_call_response_header_map(&self) -> HeaderMap {
+        self.try_get_http_call_response_header_map().unwrap()
+    }
+
+    fn try_get_http_call_response_header_map(&self) -> Result<HeaderMap> {
+        HeaderMap::load(MapType::HttpCallResponseHeaders)
     }
 
     fn get_http_call_response_body(&self, start: usize, max_size: usize) -> Option<ByteString> {
-        hostcalls::get_buffer(BufferType::HttpCallResponseBody, start, max_size).unwrap()
+        self.try_get_http_call_response_body(start, max_size)
+            .unwrap()
+    }
+
+    fn try_get_http_call_response_body(
+        &self,
+        start: usize,
+        max_size: usize,
+    ) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::HttpCallResponseBody, start, max_size)
     }
 
     fn get_http_call_response_trailers(&self) -> Vec<(ByteString, ByteString)> {
-        hostcalls::get_map(MapType::HttpCallResponseTrailers).unwrap()
+        self.try_get_http_call_response_trailers().unwrap()
+    }
+
+    fn try_get_http_call_response_trailers(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::HttpCallResponseTrailers)
+    }
+
+    fn dispatch_grpc_call(
+        &self,
+        upstream: &str,
+        service_name: &str,
+        method_name: &str,
+        initial_metadata: Vec<(&str, &str)>,
+        message: Option<&[u8]>,
+        timeout: Duration,
+    ) -> Result<u32> {
+        hostcalls::dispatch_grpc_call(
+            upstream,
+            service_name,
+            method_name,
+            &initial_metadata,
+            message,
+            timeout,
+        )
+    }
+
+    fn on_grpc_call_response(&mut self, _token_id: u32, _status_code: u32, _response_size: usize) {}
+
+    /// Fallible variant of `on_grpc_call_response`, which it calls by default.
+    ///
+    /// An error is handled like one of [`try_on_http_call_response`].
+    ///
+    /// [`try_on_http_call_response`]: #method.try_on_http_call_response
+    fn try_on_grpc_call_response(
+        &mut self,
+        token_id: u32,
+        status_code: u32,
+        response_size: usize,
+    ) -> Result<()> {
+        self.on_grpc_call_response(token_id, status_code, response_size);
+        Ok(())
+    }
+
+    fn get_grpc_call_response_body(&self, start: usize, max_size: usize) -> Option<ByteString> {
+        self.try_get_grpc_call_response_body(start, max_size)
+            .unwrap()
+    }
+
+    fn try_get_grpc_call_response_body(
+        &self,
+        start: usize,
+        max_size: usize,
+    ) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::GrpcReceiveBuffer, start, max_size)
+    }
+
+    fn cancel_grpc_call(&self, token_id: u32) -> Result<()> {
+        hostcalls::cancel_grpc_call(token_id)
+    }
+
+    fn open_grpc_stream(
+        &self,
+        upstream: &str,
+        service_name: &str,
+        method_name: &str,
+        initial_metadata: Vec<(&str, &str)>,
+    ) -> Result<u32> {
+        hostcalls::open_grpc_stream(upstream, service_name, method_name, &initial_metadata)
+    }
+
+    fn send_grpc_stream_message(
+        &self,
+        token_id: u32,
+        message: Option<&[u8]>,
+        end_stream: bool,
+    ) -> Result<()> {
+        hostcalls::send_grpc_stream_message(token_id, message, end_stream)
+    }
+
+    fn cancel_grpc_stream(&self, token_id: u32) -> Result<()> {
+        hostcalls::cancel_grpc_call(token_id)
+    }
+
+    fn close_grpc_stream(&self, token_id: u32) -> Result<()> {
+        hostcalls::close_grpc_stream(token_id)
+    }
+
+    fn on_grpc_receive_initial_metadata(&mut self, _token_id: u32, _num_elements: usize) {}
+
+    fn get_grpc_receive_initial_metadata(&self) -> Vec<(ByteString, ByteString)> {
+        self.try_get_grpc_receive_initial_metadata().unwrap()
+    }
+
+    fn try_get_grpc_receive_initial_metadata(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::GrpcReceiveInitialMetadata)
+    }
+
+    fn on_grpc_receive(&mut self, _token_id: u32, _response_size: usize) {}
+
+    fn get_grpc_stream_message(&self, start: usize, max_size: usize) -> Option<ByteString> {
+        self.try_get_grpc_stream_message(start, max_size).unwrap()
+    }
+
+    fn try_get_grpc_stream_message(
+        &self,
+        start: usize,
+        max_size: usize,
+    ) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::GrpcReceiveBuffer, start, max_size)
+    }
+
+    fn on_grpc_receive_trailing_metadata(&mut self, _token_id: u32, _num_elements: usize) {}
+
+    fn get_grpc_receive_trailing_metadata(&self) -> Vec<(ByteString, ByteString)> {
+        self.try_get_grpc_receive_trailing_metadata().unwrap()
     }
 
+    fn try_get_grpc_receive_trailing_metadata(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::GrpcReceiveTrailingMetadata)
+    }
+
+    fn on_grpc_close(&mut self, _token_id: u32, _status_code: u32) {}
+
     fn on_done(&mut self) -> bool {
         true
     }
 
     fn done(&self) {
-        hostcalls::done().unwrap()
+        self.try_done().unwrap()
+    }
+
+    fn try_done(&self) -> Result<()> {
+        hostcalls::done()
     }
 }
 
@@ -111,14 +356,32 @@ pub trait RootContext: Context {
         true
     }
 
+    /// Sets the tick period of the root context.
+    ///
+    /// While any [`timer`](../timer/index.html) is scheduled, the host ticks as often as the
+    /// timers need, but `on_tick` is still called once per this period.
     fn set_tick_period(&self, period: Duration) {
-        hostcalls::set_tick_period(period).unwrap()
+        self.try_set_tick_period(period).unwrap()
     }
 
+    fn try_set_tick_period(&self, period: Duration) -> Result<()> {
+        dispatcher::set_tick_period(period)
+    }
+
+    /// Called once per tick period, before any [`timer`](../timer/index.html) that is due.
     fn on_tick(&mut self) {}
 
+    /// Called when a shared queue has items, unless the queue has a handler set with
+    /// [`QueueReceiver::on_ready`](../shared/struct.QueueReceiver.html#method.on_ready).
     fn on_queue_ready(&mut self, _queue_id: u32) {}
 
+    /// Called when the host calls a function of the module, e.g. from a foreign function
+    /// of another module. The arguments can be read from [`BufferType::CallData`].
+    ///
+    /// [`BufferType::CallData`]: ../types/enum.BufferType.html#variant.CallData
+    #[cfg(feature = "abi-0-2-1")]
+    fn on_foreign_function(&mut self, _function_id: u32, _arguments_size: usize) {}
+
     fn on_log(&mut self) {}
 
     fn on_create_child_context(&mut self, _context_id: u32) -> Option<ChildContext> {
@@ -128,6 +391,13 @@ pub trait RootContext: Context {
     }
 }
 
+/// Callbacks of a context that handles a TCP stream.
+///
+/// The dispatcher calls the `try_*` variant of each data callback, which by default calls
+/// the infallible callback. Override it instead to use `?`: an error is logged and handled
+/// according to the policy set with [`set_error_policy`].
+///
+/// [`set_error_policy`]: ../fn.set_error_policy.html
 pub trait StreamContext: Context {
     fn on_new_connection(&mut self) -> Action {
         Action::Continue
@@ -137,8 +407,55 @@ pub trait StreamContext: Context {
         Action::Continue
     }
 
+    /// Fallible variant of `on_downstream_data`, which it calls by default.
+    fn try_on_downstream_data(&mut self, data_size: usize, end_of_stream: bool) -> Result<Action> {
+        Ok(self.on_downstream_data(data_size, end_of_stream))
+    }
+
     fn get_downstream_data(&self, start: usize, max_size: usize) -> Option<ByteString> {
-        hostcalls::get_buffer(BufferType::DownstreamData, start, max_size).unwrap()
+        self.try_get_downstream_data(start, max_size).unwrap()
+    }
+
+    fn try_get_downstream_data(&self, start: usize, max_size: usize) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::DownstreamData, start, max_size)
+    }
+
+    /// Replaces `size` bytes at offset `start` of the buffered downstream data.
+    ///
+    /// Hosts might support only replacing, appending and prepending.
+    fn set_downstream_data(&self, start: usize, size: usize, value: &[u8]) {
+        self.try_set_downstream_data(start, size, value).unwrap()
+    }
+
+    fn try_set_downstream_data(&self, start: usize, size: usize, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::DownstreamData, start, size, value)
+    }
+
+    /// Replaces the whole buffered downstream data.
+    fn replace_downstream_data(&self, value: &[u8]) {
+        self.try_replace_downstream_data(value).unwrap()
+    }
+
+    fn try_replace_downstream_data(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::DownstreamData, 0, usize::MAX, value)
+    }
+
+    /// Appends bytes to the buffered downstream data.
+    fn append_downstream_data(&self, value: &[u8]) {
+        self.try_append_downstream_data(value).unwrap()
+    }
+
+    fn try_append_downstream_data(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::DownstreamData, usize::MAX, 0, value)
+    }
+
+    /// Prepends bytes to the buffered downstream data.
+    fn prepend_downstream_data(&self, value: &[u8]) {
+        self.try_prepend_downstream_data(value).unwrap()
+    }
+
+    fn try_prepend_downstream_data(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::DownstreamData, 0, 0, value)
     }
 
     fn on_downstream_close(&mut self, _peer_type: PeerType) {}
@@ -147,134 +464,634 @@ pub trait StreamContext: Context {
         Action::Continue
     }
 
+    /// Fallible variant of `on_upstream_data`, which it calls by default.
+    fn try_on_upstream_data(&mut self, data_size: usize, end_of_stream: bool) -> Result<Action> {
+        Ok(self.on_upstream_data(data_size, end_of_stream))
+    }
+
     fn get_upstream_data(&self, start: usize, max_size: usize) -> Option<ByteString> {
-        hostcalls::get_buffer(BufferType::UpstreamData, start, max_size).unwrap()
+        self.try_get_upstream_data(start, max_size).unwrap()
+    }
+
+    fn try_get_upstream_data(&self, start: usize, max_size: usize) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::UpstreamData, start, max_size)
+    }
+
+    /// Replaces `size` bytes at offset `start` of the buffered upstream data.
+    ///
+    /// Hosts might support only replacing, appending and prepending.
+    fn set_upstream_data(&self, start: usize, size: usize, value: &[u8]) {
+        self.try_set_upstream_data(start, size, value).unwrap()
+    }
+
+    fn try_set_upstream_data(&self, start: usize, size: usize, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::UpstreamData, start, size, value)
+    }
+
+    /// Replaces the whole buffered upstream data.
+    fn replace_upstream_data(&self, value: &[u8]) {
+        self.try_replace_upstream_data(value).unwrap()
+    }
+
+    fn try_replace_upstream_data(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::UpstreamData, 0, usize::MAX, value)
+    }
+
+    /// Appends bytes to the buffered upstream data.
+    fn append_upstream_data(&self, value: &[u8]) {
+        self.try_append_upstream_data(value).unwrap()
+    }
+
+    fn try_append_upstream_data(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::UpstreamData, usize::MAX, 0, value)
+    }
+
+    /// Prepends bytes to the buffered upstream data.
+    fn prepend_upstream_data(&self, value: &[u8]) {
+        self.try_prepend_upstream_data(value).unwrap()
+    }
+
+    fn try_prepend_upstream_data(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::UpstreamData, 0, 0, value)
     }
 
     fn on_upstream_close(&mut self, _peer_type: PeerType) {}
 
+    /// Closes the downstream connection.
+    ///
+    /// Downstream data callbacks are no longer called afterwards.
+    fn close_downstream(&self) {
+        self.try_close_downstream().unwrap()
+    }
+
+    fn try_close_downstream(&self) -> Result<()> {
+        hostcalls::close_stream(StreamType::Downstream)
+    }
+
+    /// Closes the upstream connection.
+    ///
+    /// Upstream data callbacks are no longer called afterwards.
+    fn close_upstream(&self) {
+        self.try_close_upstream().unwrap()
+    }
+
+    fn try_close_upstream(&self) -> Result<()> {
+        hostcalls::close_stream(StreamType::Upstream)
+    }
+
     fn on_log(&mut self) {}
 }
 
+/// Callbacks of a context that handles an HTTP stream.
+///
+/// Callbacks that return an [`Action`] have fallible `try_*` variants, which behave
+/// like those of [`StreamContext`].
+///
+/// [`Action`]: ../types/enum.Action.html
+/// [`StreamContext`]: trait.StreamContext.html
 pub trait HttpContext: Context {
+    /// On ABI 0.1.0, which doesn't signal the end of stream with headers, `end_of_stream`
+    /// is always `false`.
     fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
         Action::Continue
     }
 
+    /// Fallible variant of `on_http_request_headers`, which it calls by default.
+    fn try_on_http_request_headers(
+        &mut self,
+        num_headers: usize,
+        end_of_stream: bool,
+    ) -> Result<Action> {
+        Ok(self.on_http_request_headers(num_headers, end_of_stream))
+    }
+
     fn get_http_request_headers(&self) -> Vec<(ByteString, ByteString)> {
-        hostcalls::get_map(MapType::HttpRequestHeaders).unwrap()
+        self.try_get_http_request_headers().unwrap()
+    }
+
+    fn try_get_http_request_headers(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::HttpRequestHeaders)
+    }
+
+    /// Returns the HTTP request headers as [`http::request::Parts`].
+    ///
+    /// [`http::request::Parts`]: https://docs.rs/http/*/http/request/struct.Parts.html
+    #[cfg(feature = "http-compat")]
+    fn get_http_request_parts(&self) -> http::request::Parts {
+        self.try_get_http_request_parts().unwrap()
+    }
+
+    #[cfg(feature = "http-compat")]
+    fn try_get_http_request_parts(&self) -> Result<http::request::Parts> {
+        crate::http_compat::get_request_parts()
+    }
+
+    /// Returns the HTTP request headers as a [`HeaderMap`].
+    ///
+    /// [`HeaderMap`]: ../types/struct.HeaderMap.html
+    fn get_http_request_header_map(&self) -> HeaderMap {
+        self.try_get_http_request_header_map().unwrap()
+    }
+
+    fn try_get_http_request_header_map(&self) -> Result<HeaderMap> {
+        HeaderMap::load(MapType::HttpRequestHeaders)
     }
 
     fn set_http_request_headers(&self, headers: Vec<(&str, &str)>) {
-        hostcalls::set_map(MapType::HttpRequestHeaders, &headers).unwrap()
+        self.try_set_http_request_headers(headers).unwrap()
+    }
+
+    fn try_set_http_request_headers(&self, headers: Vec<(&str, &str)>) -> Result<()> {
+        hostcalls::set_map(MapType::HttpRequestHeaders, &headers)
     }
 
     fn get_http_request_header(&self, name: &str) -> Option<ByteString> {
-        hostcalls::get_map_value(MapType::HttpRequestHeaders, &name).unwrap()
+        self.try_get_http_request_header(name).unwrap()
+    }
+
+    fn try_get_http_request_header(&self, name: &str) -> Result<Option<ByteString>> {
+        hostcalls::get_map_value(MapType::HttpRequestHeaders, name)
     }
 
     fn set_http_request_header(&self, name: &str, value: Option<&str>) {
-        hostcalls::set_map_value(MapType::HttpRequestHeaders, &name, value).unwrap()
+        self.try_set_http_request_header(name, value).unwrap()
+    }
+
+    fn try_set_http_request_header(&self, name: &str, value: Option<&str>) -> Result<()> {
+        hostcalls::set_map_value(MapType::HttpRequestHeaders, name, value)
     }
 
     fn add_http_request_header(&self, name: &str, value: &str) {
-        hostcalls::add_map_value(MapType::HttpRequestHeaders, &name, value).unwrap()
+        self.try_add_http_request_header(name, value).unwrap()
+    }
+
+    fn try_add_http_request_header(&self, name: &str, value: &str) -> Result<()> {
+        hostcalls::add_map_value(MapType::HttpRequestHeaders, name, value)
     }
 
     fn on_http_request_body(&mut self, _body_size: usize, _end_of_stream: bool) -> Action {
         Action::Continue
     }
 
+    /// Fallible variant of `on_http_request_body`, which it calls by default.
+    fn try_on_http_request_body(
+        &mut self,
+        body_size: usize,
+        end_of_stream: bool,
+    ) -> Result<Action> {
+        Ok(self.on_http_request_body(body_size, end_of_stream))
+    }
+
+    /// Maximum size of the HTTP request body buffered for `on_http_request_body_complete`.
+    ///
+    /// Buffering is disabled by default. When enabled, `on_http_request_body` is not
+    /// called, and a request body exceeding the limit is rejected with a local
+    /// response with `http_request_body_limit_status`.
+    fn http_request_body_limit(&self) -> Option<usize> {
+        None
+    }
+
+    /// Status code of the local response rejecting a request body that exceeds
+    /// `http_request_body_limit`, by default 413 (Payload Too Large).
+    fn http_request_body_limit_status(&self) -> u32 {
+        413
+    }
+
+    /// Called with the whole HTTP request body, if buffering is enabled by
+    /// `http_request_body_limit`, at the end of the stream, i.e. before any trailers,
+    /// or with an empty body right after `on_http_request_headers` if they end the stream,
+    /// which hosts only signal on ABI 0.2.1.
+    ///
+    /// The buffered body can be rewritten with `replace_http_request_body`.
+    fn on_http_request_body_complete(&mut self, _body: &[u8]) -> Action {
+        Action::Continue
+    }
+
+    /// Fallible variant of `on_http_request_body_complete`, which it calls by default.
+    fn try_on_http_request_body_complete(&mut self, body: &[u8]) -> Result<Action> {
+        Ok(self.on_http_request_body_complete(body))
+    }
+
+    /// Transformer applied to each chunk of the HTTP request body instead of calling
+    /// `on_http_request_body`.
+    ///
+    /// A transformer present after `on_http_request_headers` also removes the
+    /// `content-length` header, since transformed chunks can change in size.
+    /// It is ignored while `http_request_body_limit` is set.
+    fn http_request_body_transformer(&mut self) -> Option<&mut dyn BodyTransformer> {
+        None
+    }
+
     fn get_http_request_body(&self, start: usize, max_size: usize) -> Option<ByteString> {
-        hostcalls::get_buffer(BufferType::HttpRequestBody, start, max_size).unwrap()
+        self.try_get_http_request_body(start, max_size).unwrap()
+    }
+
+    fn try_get_http_request_body(
+        &self,
+        start: usize,
+        max_size: usize,
+    ) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::HttpRequestBody, start, max_size)
+    }
+
+    /// Replaces `size` bytes at offset `start` of the buffered HTTP request body.
+    ///
+    /// Hosts might support only replacing, appending and prepending.
+    fn set_http_request_body(&self, start: usize, size: usize, value: &[u8]) {
+        self.try_set_http_request_body(start, size, value).unwrap()
+    }
+
+    fn try_set_http_request_body(&self, start: usize, size: usize, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpRequestBody, start, size, value)
+    }
+
+    /// Replaces the whole buffered HTTP request body.
+    fn replace_http_request_body(&self, value: &[u8]) {
+        self.try_replace_http_request_body(value).unwrap()
+    }
+
+    fn try_replace_http_request_body(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpRequestBody, 0, usize::MAX, value)
+    }
+
+    /// Appends bytes to the buffered HTTP request body.
+    fn append_http_request_body(&self, value: &[u8]) {
+        self.try_append_http_request_body(value).unwrap()
+    }
+
+    fn try_append_http_request_body(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpRequestBody, usize::MAX, 0, value)
+    }
+
+    /// Prepends bytes to the buffered HTTP request body.
+    fn prepend_http_request_body(&self, value: &[u8]) {
+        self.try_prepend_http_request_body(value).unwrap()
+    }
+
+    fn try_prepend_http_request_body(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpRequestBody, 0, 0, value)
     }
 
     fn on_http_request_trailers(&mut self, _num_trailers: usize) -> Action {
         Action::Continue
     }
 
+    /// Fallible variant of `on_http_request_trailers`, which it calls by default.
+    fn try_on_http_request_trailers(&mut self, num_trailers: usize) -> Result<Action> {
+        Ok(self.on_http_request_trailers(num_trailers))
+    }
+
     fn get_http_request_trailers(&self) -> Vec<(ByteString, ByteString)> {
-        hostcalls::get_map(MapType::HttpRequestTrailers).unwrap()
+        self.try_get_http_request_trailers().unwrap()
+    }
+
+    fn try_get_http_request_trailers(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::HttpRequestTrailers)
+    }
+
+    /// Returns the HTTP request trailers as a [`HeaderMap`].
+    ///
+    /// [`HeaderMap`]: ../types/struct.HeaderMap.html
+    fn get_http_request_trailer_map(&self) -> HeaderMap {
+        self.try_get_http_request_trailer_map().unwrap()
+    }
+
+    fn try_get_http_request_trailer_map(&self) -> Result<HeaderMap> {
+        HeaderMap::load(MapType::HttpRequestTrailers)
     }
 
     fn set_http_request_trailers(&self, trailers: Vec<(&str, &str)>) {
-        hostcalls::set_map(MapType::HttpRequestTrailers, &trailers).unwrap()
+        self.try_set_http_request_trailers(trailers).unwrap()
+    }
+
+    fn try_set_http_request_trailers(&self, trailers: Vec<(&str, &str)>) -> Result<()> {
+        hostcalls::set_map(MapType::HttpRequestTrailers, &trailers)
     }
 
     fn get_http_request_trailer(&self, name: &str) -> Option<ByteString> {
-        hostcalls::get_map_value(MapType::HttpRequestTrailers, &name).unwrap()
+        self.try_get_http_request_trailer(name).unwrap()
+    }
+
+    fn try_get_http_request_trailer(&self, name: &str) -> Result<Option<ByteString>> {
+        hostcalls::get_map_value(MapType::HttpRequestTrailers, name)
     }
 
     fn set_http_request_trailer(&self, name: &str, value: Option<&str>) {
-        hostcalls::set_map_value(MapType::HttpRequestTrailers, &name, value).unwrap()
+        self.try_set_http_request_trailer(name, value).unwrap()
+    }
+
+    fn try_set_http_request_trailer(&self, name: &str, value: Option<&str>) -> Result<()> {
+        hostcalls::set_map_value(MapType::HttpRequestTrailers, name, value)
     }
 
     fn add_http_request_trailer(&self, name: &str, value: &str) {
-        hostcalls::add_map_value(MapType::HttpRequestTrailers, &name, value).unwrap()
+        self.try_add_http_request_trailer(name, value).unwrap()
+    }
+
+    fn try_add_http_request_trailer(&self, name: &str, value: &str) -> Result<()> {
+        hostcalls::add_map_value(MapType::HttpRequestTrailers, name, value)
     }
 
     fn resume_http_request(&self) {
-        hostcalls::continue_stream(StreamType::Request).unwrap()
+        self.try_resume_http_request().unwrap()
     }
 
+    fn try_resume_http_request(&self) -> Result<()> {
+        hostcalls::continue_stream(StreamType::Request)
+    }
+
+    /// Terminates processing of the HTTP request.
+    ///
+    /// Request callbacks are no longer called afterwards.
+    fn close_http_request(&self) {
+        self.try_close_http_request().unwrap()
+    }
+
+    fn try_close_http_request(&self) -> Result<()> {
+        hostcalls::close_stream(StreamType::Request)
+    }
+
+    /// On ABI 0.1.0, which doesn't signal the end of stream with headers, `end_of_stream`
+    /// is always `false`.
     fn on_http_response_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
         Action::Continue
     }
 
+    /// Fallible variant of `on_http_response_headers`, which it calls by default.
+    fn try_on_http_response_headers(
+        &mut self,
+        num_headers: usize,
+        end_of_stream: bool,
+    ) -> Result<Action> {
+        Ok(self.on_http_response_headers(num_headers, end_of_stream))
+    }
+
     fn get_http_response_headers(&self) -> Vec<(ByteString, ByteString)> {
-        hostcalls::get_map(MapType::HttpResponseHeaders).unwrap()
+        self.try_get_http_response_headers().unwrap()
+    }
+
+    fn try_get_http_response_headers(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::HttpResponseHeaders)
+    }
+
+    /// Returns the HTTP response headers as [`http::response::Parts`].
+    ///
+    /// [`http::response::Parts`]: https://docs.rs/http/*/http/response/struct.Parts.html
+    #[cfg(feature = "http-compat")]
+    fn get_http_response_parts(&self) -> http::response::Parts {
+        self.try_get_http_response_parts().unwrap()
+    }
+
+    #[cfg(feature = "http-compat")]
+    fn try_get_http_response_parts(&self) -> Result<http::response::Parts> {
+        crate::http_compat::get_response_parts()
+    }
+
+    /// Returns the HTTP response headers as a [`HeaderMap`].
+    ///
+    /// [`HeaderMap`]: ../types/struct.HeaderMap.html
+    fn get_http_response_header_map(&self) -> HeaderMap {
+        self.try_get_http_response_header_map().unwrap()
+    }
+
+    fn try_get_http_response_header_map(&self) -> Result<HeaderMap> {
+        HeaderMap::load(MapType::HttpResponseHeaders)
     }
 
     fn set_http_response_headers(&self, headers: Vec<(&str, &str)>) {
-        hostcalls::set_map(MapType::HttpResponseHeaders, &headers).unwrap()
+        self.try_set_http_response_headers(headers).unwrap()
+    }
+
+    fn try_set_http_response_headers(&self, headers: Vec<(&str, &str)>) -> Result<()> {
+        hostcalls::set_map(MapType::HttpResponseHeaders, &headers)
     }
 
     fn get_http_response_header(&self, name: &str) -> Option<ByteString> {
-        hostcalls::get_map_value(MapType::HttpResponseHeaders, &name).unwrap()
+        self.try_get_http_response_header(name).unwrap()
+    }
+
+    fn try_get_http_response_header(&self, name: &str) -> Result<Option<ByteString>> {
+        hostcalls::get_map_value(MapType::HttpResponseHeaders, name)
     }
 
     fn set_http_response_header(&self, name: &str, value: Option<&str>) {
-        hostcalls::set_map_value(MapType::HttpResponseHeaders, &name, value).unwrap()
+        self.try_set_http_response_header(name, value).unwrap()
+    }
+
+    fn try_set_http_response_header(&self, name: &str, value: Option<&str>) -> Result<()> {
+        hostcalls::set_map_value(MapType::HttpResponseHeaders, name, value)
     }
 
     fn add_http_response_header(&self, name: &str, value: &str) {
-        hostcalls::add_map_value(MapType::HttpResponseHeaders, &name, value).unwrap()
+        self.try_add_http_response_header(name, value).unwrap()
+    }
+
+    fn try_add_http_response_header(&self, name: &str, value: &str) -> Result<()> {
+        hostcalls::add_map_value(MapType::HttpResponseHeaders, name, value)
     }
 
     fn on_http_response_body(&mut self, _body_size: usize, _end_of_stream: bool) -> Action {
         Action::Continue
     }
 
+    /// Fallible variant of `on_http_response_body`, which it calls by default.
+    fn try_on_http_response_body(
+        &mut self,
+        body_size: usize,
+        end_of_stream: bool,
+    ) -> Result<Action> {
+        Ok(self.on_http_response_body(body_size, end_of_stream))
+    }
+
+    /// Maximum size of the HTTP response body buffered for `on_http_response_body_complete`.
+    ///
+    /// Buffering is disabled by default. When enabled, `on_http_response_body` is not
+    /// called, and a response body exceeding the limit is replaced with a local
+    /// response with `http_response_body_limit_status`. Hosts can only do so while
+    /// the response headers have not been sent downstream, so `on_http_response_headers`
+    /// should return `Action::Pause` for responses that might exceed the limit; otherwise,
+    /// hosts typically reset the stream instead.
+    fn http_response_body_limit(&self) -> Option<usize> {
+        None
+    }
+
+    /// Status code of the local response replacing a response body that exceeds
+    /// `http_response_body_limit`, by default 500 (Internal Server Error).
+    fn http_response_body_limit_status(&self) -> u32 {
+        500
+    }
+
+    /// Called with the whole HTTP response body, if buffering is enabled by
+    /// `http_response_body_limit`, at the end of the stream, i.e. before any trailers,
+    /// or with an empty body right after `on_http_response_headers` if they end the stream,
+    /// which hosts only signal on ABI 0.2.1.
+    ///
+    /// The buffered body can be rewritten with `replace_http_response_body`.
+    fn on_http_response_body_complete(&mut self, _body: &[u8]) -> Action {
+        Action::Continue
+    }
+
+    /// Fallible variant of `on_http_response_body_complete`, which it calls by default.
+    fn try_on_http_response_body_complete(&mut self, body: &[u8]) -> Result<Action> {
+        Ok(self.on_http_response_body_complete(body))
+    }
+
+    /// Transformer applied to each chunk of the HTTP response body instead of calling
+    /// `on_http_response_body`.
+    ///
+    /// A transformer present after `on_http_response_headers` also removes the
+    /// `content-length` header, since transformed chunks can change in size.
+    /// It is ignored while `http_response_body_limit` is set.
+    fn http_response_body_transformer(&mut self) -> Option<&mut dyn BodyTransformer> {
+        None
+    }
+
     fn get_http_response_body(&self, start: usize, max_size: usize) -> Option<ByteString> {
-        hostcalls::get_buffer(BufferType::HttpResponseBody, start, max_size).unwrap()
+        self.try_get_http_response_body(start, max_size).unwrap()
+    }
+
+    fn try_get_http_response_body(
+        &self,
+        start: usize,
+        max_size: usize,
+    ) -> Result<Option<ByteString>> {
+        hostcalls::get_buffer(BufferType::HttpResponseBody, start, max_size)
+    }
+
+    /// Replaces `size` bytes at offset `start` of the buffered HTTP response body.
+    ///
+    /// Hosts might support only replacing, appending and prepending.
+    fn set_http_response_body(&self, start: usize, size: usize, value: &[u8]) {
+        self.try_set_http_response_body(start, size, value).unwrap()
+    }
+
+    fn try_set_http_response_body(&self, start: usize, size: usize, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpResponseBody, start, size, value)
+    }
+
+    /// Replaces the whole buffered HTTP response body.
+    fn replace_http_response_body(&self, value: &[u8]) {
+        self.try_replace_http_response_body(value).unwrap()
+    }
+
+    fn try_replace_http_response_body(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpResponseBody, 0, usize::MAX, value)
+    }
+
+    /// Appends bytes to the buffered HTTP response body.
+    fn append_http_response_body(&self, value: &[u8]) {
+        self.try_append_http_response_body(value).unwrap()
+    }
+
+    fn try_append_http_response_body(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpResponseBody, usize::MAX, 0, value)
+    }
+
+    /// Prepends bytes to the buffered HTTP response body.
+    fn prepend_http_response_body(&self, value: &[u8]) {
+        self.try_prepend_http_response_body(value).unwrap()
+    }
+
+    fn try_prepend_http_response_body(&self, value: &[u8]) -> Result<()> {
+        hostcalls::set_buffer(BufferType::HttpResponseBody, 0, 0, value)
     }
 
     fn on_http_response_trailers(&mut self, _num_trailers: usize) -> Action {
         Action::Continue
     }
 
+    /// Fallible variant of `on_http_response_trailers`, which it calls by default.
+    fn try_on_http_response_trailers(&mut self, num_trailers: usize) -> Result<Action> {
+        Ok(self.on_http_response_trailers(num_trailers))
+    }
+
     fn get_http_response_trailers(&self) -> Vec<(ByteString, ByteString)> {
-        hostcalls::get_map(MapType::HttpResponseTrailers).unwrap()
+        self.try_get_http_response_trailers().unwrap()
+    }
+
+    fn try_get_http_response_trailers(&self) -> Result<Vec<(ByteString, ByteString)>> {
+        hostcalls::get_map(MapType::HttpResponseTrailers)
+    }
+
+    /// Returns the HTTP response trailers as a [`HeaderMap`].
+    ///
+    /// [`HeaderMap`]: ../types/struct.HeaderMap.html
+    fn get_http_response_trailer_map(&self) -> HeaderMap {
+        self.try_get_http_response_trailer_map().unwrap()
+    }
+
+    fn try_get_http_response_trailer_map(&self) -> Result<HeaderMap> {
+        HeaderMap::load(MapType::HttpResponseTrailers)
     }
 
     fn set_http_response_trailers(&self, headers: Vec<(&str, &str)>) {
-        hostcalls::set_map(MapType::HttpResponseTrailers, &headers).unwrap()
+        self.try_set_http_response_trailers(headers).unwrap()
+    }
+
+    fn try_set_http_response_trailers(&self, headers: Vec<(&str, &str)>) -> Result<()> {
+        hostcalls::set_map(MapType::HttpResponseTrailers, &headers)
     }
 
     fn get_http_response_trailer(&self, name: &str) -> Option<ByteString> {
-        hostcalls::get_map_value(MapType::HttpResponseTrailers, &name).unwrap()
+        self.try_get_http_response_trailer(name).unwrap()
+    }
+
+    fn try_get_http_response_trailer(&self, name: &str) -> Result<Option<ByteString>> {
+        hostcalls::get_map_value(MapType::HttpResponseTrailers, name)
     }
 
     fn set_http_response_trailer(&self, name: &str, value: Option<&str>) {
-        hostcalls::set_map_value(MapType::HttpResponseTrailers, &name, value).unwrap()
+        self.try_set_http_response_trailer(name, value).unwrap()
+    }
+
+    fn try_set_http_response_trailer(&self, name: &str, value: Option<&str>) -> Result<()> {
+        hostcalls::set_map_value(MapType::HttpResponseTrailers, name, value)
     }
 
     fn add_http_response_trailer(&self, name: &str, value: &str) {
-        hostcalls::add_map_value(MapType::HttpResponseTrailers, &name, value).unwrap()
+        self.try_add_http_response_trailer(name, value).unwrap()
+    }
+
+    fn try_add_http_response_trailer(&self, name: &str, value: &str) -> Result<()> {
+        hostcalls::add_map_value(MapType::HttpResponseTrailers, name, value)
     }
 
     fn resume_http_response(&self) {
-        hostcalls::continue_stream(StreamType::Response).unwrap()
+        self.try_resume_http_response().unwrap()
+    }
+
+    fn try_resume_http_response(&self) -> Result<()> {
+        hostcalls::continue_stream(StreamType::Response)
+    }
+
+    /// Terminates processing of the HTTP response.
+    ///
+    /// Response callbacks are no longer called afterwards.
+    fn close_http_response(&self) {
+        self.try_close_http_response().unwrap()
+    }
+
+    fn try_close_http_response(&self) -> Result<()> {
+        hostcalls::close_stream(StreamType::Response)
+    }
+
+    /// Terminates processing of both the HTTP request and the HTTP response.
+    ///
+    /// The ABI cannot pass a reason to the host, so a given reason is only logged
+    /// at [`LogLevel::Info`].
+    ///
+    /// [`LogLevel::Info`]: ../types/enum.LogLevel.html#variant.Info
+    fn reset_http_stream(&self, reason: &str) {
+        self.try_reset_http_stream(reason).unwrap()
+    }
+
+    fn try_reset_http_stream(&self, reason: &str) -> Result<()> {
+        hostcalls::log(
+            LogLevel::Info,
+            &format!("resetting HTTP stream: {}", reason),
+        )?;
+        hostcalls::close_stream(StreamType::Request)?;
+        hostcalls::close_stream(StreamType::Response)
     }
 
     fn send_http_response(
@@ -283,8 +1100,39 @@ pub trait HttpContext: Context {
         headers: Vec<(&str, &str)>,
         body: Option<&[u8]>,
     ) {
-        hostcalls::send_http_response(status_code, &headers, body).unwrap()
+        self.try_send_http_response(status_code, headers, body)
+            .unwrap()
+    }
+
+    fn try_send_http_response(
+        &self,
+        status_code: u32,
+        headers: Vec<(&str, &str)>,
+        body: Option<&[u8]>,
+    ) -> Result<()> {
+        hostcalls::send_http_response(status_code, &headers, body)
     }
 
     fn on_log(&mut self) {}
 }
+
+/// Rewrites a streamed body chunk by chunk, e.g. to find and replace text.
+///
+/// Data that might be needed to transform the following chunks, e.g. a partial
+/// match, can be held back and returned later, at the latest at the end of the stream.
+/// If trailers end the stream, the transformer is called with an empty chunk instead,
+/// and its output is appended to the body. Hosts that have already forwarded the body
+/// by then, including Envoy, have no body to append to, so that output is dropped with
+/// a warning; transformers that must not lose data should hold back as little as possible.
+pub trait BodyTransformer {
+    fn transform(&mut self, chunk: &[u8], end_of_stream: bool) -> Vec<u8>;
+}
+
+impl<F> BodyTransformer for F
+where
+    F: FnMut(&[u8], bool) -> Vec<u8>,
+{
+    fn transform(&mut self, chunk: &[u8], end_of_stream: bool) -> Vec<u8> {
+        self(chunk, end_of_stream)
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::error::Result;
+use crate::hostcalls;
 use crate::traits::*;
 
 pub use crate::bytestring::ByteString;
@@ -44,9 +46,16 @@ pub enum Status {
     Ok = 0,
     NotFound = 1,
     BadArgument = 2,
+    SerializationFailure = 3,
+    ParseFailure = 4,
+    BadExpression = 5,
+    InvalidMemoryAccess = 6,
     Empty = 7,
     CasMismatch = 8,
+    ResultMismatch = 9,
     InternalFailure = 10,
+    BrokenConnection = 11,
+    Unimplemented = 12,
 }
 
 #[repr(u32)]
@@ -89,4 +98,251 @@ pub enum PeerType {
 pub enum StreamType {
     Request = 0,
     Response = 1,
+    Downstream = 2,
+    Upstream = 3,
+}
+
+/// Determines how the dispatcher handles errors returned from callbacks.
+///
+/// Errors are always logged at [`LogLevel::Error`].
+///
+/// [`LogLevel::Error`]: enum.LogLevel.html#variant.Error
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum ErrorPolicy {
+    /// Continues processing of the stream, as if the callback returned `Action::Continue`.
+    FailOpen,
+    /// Rejects the stream, i.e. sends `500 Internal Server Error` local response
+    /// to HTTP streams and stops processing of TCP streams.
+    #[default]
+    FailClosed,
+}
+
+/// Determines how the dispatcher handles a panic in a callback of a stream context.
+///
+/// Panics can only be isolated in builds with `panic = "unwind"`, otherwise
+/// every panic aborts the VM regardless of the policy. This includes every
+/// `wasm32-unknown-unknown` build, which cannot unwind, as well as builds with
+/// `panic = "abort"` in the profile, so in practice the policy only takes effect
+/// in native builds, e.g. in tests against the mock host.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum PanicPolicy {
+    /// Lets the panic propagate to the host.
+    #[default]
+    Propagate,
+    /// Fails the context and sends a local response with a given status code to HTTP
+    /// streams; TCP streams are closed.
+    SendLocalResponse(u32),
+    /// Fails the context and closes its stream.
+    CloseStream,
+}
+
+/// Determines how messages logged with the [`log`](https://docs.rs/log) crate are formatted.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum LogFormat {
+    /// Logs the message only.
+    #[default]
+    Plain,
+    /// Logs the active context id, the target, the message and key-value pairs
+    /// as [logfmt](https://brandur.org/logfmt), e.g.
+    /// `context_id=2 target=my_filter msg="request denied" user=alice`.
+    Logfmt,
+    /// Logs the same fields as a JSON object, e.g.
+    /// `{"context_id":2,"target":"my_filter","msg":"request denied","user":"alice"}`.
+    Json,
+}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MetricType {
+    Counter = 0,
+    Gauge = 1,
+    Histogram = 2,
+}
+
+/// Headers or trailers of an HTTP message, e.g. of the HTTP request.
+///
+/// Names are compared case-insensitively, and the order of entries is preserved.
+/// Changes are only visible to the host after [`flush`].
+///
+/// [`flush`]: #method.flush
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderMap {
+    map_type: MapType,
+    entries: Vec<(ByteString, ByteString)>,
+}
+
+impl HeaderMap {
+    /// Creates an empty map of a given type.
+    pub fn new(map_type: MapType) -> HeaderMap {
+        HeaderMap {
+            map_type,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Reads a map of a given type from the host.
+    pub fn load(map_type: MapType) -> Result<HeaderMap> {
+        Ok(HeaderMap {
+            map_type,
+            entries: hostcalls::get_map(map_type)?,
+        })
+    }
+
+    /// Writes the map back to the host, replacing all of its entries.
+    pub fn flush(&self) -> Result<()> {
+        hostcalls::set_map(self.map_type, &self.entries)
+    }
+
+    pub fn map_type(&self) -> MapType {
+        self.map_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key<K>(&self, name: K) -> bool
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get(name).is_some()
+    }
+
+    /// Returns the first value of a given header.
+    pub fn get<K>(&self, name: K) -> Option<&ByteString>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.get_all(name).next()
+    }
+
+    /// Returns all values of a given header, in order.
+    pub fn get_all<K>(&self, name: K) -> impl Iterator<Item = &ByteString>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name.as_ref()))
+            .map(|(_, value)| value)
+    }
+
+    /// Sets a header to a single value, replacing its previous values in place.
+    pub fn insert<K, V>(&mut self, name: K, value: V)
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let name = name.as_ref();
+        let mut found = false;
+        // Keeps only the first entry of the header.
+        self.entries.retain(|(key, _)| {
+            !key.eq_ignore_ascii_case(name) || !std::mem::replace(&mut found, true)
+        });
+        match self
+            .entries
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            Some((_, entry)) => *entry = value.as_ref().into(),
+            None => self.append(name, value),
+        }
+    }
+
+    /// Adds a value to a header, keeping its previous values.
+    pub fn append<K, V>(&mut self, name: K, value: V)
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.entries
+            .push((name.as_ref().into(), value.as_ref().into()));
+    }
+
+    /// Removes all values of a given header, returning the first one.
+    pub fn remove<K>(&mut self, name: K) -> Option<ByteString>
+    where
+        K: AsRef<[u8]>,
+    {
+        let name = name.as_ref();
+        let mut removed = None;
+        self.entries.retain(|(key, value)| {
+            if !key.eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(value.clone());
+            }
+            false
+        });
+        removed
+    }
+
+    /// Iterates over all entries, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&ByteString, &ByteString)> {
+        self.entries.iter().map(|(name, value)| (name, value))
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (ByteString, ByteString);
+    type IntoIter = std::vec::IntoIter<(ByteString, ByteString)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new(MapType::HttpRequestHeaders);
+        headers.append(":path", "/");
+        headers.append("Set-Cookie", "a=1");
+        headers.append("x-request-id", "42");
+        headers.append("set-cookie", "b=2");
+        headers
+    }
+
+    #[test]
+    fn test_header_map_get() {
+        let headers = headers();
+        assert_eq!(headers.get("SET-COOKIE").unwrap(), "a=1");
+        assert_eq!(
+            headers.get_all("set-cookie").collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+        assert!(headers.contains_key("X-Request-Id"));
+        assert!(headers.get("content-length").is_none());
+    }
+
+    #[test]
+    fn test_header_map_insert() {
+        let mut headers = headers();
+        headers.insert("set-cookie", "c=3");
+        headers.insert("content-length", "0");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![
+                (&":path".into(), &"/".into()),
+                (&"Set-Cookie".into(), &"c=3".into()),
+                (&"x-request-id".into(), &"42".into()),
+                (&"content-length".into(), &"0".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_header_map_remove() {
+        let mut headers = headers();
+        assert_eq!(headers.remove("set-cookie").unwrap(), "a=1");
+        assert_eq!(headers.remove("set-cookie"), None);
+        assert_eq!(headers.len(), 2);
+    }
 }
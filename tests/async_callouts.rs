@@ -0,0 +1,130 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::hostcalls;
+use proxy_wasm::task;
+use proxy_wasm::testing::HttpFilterTest;
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::time::Duration;
+
+struct TwoStepAuth;
+
+impl Context for TwoStepAuth {}
+
+impl HttpContext for TwoStepAuth {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        let session = self
+            .dispatch_http_call_async(
+                "session",
+                vec![(":method", "GET"), (":path", "/session")],
+                None,
+                vec![],
+                Duration::from_secs(1),
+            )
+            .unwrap();
+        task::spawn_local(async move {
+            let user = match session.await {
+                Ok(session) if session.status_code() == Some(200) => session.body,
+                _ => None,
+            };
+            let user = match user {
+                Some(user) => user.to_string(),
+                None => {
+                    hostcalls::send_http_response(401, hostcalls::NO_HEADERS, hostcalls::NO_BODY)
+                        .unwrap();
+                    return;
+                }
+            };
+            let path = format!("/users/{}", user);
+            let permissions = task::dispatch_http_call_async(
+                "permissions",
+                &[(":method", "GET"), (":path", path.as_str())],
+                hostcalls::NO_BODY,
+                hostcalls::NO_TRAILERS,
+                Duration::from_secs(1),
+            )
+            .unwrap()
+            .await;
+            if permissions.ok().and_then(|response| response.status_code()) == Some(200) {
+                hostcalls::set_map_value(MapType::HttpRequestHeaders, "x-user", Some(&user))
+                    .unwrap();
+                hostcalls::continue_stream(StreamType::Request).unwrap();
+            } else {
+                hostcalls::send_http_response(403, hostcalls::NO_HEADERS, hostcalls::NO_BODY)
+                    .unwrap();
+            }
+        });
+        Action::Pause
+    }
+}
+
+fn request() -> HttpFilterTest {
+    HttpFilterTest::with_http_context(|_, _| TwoStepAuth)
+        .request_headers(&[(":path", "/")], true)
+        .expect_action(Action::Pause)
+        .expect_http_call("session")
+}
+
+#[test]
+fn test_sequential_callouts() {
+    request()
+        .http_call_response(&[(":status", "200")], Some(b"alice"), &[])
+        .expect_http_call("permissions")
+        .http_call_response(&[(":status", "200")], None, &[])
+        .expect_resumed(StreamType::Request)
+        .expect_request_header("x-user", Some("alice"))
+        .expect_no_local_response()
+        .complete();
+}
+
+#[test]
+fn test_early_return() {
+    request()
+        .http_call_response(&[(":status", "404")], None, &[])
+        .expect_no_http_call()
+        .expect_local_response(401)
+        .complete();
+}
+
+#[test]
+fn test_pending_task_dropped_with_context() {
+    request()
+        .http_call_response(&[(":status", "200")], Some(b"bob"), &[])
+        .expect_http_call("permissions")
+        .complete();
+}
+
+#[test]
+fn test_response_error() {
+    request()
+        .inspect(|host| host.fail_next("proxy_get_header_map_pairs", Status::InternalFailure))
+        .http_call_response(&[(":status", "200")], Some(b"alice"), &[])
+        .expect_no_http_call()
+        .expect_local_response(401)
+        .complete();
+}
+
+#[test]
+fn test_response_after_context_deleted() {
+    request()
+        .complete()
+        .http_call_response(&[(":status", "200")], Some(b"alice"), &[])
+        .expect_no_http_call()
+        .expect_no_local_response();
+}
@@ -0,0 +1,88 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::attributes;
+use proxy_wasm::testing;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn set_property(path: &[&str], value: &[u8]) {
+    testing::with_host(|host| host.set_property(path, Some(value)));
+}
+
+#[test]
+fn test_strings() {
+    set_property(&["request", "path"], b"/items?id=1");
+    set_property(&["plugin_name"], b"auth");
+    assert_eq!(
+        attributes::request_path().unwrap().as_deref(),
+        Some("/items?id=1")
+    );
+    assert_eq!(attributes::plugin_name().unwrap().as_deref(), Some("auth"));
+    assert_eq!(attributes::upstream_cluster().unwrap(), None);
+}
+
+#[test]
+fn test_integers() {
+    set_property(&["response", "code"], &403i64.to_le_bytes());
+    set_property(&["source", "port"], &(-1i64).to_le_bytes());
+    set_property(&["request", "size"], b"1024");
+    assert_eq!(attributes::response_code().unwrap(), Some(403));
+    assert_eq!(
+        attributes::source_port().unwrap_err().to_string(),
+        "attribute \"source.port\" is not a port"
+    );
+    assert_eq!(
+        attributes::request_size().unwrap_err().to_string(),
+        "attribute \"request.size\" is not an integer"
+    );
+}
+
+#[test]
+fn test_time() {
+    set_property(
+        &["request", "time"],
+        &1_600_000_000_123_000_000i64.to_le_bytes(),
+    );
+    set_property(&["request", "duration"], &2_500_000i64.to_le_bytes());
+    assert_eq!(
+        attributes::request_time().unwrap(),
+        Some(UNIX_EPOCH + Duration::from_nanos(1_600_000_000_123_000_000))
+    );
+    assert_eq!(
+        attributes::request_duration().unwrap(),
+        Some(Duration::from_micros(2500))
+    );
+}
+
+#[test]
+fn test_connection() {
+    set_property(&["source", "address"], b"10.0.0.1:51234");
+    set_property(&["connection", "mtls"], &[1]);
+    assert_eq!(
+        attributes::source_address().unwrap(),
+        Some("10.0.0.1:51234".parse().unwrap())
+    );
+    assert_eq!(attributes::connection_mtls().unwrap(), Some(true));
+}
+
+#[test]
+fn test_node_id() {
+    // `Node { cluster: "c", id: "node-1" }`
+    set_property(&["node"], b"\x12\x01c\x0a\x06node-1");
+    assert_eq!(attributes::node_id().unwrap().as_deref(), Some("node-1"));
+}
@@ -0,0 +1,135 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::HttpFilterTest;
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+
+struct Reverser;
+
+impl Context for Reverser {}
+
+impl HttpContext for Reverser {
+    fn http_request_body_limit(&self) -> Option<usize> {
+        Some(16)
+    }
+
+    fn on_http_request_body(&mut self, _: usize, _: bool) -> Action {
+        panic!("body is buffered")
+    }
+
+    fn on_http_request_body_complete(&mut self, body: &[u8]) -> Action {
+        let reversed: Vec<u8> = body.iter().rev().cloned().collect();
+        self.replace_http_request_body(&reversed);
+        Action::Continue
+    }
+}
+
+struct SmallResponses;
+
+impl Context for SmallResponses {}
+
+impl HttpContext for SmallResponses {
+    fn http_response_body_limit(&self) -> Option<usize> {
+        Some(4)
+    }
+
+    fn http_response_body_limit_status(&self) -> u32 {
+        502
+    }
+}
+
+#[test]
+fn test_whole_body() {
+    // The host reports the size of the body buffered so far.
+    HttpFilterTest::with_http_context(|_, _| Reverser)
+        .request_headers(&[(":method", "POST")], false)
+        .request_body(b"hello ", false)
+        .expect_action(Action::Pause)
+        .request_body(b"hello world", true)
+        .expect_action(Action::Continue)
+        .expect_request_body(b"dlrow olleh")
+        .expect_no_local_response();
+}
+
+#[test]
+fn test_body_too_large() {
+    HttpFilterTest::with_http_context(|_, _| Reverser)
+        .request_headers(&[(":method", "POST")], false)
+        .request_body(b"0123456789abcdefg", false)
+        .expect_action(Action::Pause)
+        .expect_local_response(413);
+}
+
+#[test]
+fn test_buffering_disabled_for_responses() {
+    HttpFilterTest::with_http_context(|_, _| Reverser)
+        .response_headers(&[(":status", "200")], false)
+        .response_body(b"0123456789abcdefg", true)
+        .expect_action(Action::Continue)
+        .expect_response_body(b"0123456789abcdefg");
+}
+
+#[test]
+fn test_body_completed_by_trailers() {
+    HttpFilterTest::with_http_context(|_, _| Reverser)
+        .request_headers(&[(":method", "POST")], false)
+        .request_body(b"hello world", false)
+        .expect_action(Action::Pause)
+        .request_trailers(&[("x-checksum", "abc")])
+        .expect_action(Action::Continue)
+        .expect_request_body(b"dlrow olleh")
+        .expect_no_local_response();
+}
+
+#[test]
+fn test_limit_status() {
+    HttpFilterTest::with_http_context(|_, _| SmallResponses)
+        .response_headers(&[(":status", "200")], false)
+        .response_body(b"hello", false)
+        .expect_action(Action::Pause)
+        .expect_local_response(502);
+}
+
+// ABI 0.1.0 doesn't signal the end of stream with headers.
+#[cfg(feature = "abi-0-2-1")]
+#[test]
+fn test_body_completed_by_headers() {
+    struct RequireBody;
+
+    impl Context for RequireBody {}
+
+    impl HttpContext for RequireBody {
+        fn http_request_body_limit(&self) -> Option<usize> {
+            Some(16)
+        }
+
+        fn on_http_request_body_complete(&mut self, body: &[u8]) -> Action {
+            if body.is_empty() {
+                self.send_http_response(400, vec![], None);
+                return Action::Pause;
+            }
+            Action::Continue
+        }
+    }
+
+    HttpFilterTest::with_http_context(|_, _| RequireBody)
+        .request_headers(&[(":method", "POST")], true)
+        .expect_action(Action::Pause)
+        .expect_local_response(400);
+}
@@ -0,0 +1,64 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::HttpFilterTest;
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+
+struct Rewriter;
+
+impl Context for Rewriter {}
+
+impl HttpContext for Rewriter {
+    fn on_http_request_body(&mut self, _: usize, end_of_stream: bool) -> Action {
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        self.prepend_http_request_body(b"[");
+        self.append_http_request_body(b"]");
+        Action::Continue
+    }
+
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        let body = self.get_http_response_body(0, body_size).unwrap();
+        self.replace_http_response_body(body.to_string().to_uppercase().as_bytes());
+        self.set_http_response_body(0, 5, b"Howdy");
+        Action::Continue
+    }
+}
+
+#[test]
+fn test_append_and_prepend() {
+    HttpFilterTest::with_http_context(|_, _| Rewriter)
+        .request_headers(&[], false)
+        .request_body(b"1, 2, 3", true)
+        .expect_action(Action::Continue)
+        .expect_request_body(b"[1, 2, 3]");
+}
+
+#[test]
+fn test_replace() {
+    HttpFilterTest::with_http_context(|_, _| Rewriter)
+        .response_headers(&[(":status", "200")], false)
+        .response_body(b"hello, world", true)
+        .expect_action(Action::Continue)
+        .expect_response_body(b"Howdy, WORLD");
+}
@@ -0,0 +1,165 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+
+/// Replaces "cat" with "dog", holding back a possible partial match.
+#[derive(Default)]
+struct Replace {
+    pending: Vec<u8>,
+}
+
+impl BodyTransformer for Replace {
+    fn transform(&mut self, chunk: &[u8], end_of_stream: bool) -> Vec<u8> {
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(chunk);
+        let text = String::from_utf8(data).unwrap().replace("cat", "dog");
+        let mut output = text.into_bytes();
+        if !end_of_stream {
+            let keep = output.len().min(2);
+            self.pending = output.split_off(output.len() - keep);
+        }
+        output
+    }
+}
+
+struct Filter {
+    replace: Option<Replace>,
+}
+
+impl Context for Filter {}
+
+impl HttpContext for Filter {
+    fn on_http_response_headers(&mut self, _: usize, _: bool) -> Action {
+        let content_type = self.get_http_response_header("content-type");
+        if content_type.is_some_and(|value| value == "text/plain") {
+            self.replace = Some(Replace::default());
+        }
+        Action::Continue
+    }
+
+    fn http_response_body_transformer(&mut self) -> Option<&mut dyn BodyTransformer> {
+        self.replace
+            .as_mut()
+            .map(|replace| replace as &mut dyn BodyTransformer)
+    }
+}
+
+struct Buffered {
+    replace: Replace,
+}
+
+impl Context for Buffered {}
+
+impl HttpContext for Buffered {
+    fn http_response_body_limit(&self) -> Option<usize> {
+        Some(16)
+    }
+
+    fn http_response_body_transformer(&mut self) -> Option<&mut dyn BodyTransformer> {
+        Some(&mut self.replace)
+    }
+}
+
+#[test]
+fn test_streamed_replacement() {
+    HttpFilterTest::with_http_context(|_, _| Filter { replace: None })
+        .response_headers(
+            &[
+                (":status", "200"),
+                ("content-type", "text/plain"),
+                ("content-length", "15"),
+            ],
+            false,
+        )
+        .expect_response_header("content-length", None)
+        .response_body(b"the c", false)
+        .expect_action(Action::Continue)
+        .expect_response_body(b"the")
+        .response_body(b"at sat", false)
+        .expect_response_body(b" dog s")
+        .response_body(b"", true)
+        .expect_response_body(b"at");
+}
+
+#[test]
+fn test_without_transformer() {
+    HttpFilterTest::with_http_context(|_, _| Filter { replace: None })
+        .response_headers(
+            &[
+                (":status", "200"),
+                ("content-type", "application/json"),
+                ("content-length", "7"),
+            ],
+            false,
+        )
+        .expect_response_header("content-length", Some("7"))
+        .response_body(b"\"a cat\"", true)
+        .expect_response_body(b"\"a cat\"");
+}
+
+#[test]
+fn test_flushed_by_trailers() {
+    let test = HttpFilterTest::with_http_context(|_, _| Filter { replace: None })
+        .response_headers(&[(":status", "200"), ("content-type", "text/plain")], false)
+        .response_body(b"a ca", false)
+        .expect_response_body(b"a ");
+    // The host has forwarded the chunk by the time trailers arrive.
+    testing::with_host(|host| host.set_buffer(BufferType::HttpResponseBody, Some("")));
+    test.response_trailers(&[("x-checksum", "abc")])
+        .expect_action(Action::Continue)
+        .expect_response_body(b"ca");
+}
+
+#[test]
+fn test_ignored_with_limit() {
+    HttpFilterTest::with_http_context(|_, _| Buffered {
+        replace: Replace::default(),
+    })
+    .response_headers(
+        &[
+            (":status", "200"),
+            ("content-type", "text/plain"),
+            ("content-length", "5"),
+        ],
+        false,
+    )
+    .expect_response_header("content-length", Some("5"))
+    .response_body(b"a cat", true)
+    .expect_action(Action::Continue)
+    .expect_response_body(b"a cat");
+}
+
+#[test]
+fn test_flushed_without_body_buffer() {
+    let test = HttpFilterTest::with_http_context(|_, _| Filter { replace: None })
+        .response_headers(&[(":status", "200"), ("content-type", "text/plain")], false)
+        .response_body(b"a ca", false)
+        .expect_response_body(b"a ");
+    // Envoy no longer buffers the body by the time trailers arrive.
+    testing::with_host(|host| host.set_buffer(BufferType::HttpResponseBody, None::<&[u8]>));
+    test.response_trailers(&[("x-checksum", "abc")])
+        .expect_action(Action::Continue)
+        .expect_log(
+            LogLevel::Warn,
+            "dropped 2 bytes held back by the body transformer: no body buffer",
+        )
+        .expect_no_local_response();
+}
@@ -0,0 +1,125 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::hostcalls;
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::time::Duration;
+
+struct Guard;
+
+impl Context for Guard {}
+
+impl HttpContext for Guard {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        let action = self.get_http_request_header("x-action");
+        match action.as_ref().map(|value| value.to_string()).as_deref() {
+            Some("close") => self.close_http_request(),
+            Some("reset") => self.reset_http_stream("forbidden client"),
+            Some("close-later") => {
+                self.schedule_after(Duration::from_secs(1), || {
+                    if let Err(error) = hostcalls::close_stream(StreamType::Request) {
+                        hostcalls::log(LogLevel::Warn, &error.to_string()).unwrap();
+                    }
+                })
+                .unwrap();
+            }
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    fn on_http_request_body(&mut self, _: usize, _: bool) -> Action {
+        hostcalls::log(LogLevel::Debug, "request body").unwrap();
+        Action::Continue
+    }
+
+    fn on_http_response_headers(&mut self, _: usize, _: bool) -> Action {
+        hostcalls::log(LogLevel::Debug, "response headers").unwrap();
+        Action::Continue
+    }
+}
+
+#[test]
+fn test_open_stream() {
+    HttpFilterTest::with_http_context(|_, _| Guard)
+        .request_headers(&[], false)
+        .request_body(b"data", true)
+        .expect_action(Action::Continue)
+        .response_headers(&[(":status", "200")], true)
+        .expect_action(Action::Continue);
+    assert!(testing::logged("request body"));
+    assert!(testing::logged("response headers"));
+}
+
+#[test]
+fn test_close_http_request() {
+    HttpFilterTest::with_http_context(|_, _| Guard)
+        .request_headers(&[("x-action", "close")], false)
+        .expect_closed(StreamType::Request)
+        .request_body(b"data", true)
+        .expect_action(Action::Pause)
+        .response_headers(&[(":status", "200")], true)
+        .expect_action(Action::Continue);
+    assert!(!testing::logged("request body"));
+    assert!(testing::logged("response headers"));
+}
+
+#[test]
+fn test_reset_http_stream() {
+    HttpFilterTest::with_http_context(|_, _| Guard)
+        .request_headers(&[("x-action", "reset")], false)
+        .expect_closed(StreamType::Request)
+        .expect_closed(StreamType::Response)
+        .expect_log(LogLevel::Info, "resetting HTTP stream: forbidden client")
+        .request_body(b"data", true)
+        .expect_action(Action::Pause)
+        .response_headers(&[(":status", "200")], true)
+        .expect_action(Action::Pause)
+        .complete();
+    assert!(!testing::logged("request body"));
+    assert!(!testing::logged("response headers"));
+}
+
+#[test]
+fn test_close_from_timer() {
+    HttpFilterTest::with_http_context(|_, _| Guard)
+        .request_headers(&[("x-action", "close-later")], false)
+        .advance_time(Duration::from_secs(1))
+        .tick()
+        .expect_closed(StreamType::Request)
+        .request_body(b"data", true)
+        .expect_action(Action::Pause);
+    assert!(!testing::logged("request body"));
+}
+
+#[test]
+fn test_close_from_root_context() {
+    // The timer outlives the HTTP context, so it fires as one of the root context.
+    HttpFilterTest::with_http_context(|_, _| Guard)
+        .request_headers(&[("x-action", "close-later")], false)
+        .complete()
+        .advance_time(Duration::from_secs(1))
+        .tick()
+        .expect_log(
+            LogLevel::Warn,
+            "cannot close Request stream from a root context",
+        );
+    testing::with_host(|host| assert!(host.closed_streams().is_empty()));
+}
@@ -0,0 +1,121 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::config::{self, ConfigFormat, ConfigurableRootContext};
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use serde::Deserialize;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Config {
+    header: String,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+struct AddHeader {
+    config: Rc<Config>,
+}
+
+impl Context for AddHeader {}
+
+impl HttpContext for AddHeader {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        let value = self.config.value.as_deref().unwrap_or("true");
+        self.set_http_request_header(&self.config.header, Some(value));
+        Action::Continue
+    }
+}
+
+fn setup(format: ConfigFormat) -> HttpFilterTest {
+    HttpFilterTest::with_root_context(move |_| {
+        ConfigurableRootContext::<Config>::new()
+            .with_format(format)
+            .with_validator(|config| {
+                if config.header.is_empty() {
+                    return Err("header must not be empty".into());
+                }
+                Ok(())
+            })
+            .with_http_context(|_, config| Box::new(AddHeader { config }))
+    })
+}
+
+#[test]
+fn test_json() {
+    setup(ConfigFormat::Json)
+        .plugin_config(r#"{"header": "x-filtered"}"#)
+        .expect_config_accepted(true)
+        .request_headers(&[], true)
+        .expect_request_header("x-filtered", Some("true"));
+}
+
+#[test]
+fn test_invalid_json() {
+    setup(ConfigFormat::Json)
+        .plugin_config(r#"{"header": 1}"#)
+        .expect_config_accepted(false);
+    let (level, message) = &testing::logs()[0];
+    assert_eq!(*level, LogLevel::Error);
+    assert!(message.starts_with("invalid plugin configuration: invalid type: integer `1`"));
+    assert!(message.ends_with("at line 1 column 12"));
+}
+
+#[test]
+fn test_rejected_by_validator() {
+    setup(ConfigFormat::Json)
+        .plugin_config(r#"{"header": ""}"#)
+        .expect_config_accepted(false)
+        .expect_log(
+            LogLevel::Error,
+            "invalid plugin configuration: header must not be empty",
+        );
+}
+
+#[cfg(feature = "serde-yaml")]
+#[test]
+fn test_yaml() {
+    setup(ConfigFormat::Yaml)
+        .plugin_config("header: x-filtered\nvalue: yaml")
+        .expect_config_accepted(true)
+        .request_headers(&[], true)
+        .expect_request_header("x-filtered", Some("yaml"));
+}
+
+#[cfg(feature = "serde-toml")]
+#[test]
+fn test_toml() {
+    assert_eq!(
+        config::parse::<Config>(b"header = \"x-filtered\"", ConfigFormat::Toml).unwrap(),
+        Config {
+            header: "x-filtered".to_string(),
+            value: None,
+        }
+    );
+}
+
+#[test]
+fn test_load_vm_config() {
+    testing::with_host(|host| {
+        host.set_buffer(BufferType::VmConfiguration, Some(r#"{"header": "x-vm"}"#))
+    });
+    let config: Config = config::load(BufferType::VmConfiguration, 18, ConfigFormat::Json).unwrap();
+    assert_eq!(config.header, "x-vm");
+}
@@ -0,0 +1,124 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::error::Result;
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::time::Duration;
+
+struct CopyHeader;
+
+impl Context for CopyHeader {}
+
+impl HttpContext for CopyHeader {
+    fn try_on_http_request_headers(&mut self, _: usize, _: bool) -> Result<Action> {
+        let value = self
+            .try_get_http_request_header("x-source")?
+            .ok_or("missing x-source header")?;
+        self.try_set_http_request_header("x-target", Some(&value.to_string()))?;
+        Ok(Action::Continue)
+    }
+}
+
+struct CheckAuth;
+
+impl Context for CheckAuth {
+    fn try_on_http_call_response(&mut self, _: u32, _: usize, _: usize, _: usize) -> Result<()> {
+        let headers = self.try_get_http_call_response_header_map()?;
+        let status = headers.get(":status").ok_or("missing :status header")?;
+        if status != "200" {
+            return Err(format!("auth service responded with {}", status).into());
+        }
+        self.try_resume_http_request()
+    }
+}
+
+impl HttpContext for CheckAuth {
+    fn try_on_http_request_headers(&mut self, _: usize, _: bool) -> Result<Action> {
+        self.dispatch_http_call("auth", vec![], None, vec![], Duration::from_secs(1))?;
+        Ok(Action::Pause)
+    }
+}
+
+fn setup(policy: Option<ErrorPolicy>) -> HttpFilterTest {
+    if let Some(policy) = policy {
+        proxy_wasm::set_error_policy(policy);
+    }
+    HttpFilterTest::with_http_context(|_, _| CopyHeader)
+}
+
+#[test]
+fn test_success() {
+    setup(None)
+        .request_headers(&[("x-source", "value")], true)
+        .expect_action(Action::Continue)
+        .expect_request_header("x-target", Some("value"))
+        .expect_no_local_response();
+}
+
+#[test]
+fn test_fail_closed_by_default() {
+    setup(None)
+        .request_headers(&[], true)
+        .expect_action(Action::Pause)
+        .expect_local_response(500)
+        .expect_error("missing x-source header");
+}
+
+#[test]
+fn test_fail_open() {
+    testing::with_host(|host| host.fail_next("proxy_get_header_map_value", Status::BadArgument));
+    setup(Some(ErrorPolicy::FailOpen))
+        .request_headers(&[("x-source", "value")], true)
+        .expect_action(Action::Continue)
+        .expect_request_header("x-target", None)
+        .expect_no_local_response()
+        .expect_error("proxy_get_header_map_value");
+}
+
+#[test]
+fn test_fail_closed_without_local_response() {
+    testing::with_host(|host| host.fail_next("proxy_send_local_response", Status::BadArgument));
+    setup(None)
+        .request_headers(&[], true)
+        .expect_action(Action::Pause)
+        .expect_no_local_response()
+        .expect_error("proxy_send_local_response");
+    assert_eq!(testing::logs().len(), 2);
+}
+
+#[test]
+fn test_call_response_fail_closed() {
+    HttpFilterTest::with_http_context(|_, _| CheckAuth)
+        .request_headers(&[], true)
+        .expect_action(Action::Pause)
+        .http_call_response(&[(":status", "503")], None, &[])
+        .expect_local_response(500)
+        .expect_error("auth service responded with 503");
+}
+
+#[test]
+fn test_call_response_fail_open() {
+    proxy_wasm::set_error_policy(ErrorPolicy::FailOpen);
+    HttpFilterTest::with_http_context(|_, _| CheckAuth)
+        .request_headers(&[], true)
+        .http_call_response(&[(":status", "503")], None, &[])
+        .expect_no_local_response()
+        .expect_error("auth service responded with 503");
+}
@@ -0,0 +1,59 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+
+struct Sanitizer;
+
+impl Context for Sanitizer {}
+
+impl HttpContext for Sanitizer {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        let mut headers = self.get_http_request_header_map();
+        headers.remove("x-internal");
+        let hops = headers.get_all("via").count();
+        headers.insert("x-hops", hops.to_string());
+        headers.append("via", "wasm");
+        headers.flush().unwrap();
+        Action::Continue
+    }
+}
+
+#[test]
+fn test_flush() {
+    HttpFilterTest::with_http_context(|_, _| Sanitizer)
+        .request_headers(
+            &[
+                (":path", "/"),
+                ("Via", "edge"),
+                ("X-Internal", "1"),
+                ("via", "mesh"),
+                ("x-internal", "2"),
+            ],
+            true,
+        )
+        .expect_action(Action::Continue)
+        .expect_request_header("x-internal", None)
+        .expect_request_header("x-hops", Some("2"));
+    let headers =
+        testing::with_host(|host| host.map(MapType::HttpRequestHeaders).unwrap().to_vec());
+    let names: Vec<String> = headers.iter().map(|(name, _)| name.to_string()).collect();
+    assert_eq!(names, vec![":path", "Via", "via", "x-hops", "via"]);
+}
@@ -0,0 +1,110 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(all(feature = "http-compat", not(target_arch = "wasm32")))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use http::{Method, Request, Response, StatusCode};
+use proxy_wasm::http_compat;
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::time::Duration;
+
+struct Router;
+
+impl Context for Router {}
+
+impl HttpContext for Router {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        let parts = self.get_http_request_parts();
+        assert_eq!(parts.uri.scheme_str(), Some("https"));
+        assert_eq!(parts.uri.host(), Some("example.com"));
+        match (&parts.method, parts.uri.path()) {
+            (&Method::GET, "/health") => {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header("content-type", "text/plain")
+                    .body("ok")
+                    .unwrap();
+                http_compat::send_http_response(response).unwrap();
+                Action::Pause
+            }
+            (&Method::POST, _) => {
+                let request = Request::post("/audit")
+                    .header("host", "audit.local")
+                    .header("x-user", parts.headers["x-user"].clone())
+                    .body(Vec::new())
+                    .unwrap();
+                http_compat::dispatch_http_call("audit", request, Duration::from_secs(1)).unwrap();
+                Action::Pause
+            }
+            _ => Action::Continue,
+        }
+    }
+}
+
+fn request(method: &'static str, path: &'static str) -> Vec<(&'static str, &'static str)> {
+    vec![
+        (":method", method),
+        (":scheme", "https"),
+        (":authority", "example.com"),
+        (":path", path),
+        ("x-user", "alice"),
+    ]
+}
+
+#[test]
+fn test_request_parts() {
+    let parts = http_compat::request_parts(&[
+        (":method".into(), "PUT".into()),
+        (":path".into(), "/items?id=1".into()),
+        ("accept".into(), "*/*".into()),
+    ])
+    .unwrap();
+    assert_eq!(parts.method, Method::PUT);
+    assert_eq!(parts.uri, "/items?id=1");
+    assert_eq!(parts.headers["accept"], "*/*");
+}
+
+#[test]
+fn test_send_http_response() {
+    HttpFilterTest::with_http_context(|_, _| Router)
+        .request_headers(&request("GET", "/health"), true)
+        .expect_action(Action::Pause)
+        .expect_local_response(200)
+        .expect_local_response_body(b"ok");
+}
+
+#[test]
+fn test_dispatch_http_call() {
+    HttpFilterTest::with_http_context(|_, _| Router)
+        .request_headers(&request("POST", "/items"), true)
+        .expect_http_call("audit");
+    let headers = testing::with_host(|host| host.http_calls()[0].headers.clone());
+    let headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    assert_eq!(
+        headers,
+        vec![
+            (":method".to_string(), "POST".to_string()),
+            (":path".to_string(), "/audit".to_string()),
+            (":authority".to_string(), "audit.local".to_string()),
+            ("x-user".to_string(), "alice".to_string()),
+        ]
+    );
+}
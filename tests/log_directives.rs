@@ -0,0 +1,118 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::hostcalls;
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+
+struct Root;
+
+impl Context for Root {}
+
+impl RootContext for Root {
+    fn on_configure(&mut self, plugin_configuration_size: usize) -> bool {
+        let directives = hostcalls::get_buffer(
+            BufferType::PluginConfiguration,
+            0,
+            plugin_configuration_size,
+        )
+        .unwrap()
+        .unwrap_or_default();
+        proxy_wasm::set_log_directives(&directives.to_string()).is_ok()
+    }
+}
+
+struct Filter;
+
+impl Context for Filter {}
+
+impl HttpContext for Filter {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        log::debug!(target: "my_filter::auth", "auth debug");
+        log::trace!(target: "my_filter::auth", "auth trace");
+        log::debug!(target: "my_filter", "filter debug");
+        log::info!(target: "my_filter", "filter info");
+        log::info!(target: "other", "other info");
+        log::warn!(target: "other", "other warn");
+        Action::Continue
+    }
+}
+
+fn logged(directives: &str) -> Vec<String> {
+    testing::reset();
+    HttpFilterTest::new()
+        .plugin_config(directives)
+        .expect_config_accepted(true)
+        .request_headers(&[], true);
+    messages()
+}
+
+fn messages() -> Vec<String> {
+    testing::logs()
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect()
+}
+
+// Directives are set by a single test, since the maximum level of the `log`
+// crate is shared by all threads.
+#[test]
+fn test_log_directives() {
+    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> { Box::new(Root) });
+    proxy_wasm::set_http_context(|_, _| -> Box<dyn HttpContext> { Box::new(Filter) });
+
+    assert_eq!(
+        logged("my_filter::auth=debug,my_filter=info,warn"),
+        vec!["auth debug", "filter info", "other warn"]
+    );
+    assert_eq!(
+        logged("my_filter"),
+        vec!["auth debug", "auth trace", "filter debug", "filter info"]
+    );
+    assert_eq!(logged("info,other=off"), vec!["filter info"]);
+
+    // Invalid directives are rejected, keeping the previous ones.
+    testing::reset();
+    HttpFilterTest::new()
+        .plugin_config("my_filter=loud")
+        .expect_config_accepted(false)
+        .request_headers(&[], true);
+    assert_eq!(messages(), vec!["filter info"]);
+
+    // Levels below the one of the host are discarded.
+    #[cfg(feature = "abi-0-2-1")]
+    {
+        testing::reset();
+        testing::with_host(|host| host.set_log_level(LogLevel::Warn));
+        let test = HttpFilterTest::new()
+            .plugin_config("trace")
+            .request_headers(&[], true);
+        assert_eq!(messages(), vec!["other warn"]);
+
+        // The level of the host is not queried per message, but refreshed on ticks.
+        testing::with_host(|host| host.set_log_level(LogLevel::Trace));
+        let test = test.complete().request_headers(&[], true);
+        assert_eq!(messages().len(), 2);
+        test.complete().tick().request_headers(&[], true);
+        assert_eq!(messages().len(), 8);
+    }
+
+    // Targets are off without a default level.
+    assert!(logged("").is_empty());
+}
@@ -0,0 +1,450 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::hostcalls;
+use proxy_wasm::metrics::{Counter, Gauge};
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
+
+struct TestContext;
+
+impl Context for TestContext {}
+impl HttpContext for TestContext {}
+
+#[test]
+fn test_headers() {
+    testing::with_host(|host| {
+        host.set_map(
+            MapType::HttpRequestHeaders,
+            &[(":path", "/"), ("x-multi", "a"), ("x-multi", "b")],
+        )
+    });
+
+    let context = TestContext;
+    assert_eq!(context.get_http_request_header(":path").unwrap(), "/");
+    assert_eq!(context.get_http_request_headers().len(), 3);
+
+    context.set_http_request_header("X-Multi", Some("c"));
+    context.add_http_request_header("x-added", "d");
+    context.set_http_request_header(":path", None);
+
+    testing::with_host(|host| {
+        assert_eq!(
+            host.map(MapType::HttpRequestHeaders).unwrap(),
+            &[
+                ("x-multi".into(), "c".into()),
+                ("x-added".into(), "d".into()),
+            ][..]
+        );
+    });
+}
+
+#[test]
+fn test_buffers() {
+    testing::with_host(|host| host.set_buffer(BufferType::HttpRequestBody, Some("hello world")));
+
+    let context = TestContext;
+    assert_eq!(context.get_http_request_body(6, 5).unwrap(), "world");
+    assert_eq!(
+        context.get_http_request_body(0, 100).unwrap(),
+        "hello world"
+    );
+    assert_eq!(context.get_http_response_body(0, 100), None);
+
+    hostcalls::set_buffer(BufferType::HttpRequestBody, 0, 5, "goodbye").unwrap();
+
+    testing::with_host(|host| {
+        assert_eq!(
+            host.buffer(BufferType::HttpRequestBody).unwrap(),
+            "goodbye world"
+        );
+    });
+}
+
+#[test]
+fn test_properties() {
+    testing::with_host(|host| host.set_property(&["source", "address"], Some("1.2.3.4:80")));
+
+    let context = TestContext;
+    assert_eq!(
+        context.get_property(vec!["source", "address"]).unwrap(),
+        "1.2.3.4:80"
+    );
+    assert_eq!(context.get_property(vec!["missing"]), None);
+}
+
+#[test]
+fn test_shared_data_cas() {
+    let context = TestContext;
+    assert_eq!(context.get_shared_data("key"), (None, None));
+
+    context.set_shared_data("key", Some(b"one"), None).unwrap();
+    let (value, cas) = context.get_shared_data("key");
+    assert_eq!(value.unwrap(), "one");
+
+    context.set_shared_data("key", Some(b"two"), cas).unwrap();
+    assert!(context.set_shared_data("key", Some(b"three"), cas).is_err());
+}
+
+#[test]
+fn test_shared_queues() {
+    testing::with_host(|host| host.set_vm_id("my_vm"));
+
+    let context = TestContext;
+    let queue_id = context.register_shared_queue("my_queue");
+    assert_eq!(
+        context.resolve_shared_queue("my_vm", "my_queue"),
+        Some(queue_id)
+    );
+    assert_eq!(context.resolve_shared_queue("other_vm", "my_queue"), None);
+
+    context
+        .enqueue_shared_queue(queue_id, Some(b"message"))
+        .unwrap();
+    assert_eq!(
+        context.dequeue_shared_queue(queue_id).unwrap().unwrap(),
+        "message"
+    );
+    assert_eq!(context.dequeue_shared_queue(queue_id).unwrap(), None);
+}
+
+#[test]
+fn test_time_and_tick_period() {
+    testing::with_host(|host| host.set_current_time(UNIX_EPOCH + Duration::from_secs(42)));
+
+    let context = TestContext;
+    assert_eq!(
+        context.get_current_time(),
+        UNIX_EPOCH + Duration::from_secs(42)
+    );
+
+    hostcalls::set_tick_period(Duration::from_secs(5)).unwrap();
+    testing::with_host(|host| assert_eq!(host.tick_period(), Some(Duration::from_secs(5))));
+}
+
+#[test]
+fn test_local_response() {
+    let context = TestContext;
+    context.send_http_response(403, vec![("x-reason", "denied")], Some(b"Forbidden"));
+
+    testing::with_host(|host| {
+        let response = &host.local_responses()[0];
+        assert_eq!(response.status_code, 403);
+        assert_eq!(response.headers, vec![("x-reason".into(), "denied".into())]);
+        assert_eq!(response.body.as_ref().unwrap(), "Forbidden");
+    });
+}
+
+#[test]
+fn test_http_call() {
+    let context = TestContext;
+    let token_id = context
+        .dispatch_http_call(
+            "cluster",
+            vec![(":method", "GET"), (":path", "/")],
+            None,
+            vec![],
+            Duration::from_secs(1),
+        )
+        .unwrap();
+
+    testing::with_host(|host| {
+        let call = &host.http_calls()[0];
+        assert_eq!(call.token_id, token_id);
+        assert_eq!(call.upstream, "cluster");
+        assert_eq!(call.headers.len(), 2);
+        assert_eq!(call.body, None);
+        assert_eq!(call.timeout, Duration::from_secs(1));
+    });
+}
+
+#[test]
+fn test_log() {
+    hostcalls::log(LogLevel::Warn, "something happened").unwrap();
+
+    testing::with_host(|host| {
+        assert_eq!(
+            host.logs(),
+            &[(LogLevel::Warn, "something happened".to_owned())][..]
+        );
+    });
+}
+
+#[test]
+fn test_metrics() {
+    let counter = Counter::new("requests_total");
+    counter.increment(2).unwrap();
+    counter.increment(3).unwrap();
+
+    assert_eq!(counter.get().unwrap(), 5);
+    testing::with_host(|host| assert_eq!(host.metric("requests_total"), Some(5)));
+}
+
+#[test]
+fn test_metric_overflow() {
+    let counter = Counter::new("bytes_total");
+    assert_eq!(
+        counter.increment(u64::MAX).unwrap_err().to_string(),
+        format!("counter increment {} exceeds i64::MAX", u64::MAX)
+    );
+    assert_eq!(counter.get().unwrap(), 0);
+
+    let gauge = Gauge::new("connections");
+    gauge.increment(3).unwrap();
+    assert!(gauge.decrement(i64::MIN).is_err());
+    gauge.decrement(1).unwrap();
+    assert_eq!(gauge.get().unwrap(), 2);
+}
+
+#[cfg(feature = "abi-0-2-1")]
+#[test]
+fn test_foreign_function_making_hostcalls() {
+    testing::with_host(|host| {
+        host.register_foreign_function("echo", |arguments| {
+            hostcalls::log(LogLevel::Debug, "echo called").unwrap();
+            Some(arguments.to_vec())
+        })
+    });
+
+    for _ in 0..2 {
+        assert_eq!(
+            hostcalls::call_foreign_function("echo", Some(b"ping"))
+                .unwrap()
+                .unwrap(),
+            b"ping".to_vec()
+        );
+    }
+    assert_eq!(
+        hostcalls::call_foreign_function("missing", Some(b"ping")).unwrap(),
+        None
+    );
+    testing::with_host(|host| {
+        assert_eq!(
+            host.logs(),
+            &[
+                (LogLevel::Debug, "echo called".to_owned()),
+                (LogLevel::Debug, "echo called".to_owned())
+            ][..]
+        )
+    });
+}
+
+#[cfg(feature = "abi-0-2-1")]
+#[test]
+fn test_on_foreign_function() {
+    struct Callee;
+
+    impl Context for Callee {}
+
+    impl RootContext for Callee {
+        fn on_foreign_function(&mut self, function_id: u32, arguments_size: usize) {
+            let arguments = hostcalls::get_buffer(BufferType::CallData, 0, arguments_size)
+                .unwrap()
+                .unwrap();
+            hostcalls::log(
+                LogLevel::Info,
+                &format!("function {} called with {}", function_id, arguments),
+            )
+            .unwrap();
+        }
+    }
+
+    HttpFilterTest::with_root_context(|_| Callee)
+        .foreign_function(7, "ping")
+        .expect_log(LogLevel::Info, "function 7 called with ping");
+}
+
+#[cfg(not(feature = "abi-0-2-1"))]
+#[test]
+fn test_continue_stream_abi_0_1_0() {
+    hostcalls::continue_stream(StreamType::Request).unwrap();
+    hostcalls::continue_stream(StreamType::Response).unwrap();
+    assert_eq!(
+        hostcalls::continue_stream(StreamType::Downstream)
+            .unwrap_err()
+            .to_string(),
+        "cannot continue Downstream stream on ABI 0.1.0"
+    );
+
+    testing::with_host(|host| {
+        assert_eq!(
+            host.continued_streams(),
+            &[StreamType::Request, StreamType::Response][..]
+        )
+    });
+}
+
+#[test]
+fn test_fail_next() {
+    testing::with_host(|host| host.fail_next("proxy_http_call", Status::BadArgument));
+
+    let context = TestContext;
+    let dispatch =
+        || context.dispatch_http_call("cluster", vec![], None, vec![], Duration::from_secs(1));
+    assert!(dispatch().is_err());
+    assert!(dispatch().is_ok());
+}
+
+type Received = Rc<RefCell<Vec<String>>>;
+
+struct GrpcClient {
+    stream: bool,
+    received: Received,
+}
+
+impl Context for GrpcClient {
+    fn on_grpc_call_response(&mut self, token_id: u32, status_code: u32, response_size: usize) {
+        let message = self.get_grpc_call_response_body(0, response_size);
+        self.received.borrow_mut().push(format!(
+            "response {} {} {:?}",
+            token_id,
+            status_code,
+            message.map(|message| message.to_string())
+        ));
+    }
+
+    fn on_grpc_receive_initial_metadata(&mut self, token_id: u32, num_elements: usize) {
+        let metadata = self.get_grpc_receive_initial_metadata();
+        assert_eq!(metadata.len(), num_elements);
+        self.received
+            .borrow_mut()
+            .push(format!("initial metadata {} {}", token_id, metadata[0].1));
+    }
+
+    fn on_grpc_receive(&mut self, token_id: u32, response_size: usize) {
+        let message = self.get_grpc_stream_message(0, response_size).unwrap();
+        self.received
+            .borrow_mut()
+            .push(format!("message {} {}", token_id, message));
+    }
+
+    fn on_grpc_receive_trailing_metadata(&mut self, token_id: u32, num_elements: usize) {
+        let metadata = self.get_grpc_receive_trailing_metadata();
+        assert_eq!(metadata.len(), num_elements);
+        self.received
+            .borrow_mut()
+            .push(format!("trailing metadata {} {}", token_id, metadata[0].1));
+    }
+
+    fn on_grpc_close(&mut self, token_id: u32, status_code: u32) {
+        self.received
+            .borrow_mut()
+            .push(format!("close {} {}", token_id, status_code));
+    }
+}
+
+impl HttpContext for GrpcClient {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        if self.stream {
+            self.open_grpc_stream("cluster", "Echo", "Chat", vec![])
+                .unwrap();
+        } else {
+            self.dispatch_grpc_call(
+                "cluster",
+                "Echo",
+                "Say",
+                vec![],
+                Some(b"ping"),
+                Duration::from_secs(1),
+            )
+            .unwrap();
+        }
+        Action::Pause
+    }
+}
+
+fn grpc_client(stream: bool) -> (HttpFilterTest, Received) {
+    let received = Received::default();
+    let test = HttpFilterTest::with_http_context({
+        let received = Rc::clone(&received);
+        move |_, _| GrpcClient {
+            stream,
+            received: Rc::clone(&received),
+        }
+    })
+    .request_headers(&[(":path", "/")], true);
+    (test, received)
+}
+
+#[test]
+fn test_grpc_call_response() {
+    let (test, received) = grpc_client(false);
+    let token_id = testing::with_host(|host| host.grpc_calls()[0].token_id);
+
+    test.grpc_message(token_id, "pong").complete();
+
+    assert_eq!(
+        *received.borrow(),
+        vec![format!("response {} 0 Some(\"pong\")", token_id)]
+    );
+}
+
+#[test]
+fn test_grpc_call_failure() {
+    let (test, received) = grpc_client(false);
+    let token_id = testing::with_host(|host| host.grpc_calls()[0].token_id);
+
+    test.grpc_close(token_id, 14).complete();
+
+    assert_eq!(
+        *received.borrow(),
+        vec![format!("response {} 14 None", token_id)]
+    );
+}
+
+#[test]
+fn test_grpc_stream() {
+    let (test, received) = grpc_client(true);
+    let token_id = testing::with_host(|host| host.grpc_streams()[0].token_id);
+
+    test.grpc_initial_metadata(token_id, &[("x-initial", "a")])
+        .grpc_message(token_id, "hello")
+        .grpc_trailing_metadata(token_id, &[("grpc-message", "done")])
+        .grpc_close(token_id, 0)
+        .complete();
+
+    assert_eq!(
+        *received.borrow(),
+        vec![
+            format!("initial metadata {} a", token_id),
+            format!("message {} hello", token_id),
+            format!("trailing metadata {} done", token_id),
+            format!("close {} 0", token_id),
+        ]
+    );
+}
+
+#[test]
+fn test_grpc_token_dropped_on_close() {
+    // Isolate the panic on the unknown token, so that it can be observed.
+    proxy_wasm::set_panic_policy(PanicPolicy::CloseStream);
+    let (test, received) = grpc_client(true);
+    let token_id = testing::with_host(|host| host.grpc_streams()[0].token_id);
+
+    test.grpc_close(token_id, 0)
+        .grpc_message(token_id, "late")
+        .expect_error("panicked")
+        .expect_closed(StreamType::Request)
+        .complete();
+
+    assert_eq!(*received.borrow(), vec![format!("close {} 0", token_id)]);
+}
@@ -0,0 +1,146 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::time::Duration;
+
+struct PanicOnPath;
+
+impl Context for PanicOnPath {}
+
+impl HttpContext for PanicOnPath {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        if self.get_http_request_header(":path").unwrap() == "/panic" {
+            panic!("unexpected path");
+        }
+        Action::Continue
+    }
+
+    fn on_http_response_headers(&mut self, _: usize, _: bool) -> Action {
+        self.set_http_response_header("x-filtered", Some("true"));
+        Action::Continue
+    }
+}
+
+fn setup(policy: PanicPolicy) -> HttpFilterTest {
+    proxy_wasm::set_panic_policy(policy);
+    HttpFilterTest::with_http_context(|_, _| PanicOnPath)
+}
+
+#[test]
+fn test_send_local_response() {
+    setup(PanicPolicy::SendLocalResponse(503))
+        .request_headers(&[(":path", "/panic")], true)
+        .expect_action(Action::Pause)
+        .expect_local_response(503)
+        .response_headers(&[(":status", "503")], true)
+        .expect_action(Action::Continue)
+        .expect_response_header("x-filtered", None)
+        .complete();
+}
+
+#[test]
+fn test_close_stream() {
+    setup(PanicPolicy::CloseStream)
+        .request_headers(&[(":path", "/panic")], false)
+        .expect_action(Action::Pause)
+        .expect_no_local_response()
+        .request_body("data", true)
+        .expect_action(Action::Pause)
+        .response_headers(&[(":status", "200")], true)
+        .expect_action(Action::Pause)
+        .expect_response_header("x-filtered", None)
+        .complete();
+    testing::with_host(|host| assert_eq!(host.closed_streams(), &[StreamType::Request][..]));
+}
+
+#[test]
+fn test_close_stream_failure_logged() {
+    testing::with_host(|host| host.fail_next("proxy_close_stream", Status::NotFound));
+    setup(PanicPolicy::CloseStream)
+        .request_headers(&[(":path", "/panic")], true)
+        .expect_action(Action::Pause)
+        .expect_error("proxy_close_stream")
+        .complete();
+    testing::with_host(|host| assert!(host.closed_streams().is_empty()));
+}
+
+#[test]
+fn test_other_streams_unaffected() {
+    setup(PanicPolicy::SendLocalResponse(500))
+        .request_headers(&[(":path", "/panic")], true)
+        .expect_local_response(500)
+        .complete();
+    HttpFilterTest::new()
+        .request_headers(&[(":path", "/")], true)
+        .expect_action(Action::Continue)
+        .response_headers(&[(":status", "200")], true)
+        .expect_response_header("x-filtered", Some("true"))
+        .complete();
+}
+
+struct PanicOnTimer;
+
+impl Context for PanicOnTimer {}
+
+impl HttpContext for PanicOnTimer {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        self.schedule_every(Duration::from_secs(1), || panic!("unexpected tick"))
+            .unwrap();
+        Action::Pause
+    }
+}
+
+#[test]
+fn test_timer_of_http_context() {
+    proxy_wasm::set_panic_policy(PanicPolicy::SendLocalResponse(503));
+    let test = HttpFilterTest::with_http_context(|_, _| PanicOnTimer)
+        .request_headers(&[(":path", "/")], true)
+        .advance_time(Duration::from_secs(1))
+        .tick()
+        .expect_local_response(503)
+        .expect_error("panicked, no further callbacks");
+    let logged = testing::logs().len();
+    // The timer has been cancelled along with the context.
+    test.advance_time(Duration::from_secs(1)).tick().complete();
+    assert_eq!(testing::logs().len(), logged);
+}
+
+struct PanicOnDrop;
+
+impl Context for PanicOnDrop {}
+
+impl HttpContext for PanicOnDrop {}
+
+impl Drop for PanicOnDrop {
+    fn drop(&mut self) {
+        panic!("unexpected drop");
+    }
+}
+
+#[test]
+fn test_panic_on_delete() {
+    proxy_wasm::set_panic_policy(PanicPolicy::CloseStream);
+    HttpFilterTest::with_http_context(|_, _| PanicOnDrop)
+        .request_headers(&[(":path", "/")], true)
+        .expect_action(Action::Continue)
+        .complete()
+        .expect_error("panicked while being deleted");
+}
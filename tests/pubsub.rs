@@ -0,0 +1,167 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::shared::codec::Raw;
+use proxy_wasm::shared::{self, Publisher};
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn publisher() -> Publisher<String, Raw> {
+    Publisher::new("service", "stats", Raw)
+}
+
+fn queued(queue_id: u32) -> Vec<String> {
+    testing::with_host(|host| {
+        host.shared_queue(queue_id)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    })
+}
+
+fn register_subscriber() -> u32 {
+    testing::with_host(|host| host.register_shared_queue("service", "stats"))
+}
+
+#[test]
+fn test_publish_before_subscriber() {
+    testing::reset();
+    let mut publisher = publisher();
+    publisher.publish(&"a".to_string()).unwrap();
+    assert_eq!(publisher.pending(), 1);
+    assert_eq!(publisher.flush().unwrap(), 0);
+
+    let queue_id = register_subscriber();
+    publisher.publish(&"b".to_string()).unwrap();
+    assert_eq!(publisher.pending(), 0);
+    assert_eq!(queued(queue_id), vec!["a", "b"]);
+}
+
+#[test]
+fn test_drop_oldest_pending() {
+    testing::reset();
+    let mut publisher = publisher().with_capacity(2);
+    for message in &["a", "b", "c"] {
+        publisher.publish(&message.to_string()).unwrap();
+    }
+    assert_eq!(publisher.pending(), 2);
+    assert_eq!(publisher.dropped(), 1);
+
+    let queue_id = register_subscriber();
+    assert_eq!(publisher.flush().unwrap(), 2);
+    assert_eq!(queued(queue_id), vec!["b", "c"]);
+}
+
+#[test]
+fn test_retry_after_host_failure() {
+    testing::reset();
+    let queue_id = register_subscriber();
+    let mut publisher = publisher();
+    testing::with_host(|host| {
+        host.fail_next("proxy_enqueue_shared_queue", Status::InternalFailure)
+    });
+    publisher.publish(&"a".to_string()).unwrap();
+    assert_eq!(publisher.pending(), 1);
+    assert!(queued(queue_id).is_empty());
+
+    publisher.publish(&"b".to_string()).unwrap();
+    assert_eq!(publisher.pending(), 0);
+    assert_eq!(queued(queue_id), vec!["a", "b"]);
+}
+
+#[test]
+fn test_failure_logged_once() {
+    testing::reset();
+    register_subscriber();
+    let mut publisher = publisher();
+    let failures = || {
+        testing::logs()
+            .iter()
+            .filter(|(level, message)| {
+                *level == LogLevel::Warn && message.starts_with("failed to publish")
+            })
+            .count()
+    };
+    for message in &["a", "b"] {
+        testing::with_host(|host| {
+            host.fail_next("proxy_enqueue_shared_queue", Status::InternalFailure)
+        });
+        publisher.publish(&message.to_string()).unwrap();
+    }
+    assert_eq!(failures(), 1);
+
+    // Logged again once delivery has recovered and then fails anew.
+    publisher.publish(&"c".to_string()).unwrap();
+    testing::with_host(|host| {
+        host.fail_next("proxy_enqueue_shared_queue", Status::InternalFailure)
+    });
+    publisher.publish(&"d".to_string()).unwrap();
+    assert_eq!(failures(), 2);
+}
+
+#[test]
+fn test_resolve_again_after_not_found() {
+    testing::reset();
+    let queue_id = register_subscriber();
+    let mut publisher = publisher();
+    publisher.publish(&"a".to_string()).unwrap();
+    testing::with_host(|host| host.fail_next("proxy_enqueue_shared_queue", Status::NotFound));
+    publisher.publish(&"b".to_string()).unwrap();
+    assert_eq!(publisher.pending(), 1);
+    assert_eq!(publisher.flush().unwrap(), 1);
+    assert_eq!(queued(queue_id), vec!["a", "b"]);
+}
+
+struct Aggregator {
+    received: Rc<RefCell<Vec<String>>>,
+}
+
+impl Context for Aggregator {}
+
+impl RootContext for Aggregator {
+    fn on_vm_start(&mut self, _: usize) -> bool {
+        let received = Rc::clone(&self.received);
+        shared::subscribe("stats", Raw, move |message: String| {
+            received.borrow_mut().push(message)
+        })
+        .is_ok()
+    }
+}
+
+#[test]
+fn test_subscribe() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let aggregated = Rc::clone(&received);
+    let test = HttpFilterTest::with_root_context(move |_| Aggregator {
+        received: Rc::clone(&aggregated),
+    })
+    .vm_config(b"");
+    let vm_id = testing::with_host(|host| host.vm_id().to_owned());
+    let mut publisher = Publisher::new(vm_id.as_str(), "stats", Raw);
+    publisher.publish(&"a".to_string()).unwrap();
+    publisher.publish(&"b".to_string()).unwrap();
+    assert_eq!(publisher.pending(), 0);
+
+    let queue_id = testing::with_host(|host| host.register_shared_queue(&vm_id, "stats"));
+    test.queue_ready(queue_id);
+    assert_eq!(*received.borrow(), vec!["a", "b"]);
+}
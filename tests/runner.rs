@@ -0,0 +1,75 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "runner")]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn run(module: &str, fixture: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_proxy-wasm-run"))
+        .arg(module)
+        .arg(fixture)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Builds an SDK example for `wasm32-unknown-unknown` and returns the path to the module.
+fn build_example(name: &str) -> String {
+    // A separate target directory keeps the nested build off the lock held by `cargo test`.
+    let target_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/runner-fixtures");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--release", "--target", "wasm32-unknown-unknown"])
+        .args(["--example", name])
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build example {}", name);
+    target_dir
+        .join("wasm32-unknown-unknown/release/examples")
+        .join(format!("{}.wasm", name))
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+#[test]
+fn test_access_granted() {
+    let output = run("tests/runner/auth.wat", "tests/runner/auth.yaml");
+    assert!(output.contains("http call #1 to \"auth\": /auth"));
+    assert!(output.contains("[info] Access granted."));
+    assert!(output.contains("  x-auth: ok"));
+    assert!(output.contains("  Hello, World!"));
+}
+
+#[test]
+fn test_access_denied() {
+    let output = run("tests/runner/auth.wat", "tests/runner/auth-denied.json");
+    assert!(output.contains("local response: 403"));
+    assert!(output.contains("  Access denied."));
+    assert!(!output.contains("proxy_on_response_headers"));
+}
+
+#[test]
+fn test_sdk_example() {
+    let module = build_example("http_headers");
+    let output = run(&module, "tests/runner/http_headers.yaml");
+    assert!(output.contains("local response: 200"));
+    assert!(output.contains("  Hello, World!"));
+    assert!(output.contains("[trace] #2 -> :path: /hello"));
+    assert!(output.contains("[trace] #3 <- :status: 200"));
+    assert!(output.contains("[trace] #3 completed."));
+}
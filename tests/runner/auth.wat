@@ -0,0 +1,71 @@
+;; Copyright 2020 Tetrate
+;;
+;; Licensed under the Apache License, Version 2.0 (the "License");
+;; you may not use this file except in compliance with the License.
+;; You may obtain a copy of the License at
+;;
+;;     http://www.apache.org/licenses/LICENSE-2.0
+;;
+;; Unless required by applicable law or agreed to in writing, software
+;; distributed under the License is distributed on an "AS IS" BASIS,
+;; WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+;; See the License for the specific language governing permissions and
+;; limitations under the License.
+
+;; A minimal filter, written by hand, that authorizes requests via an HTTP call
+;; and adds a header to the authorized ones.
+(module
+  (import "env" "proxy_log" (func $log (param i32 i32 i32) (result i32)))
+  (import "env" "proxy_add_header_map_value"
+    (func $add_header (param i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "proxy_http_call"
+    (func $http_call (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+  (import "env" "proxy_continue_stream" (func $continue (param i32) (result i32)))
+  (import "env" "proxy_send_local_response"
+    (func $local_response (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+
+  ;; 0: "auth", 8: serialized headers [(":path", "/auth")], 64: "x-auth", 72: "ok",
+  ;; 80: "Access granted.", 96: "Access denied.\n"
+  (data (i32.const 0) "auth")
+  (data (i32.const 8) "\01\00\00\00\05\00\00\00\05\00\00\00:path\00/auth\00")
+  (data (i32.const 64) "x-auth")
+  (data (i32.const 72) "ok")
+  (data (i32.const 80) "Access granted.")
+  (data (i32.const 96) "Access denied.\n")
+
+  (func (export "proxy_on_memory_allocate") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $size)))
+    (local.get $ptr))
+
+  (func (export "proxy_on_context_create") (param i32 i32))
+  (func (export "proxy_on_vm_start") (param i32 i32) (result i32) (i32.const 1))
+  (func (export "proxy_on_configure") (param i32 i32) (result i32) (i32.const 1))
+
+  (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+    (drop (call $http_call
+      (i32.const 0) (i32.const 4) (i32.const 8) (i32.const 24)
+      (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)
+      (i32.const 1000) (i32.const 512)))
+    (i32.const 1))
+
+  (func (export "proxy_on_http_call_response") (param i32 i32 i32 i32 i32)
+    (if (i32.gt_u (local.get 3) (i32.const 0))
+      (then
+        (drop (call $log (i32.const 2) (i32.const 80) (i32.const 15)))
+        (drop (call $add_header (i32.const 0) (i32.const 64) (i32.const 6) (i32.const 72) (i32.const 2)))
+        (drop (call $continue (i32.const 0))))
+      (else
+        (drop (call $local_response
+          (i32.const 403) (i32.const 0) (i32.const 0) (i32.const 96) (i32.const 15)
+          (i32.const 0) (i32.const 0) (i32.const -1))))))
+
+  (func (export "proxy_on_response_headers") (param i32 i32 i32) (result i32) (i32.const 0))
+  (func (export "proxy_on_response_body") (param i32 i32 i32) (result i32) (i32.const 0))
+  (func (export "proxy_on_done") (param i32) (result i32) (i32.const 1))
+  (func (export "proxy_on_log") (param i32))
+  (func (export "proxy_on_delete") (param i32)))
@@ -0,0 +1,81 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::shared::codec::Raw;
+use proxy_wasm::shared::SharedCache;
+use proxy_wasm::testing;
+use std::time::Duration;
+
+fn tokens() -> SharedCache<String, Raw> {
+    SharedCache::new("tokens", Raw).with_ttl(Duration::from_secs(60))
+}
+
+fn stored(key: &str) -> Option<Vec<u8>> {
+    testing::with_host(|host| {
+        host.shared_data(key)
+            .map(|(value, _)| value.as_bytes().to_vec())
+    })
+}
+
+#[test]
+fn test_entry_expires() {
+    testing::reset();
+    let cache = tokens();
+    cache.set("upstream", &"secret".to_string()).unwrap();
+    assert_eq!(cache.get("upstream").unwrap().as_deref(), Some("secret"));
+    assert!(stored("tokens/upstream").unwrap().ends_with(b"secret"));
+
+    testing::with_host(|host| host.advance_time(Duration::from_secs(59)));
+    assert_eq!(cache.get("upstream").unwrap().as_deref(), Some("secret"));
+
+    testing::with_host(|host| host.advance_time(Duration::from_secs(1)));
+    assert_eq!(cache.get("upstream").unwrap(), None);
+    // Expired entries are deleted lazily.
+    assert_eq!(stored("tokens/upstream"), Some(Vec::new()));
+    assert_eq!(cache.get("upstream").unwrap(), None);
+}
+
+#[test]
+fn test_set_with_ttl() {
+    testing::reset();
+    let cache = tokens();
+    cache
+        .set_with_ttl("upstream", &"secret".to_string(), Duration::from_secs(1))
+        .unwrap();
+    testing::with_host(|host| host.advance_time(Duration::from_secs(1)));
+    assert_eq!(cache.get("upstream").unwrap(), None);
+}
+
+#[test]
+fn test_entry_without_ttl() {
+    testing::reset();
+    let cache = SharedCache::new("tokens", Raw);
+    cache.set("upstream", &"secret".to_string()).unwrap();
+    testing::with_host(|host| host.advance_time(Duration::from_secs(86400)));
+    assert_eq!(cache.get("upstream").unwrap().as_deref(), Some("secret"));
+}
+
+#[test]
+fn test_remove() {
+    testing::reset();
+    let cache = tokens();
+    cache.set("upstream", &"secret".to_string()).unwrap();
+    cache.remove("upstream").unwrap();
+    assert_eq!(cache.get("upstream").unwrap(), None);
+    assert_eq!(cache.get("missing").unwrap(), None);
+}
@@ -0,0 +1,63 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::shared::SharedCounter;
+use proxy_wasm::testing;
+
+#[test]
+fn test_fetch_add() {
+    testing::reset();
+    let counter = SharedCounter::new("requests");
+    assert_eq!(counter.get().unwrap(), 0);
+    assert_eq!(counter.fetch_add(1).unwrap(), 0);
+    assert_eq!(counter.fetch_add(5).unwrap(), 1);
+    assert_eq!(counter.fetch_sub(2).unwrap(), 6);
+    assert_eq!(counter.get().unwrap(), 4);
+    assert_eq!(
+        testing::with_host(|host| host.shared_data("requests").unwrap().0.clone()),
+        4i64.to_le_bytes().to_vec()
+    );
+}
+
+#[test]
+fn test_fetch_add_wraps_around() {
+    testing::reset();
+    let counter = SharedCounter::new("requests");
+    counter.set(i64::MAX).unwrap();
+    assert_eq!(counter.fetch_add(1).unwrap(), i64::MAX);
+    assert_eq!(counter.get().unwrap(), i64::MIN);
+}
+
+#[test]
+fn test_counters_share_values() {
+    testing::reset();
+    SharedCounter::new("requests").fetch_add(3).unwrap();
+    assert_eq!(SharedCounter::new("requests").fetch_add(1).unwrap(), 3);
+}
+
+#[test]
+fn test_invalid_value() {
+    testing::reset();
+    testing::with_host(|host| host.set_shared_data("requests", "abc"));
+    let counter = SharedCounter::new("requests");
+    assert_eq!(
+        counter.get().unwrap_err().to_string(),
+        "shared counter value has 3 bytes, not 8"
+    );
+    assert!(counter.fetch_add(1).is_err());
+}
@@ -0,0 +1,162 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::error::HostCallError;
+use proxy_wasm::shared::codec::Raw;
+use proxy_wasm::shared::SharedData;
+use proxy_wasm::testing;
+use proxy_wasm::types::*;
+
+fn stored(key: &str) -> Option<(String, u32)> {
+    testing::with_host(|host| {
+        host.shared_data(key)
+            .map(|(value, cas)| (value.to_string(), cas))
+    })
+}
+
+#[test]
+fn test_get_and_set() {
+    testing::reset();
+    let data = SharedData::new("greeting", Raw);
+    assert_eq!(data.get().unwrap(), None::<String>);
+
+    data.set(&"hello".to_string()).unwrap();
+    assert_eq!(data.get().unwrap().as_deref(), Some("hello"));
+    assert_eq!(stored("greeting"), Some(("hello".to_string(), 1)));
+}
+
+#[test]
+fn test_set_with_stale_cas() {
+    testing::reset();
+    testing::with_host(|host| host.set_shared_data("greeting", "hello"));
+    let data = SharedData::new("greeting", Raw);
+    let (_, cas) = data.get_with_cas().unwrap();
+    testing::with_host(|host| host.set_shared_data("greeting", "hi"));
+
+    let error = data.set_with_cas(&"hey".to_string(), cas).unwrap_err();
+    let error = error.downcast_ref::<HostCallError>().unwrap();
+    assert_eq!(error.status(), Status::CasMismatch);
+    assert_eq!(data.get().unwrap().as_deref(), Some("hi"));
+}
+
+#[test]
+fn test_update_retries_on_cas_mismatch() {
+    testing::reset();
+    testing::with_host(|host| host.set_shared_data("visits", "1"));
+    let data = SharedData::new("visits", Raw);
+    let mut attempts = 0;
+    let updated = data
+        .update(|visits: Option<String>| {
+            attempts += 1;
+            if attempts == 1 {
+                // Another VM updates the value in the meantime.
+                testing::with_host(|host| host.set_shared_data("visits", "5"));
+            }
+            let visits: u32 = visits.unwrap().parse().unwrap();
+            (visits + 1).to_string()
+        })
+        .unwrap();
+    assert_eq!(attempts, 2);
+    assert_eq!(updated, "6");
+    assert_eq!(stored("visits"), Some(("6".to_string(), 3)));
+}
+
+#[test]
+fn test_update_gives_up() {
+    testing::reset();
+    testing::with_host(|host| host.set_shared_data("visits", "1"));
+    let data = SharedData::new("visits", Raw).with_max_attempts(3);
+    let mut attempts = 0;
+    let error = data
+        .update(|_: Option<String>| {
+            attempts += 1;
+            testing::with_host(|host| host.set_shared_data("visits", "2"));
+            "3".to_string()
+        })
+        .unwrap_err();
+    assert_eq!(attempts, 3);
+    assert_eq!(
+        error.to_string(),
+        "shared data \"visits\" has been changed concurrently 3 times in a row"
+    );
+    assert_eq!(stored("visits").unwrap().0, "2");
+}
+
+#[cfg(feature = "serde")]
+mod serde_codecs {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Stats {
+        requests: u64,
+        hosts: Vec<String>,
+    }
+
+    fn record<C>(data: &SharedData<Stats, C>, host: &str) -> Stats
+    where
+        C: proxy_wasm::shared::codec::Codec<Stats>,
+    {
+        data.update(|stats| {
+            let mut stats = stats.unwrap_or_default();
+            stats.requests += 1;
+            stats.hosts.push(host.to_string());
+            stats
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_json() {
+        use proxy_wasm::shared::codec::Json;
+
+        testing::reset();
+        let data = SharedData::new("stats", Json);
+        record(&data, "a.example");
+        let stats = record(&data, "b.example");
+        assert_eq!(stats.requests, 2);
+        assert_eq!(
+            stored("stats").unwrap().0,
+            r#"{"requests":2,"hosts":["a.example","b.example"]}"#
+        );
+        assert_eq!(data.get().unwrap(), Some(stats));
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        use proxy_wasm::shared::codec::Json;
+
+        testing::reset();
+        testing::with_host(|host| host.set_shared_data("stats", "{"));
+        let data: SharedData<Stats, _> = SharedData::new("stats", Json);
+        assert!(data.get().is_err());
+        assert!(data.update(|_| Stats::default()).is_err());
+    }
+
+    #[cfg(feature = "serde-bincode")]
+    #[test]
+    fn test_bincode() {
+        use proxy_wasm::shared::codec::Bincode;
+
+        testing::reset();
+        let data = SharedData::new("stats", Bincode);
+        record(&data, "a.example");
+        let stats = record(&data, "b.example");
+        assert_eq!(data.get().unwrap(), Some(stats));
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::shared::codec::Raw;
+use proxy_wasm::shared::{QueueReceiver, QueueSender};
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type Batches = Rc<RefCell<Vec<Vec<String>>>>;
+
+struct Collector {
+    batches: Batches,
+}
+
+impl Context for Collector {}
+
+impl RootContext for Collector {
+    fn on_vm_start(&mut self, _: usize) -> bool {
+        let batches = Rc::clone(&self.batches);
+        QueueReceiver::register("events", Raw)
+            .unwrap()
+            .with_batch_size(2)
+            .on_ready(move |batch: Vec<String>| batches.borrow_mut().push(batch));
+        self.register_shared_queue("other");
+        true
+    }
+
+    fn on_queue_ready(&mut self, queue_id: u32) {
+        self.batches
+            .borrow_mut()
+            .push(vec![format!("unhandled queue {}", queue_id)]);
+    }
+}
+
+fn setup() -> (Batches, HttpFilterTest) {
+    let batches = Batches::default();
+    let collected = Rc::clone(&batches);
+    let test = HttpFilterTest::with_root_context(move |_| Collector {
+        batches: Rc::clone(&collected),
+    });
+    (batches, test.vm_config(b""))
+}
+
+fn sender(name: &str) -> QueueSender<String, Raw> {
+    let vm_id = testing::with_host(|host| host.vm_id().to_owned());
+    QueueSender::resolve(&vm_id, name, Raw).unwrap().unwrap()
+}
+
+#[test]
+fn test_batches() {
+    let (batches, test) = setup();
+    let events = sender("events");
+    for event in &["a", "b", "c", "d", "e"] {
+        events.send(&event.to_string()).unwrap();
+    }
+    test.queue_ready(events.queue_id());
+    assert_eq!(
+        *batches.borrow(),
+        vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]
+    );
+    assert!(testing::with_host(|host| host
+        .shared_queue(events.queue_id())
+        .unwrap()
+        .is_empty()));
+}
+
+#[test]
+fn test_queue_without_handler() {
+    let (batches, test) = setup();
+    let other = sender("other");
+    other.send(&"a".to_string()).unwrap();
+    test.queue_ready(other.queue_id());
+    assert_eq!(
+        *batches.borrow(),
+        vec![vec![format!("unhandled queue {}", other.queue_id())]]
+    );
+    assert_eq!(
+        testing::with_host(|host| host.shared_queue(other.queue_id()).unwrap().len()),
+        1
+    );
+}
+
+#[test]
+fn test_undecodable_message() {
+    let (batches, test) = setup();
+    let events = sender("events");
+    testing::with_host(|host| host.enqueue_shared_queue(events.queue_id(), b"\xff"));
+    events.send(&"a".to_string()).unwrap();
+    test.queue_ready(events.queue_id()).expect_log(
+        LogLevel::Warn,
+        &format!(
+            "dropping message from shared queue {}: invalid utf-8 sequence of 1 bytes from index 0",
+            events.queue_id()
+        ),
+    );
+    assert_eq!(*batches.borrow(), vec![vec!["a"]]);
+}
+
+#[test]
+fn test_recv() {
+    testing::reset();
+    let receiver = QueueReceiver::register("events", Raw).unwrap();
+    let sender = QueueSender::new(receiver.queue_id(), Raw);
+    sender.send(&b"a".to_vec()).unwrap();
+    assert_eq!(receiver.recv().unwrap(), Some(b"a".to_vec()));
+    assert_eq!(receiver.recv().unwrap(), None);
+}
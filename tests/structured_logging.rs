@@ -0,0 +1,101 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+
+struct Auth;
+
+impl Context for Auth {}
+
+impl HttpContext for Auth {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        log::warn!(target: "auth", user = "alice", attempts = 3, admin = false; "request \"denied\"");
+        log::debug!("checked");
+        Action::Continue
+    }
+}
+
+fn setup(format: LogFormat) -> HttpFilterTest {
+    proxy_wasm::set_log_level(LogLevel::Trace);
+    proxy_wasm::set_log_format(format);
+    HttpFilterTest::with_http_context(|_, _| Auth).request_headers(&[], true)
+}
+
+#[test]
+fn test_plain() {
+    setup(LogFormat::Plain);
+    assert_eq!(
+        testing::logs(),
+        vec![
+            (LogLevel::Warn, "request \"denied\"".to_string()),
+            (LogLevel::Debug, "checked".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_logfmt() {
+    let test = setup(LogFormat::Logfmt);
+    let context_id = test.http_context_id();
+    assert_eq!(
+        testing::logs(),
+        vec![
+            (
+                LogLevel::Warn,
+                format!(
+                    "context_id={} target=auth module=structured_logging msg=\"request \\\"denied\\\"\" user=alice attempts=3 admin=false",
+                    context_id
+                )
+            ),
+            (
+                LogLevel::Debug,
+                format!(
+                    "context_id={} target=structured_logging msg=checked",
+                    context_id
+                )
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_json() {
+    let test = setup(LogFormat::Json);
+    let context_id = test.http_context_id();
+    assert_eq!(
+        testing::logs(),
+        vec![
+            (
+                LogLevel::Warn,
+                format!(
+                    r#"{{"context_id":{},"target":"auth","module":"structured_logging","msg":"request \"denied\"","user":"alice","attempts":3,"admin":false}}"#,
+                    context_id
+                )
+            ),
+            (
+                LogLevel::Debug,
+                format!(
+                    r#"{{"context_id":{},"target":"structured_logging","msg":"checked"}}"#,
+                    context_id
+                )
+            ),
+        ]
+    );
+}
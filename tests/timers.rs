@@ -0,0 +1,266 @@
+// Copyright 2020 Tetrate
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proxy_wasm_experimental as proxy_wasm;
+
+use proxy_wasm::testing::{self, HttpFilterTest};
+use proxy_wasm::timer::{self, TimerHandle};
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+type Fired = Rc<RefCell<Vec<&'static str>>>;
+type Handles = Rc<RefCell<Vec<TimerHandle>>>;
+
+struct Scheduler {
+    fired: Fired,
+    handles: Handles,
+}
+
+impl Context for Scheduler {}
+
+impl RootContext for Scheduler {
+    fn on_vm_start(&mut self, _: usize) -> bool {
+        let fired = Rc::clone(&self.fired);
+        let once = self
+            .schedule_after(Duration::from_secs(3), move || {
+                fired.borrow_mut().push("once")
+            })
+            .unwrap();
+        let fired = Rc::clone(&self.fired);
+        let every = self
+            .schedule_every(Duration::from_secs(2), move || {
+                fired.borrow_mut().push("every")
+            })
+            .unwrap();
+        self.handles.borrow_mut().extend(vec![once, every]);
+        true
+    }
+
+    fn on_tick(&mut self) {
+        self.fired.borrow_mut().push("tick");
+    }
+}
+
+struct Ticker {
+    fired: Fired,
+    handles: Handles,
+}
+
+impl Context for Ticker {}
+
+impl RootContext for Ticker {
+    fn on_vm_start(&mut self, _: usize) -> bool {
+        self.set_tick_period(Duration::from_secs(2));
+        let fired = Rc::clone(&self.fired);
+        let every = self
+            .schedule_every(Duration::from_secs(3), move || {
+                fired.borrow_mut().push("every")
+            })
+            .unwrap();
+        self.handles.borrow_mut().push(every);
+        true
+    }
+
+    fn on_tick(&mut self) {
+        self.fired.borrow_mut().push("tick");
+    }
+}
+
+struct Deferred {
+    fired: Fired,
+}
+
+impl Context for Deferred {}
+
+impl HttpContext for Deferred {
+    fn on_http_request_headers(&mut self, _: usize, _: bool) -> Action {
+        let fired = Rc::clone(&self.fired);
+        self.schedule_after(Duration::from_millis(500), move || {
+            fired.borrow_mut().push("deferred")
+        })
+        .unwrap();
+        Action::Continue
+    }
+}
+
+struct Fast {
+    fired: Fired,
+}
+
+impl Context for Fast {}
+
+impl RootContext for Fast {
+    fn on_vm_start(&mut self, _: usize) -> bool {
+        let fired = Rc::clone(&self.fired);
+        self.schedule_every(Duration::from_millis(3), move || {
+            fired.borrow_mut().push("fast")
+        })
+        .is_ok()
+    }
+}
+
+fn fast() -> (Fired, HttpFilterTest) {
+    let fired = Fired::default();
+    let ticked = Rc::clone(&fired);
+    let test = HttpFilterTest::with_root_context(move |_| Fast {
+        fired: Rc::clone(&ticked),
+    });
+    (fired, test)
+}
+
+fn setup() -> (Fired, Handles, HttpFilterTest) {
+    let fired = Fired::default();
+    let handles = Handles::default();
+    let deferred = Rc::clone(&fired);
+    proxy_wasm::set_http_context(move |_, _| -> Box<dyn HttpContext> {
+        Box::new(Deferred {
+            fired: Rc::clone(&deferred),
+        })
+    });
+    let (scheduled, scheduled_handles) = (Rc::clone(&fired), Rc::clone(&handles));
+    let test = HttpFilterTest::with_root_context(move |_| Scheduler {
+        fired: Rc::clone(&scheduled),
+        handles: Rc::clone(&scheduled_handles),
+    });
+    (fired, handles, test)
+}
+
+fn tick_period() -> Option<Duration> {
+    testing::with_host(|host| host.tick_period())
+}
+
+#[test]
+fn test_timers_fire_when_due() {
+    let (fired, _, test) = setup();
+    let test = test.vm_config(b"");
+    assert_eq!(tick_period(), Some(Duration::from_secs(1)));
+
+    // Without a tick period of its own, the root context is not ticked for timers.
+    let test = test.advance_time(Duration::from_secs(1)).tick();
+    assert!(fired.borrow().is_empty());
+
+    let test = test.advance_time(Duration::from_secs(1)).tick();
+    assert_eq!(*fired.borrow(), vec!["every"]);
+
+    test.advance_time(Duration::from_secs(1)).tick();
+    assert_eq!(*fired.borrow(), vec!["every", "once"]);
+    assert_eq!(tick_period(), Some(Duration::from_secs(2)));
+}
+
+#[test]
+fn test_cancel() {
+    let (fired, handles, test) = setup();
+    let test = test.vm_config(b"");
+    let scheduled = handles.borrow().clone();
+    timer::cancel(scheduled[0]).unwrap();
+    assert_eq!(tick_period(), Some(Duration::from_secs(2)));
+    timer::cancel(scheduled[1]).unwrap();
+    assert_eq!(tick_period(), None);
+    // Cancelling again is a no-op.
+    timer::cancel(scheduled[1]).unwrap();
+
+    test.advance_time(Duration::from_secs(5)).tick();
+    assert_eq!(*fired.borrow(), vec!["tick"]);
+}
+
+#[test]
+fn test_timer_scheduled_by_child_context() {
+    let (fired, _, test) = setup();
+    let test = test
+        .request_headers(&[], true)
+        .expect_action(Action::Continue);
+    assert_eq!(tick_period(), Some(Duration::from_millis(500)));
+
+    test.complete()
+        .advance_time(Duration::from_millis(500))
+        .tick();
+    assert_eq!(*fired.borrow(), vec!["deferred"]);
+    assert_eq!(tick_period(), Some(Duration::from_secs(1)));
+}
+
+#[test]
+fn test_tick_period_with_timers() {
+    let fired = Fired::default();
+    let handles = Handles::default();
+    let (ticked, ticker_handles) = (Rc::clone(&fired), Rc::clone(&handles));
+    let mut test = HttpFilterTest::with_root_context(move |_| Ticker {
+        fired: Rc::clone(&ticked),
+        handles: Rc::clone(&ticker_handles),
+    })
+    .vm_config(b"");
+    assert_eq!(tick_period(), Some(Duration::from_secs(1)));
+
+    for _ in 0..6 {
+        test = test.advance_time(Duration::from_secs(1)).tick();
+    }
+    assert_eq!(
+        *fired.borrow(),
+        vec!["tick", "every", "tick", "tick", "every"]
+    );
+
+    // The tick period of the root context is restored once no timers remain.
+    timer::cancel(handles.borrow()[0]).unwrap();
+    assert_eq!(tick_period(), Some(Duration::from_secs(2)));
+    test.advance_time(Duration::from_secs(2)).tick();
+    assert_eq!(fired.borrow().len(), 6);
+}
+
+#[test]
+fn test_tick_skipped_without_time() {
+    let (fired, _, test) = setup();
+    let test = test.vm_config(b"");
+    testing::with_host(|host| {
+        host.fail_next(
+            "proxy_get_current_time_nanoseconds",
+            Status::InternalFailure,
+        )
+    });
+    let test = test
+        .advance_time(Duration::from_secs(2))
+        .tick()
+        .expect_error("proxy_get_current_time_nanoseconds");
+    assert!(fired.borrow().is_empty());
+
+    test.tick();
+    assert_eq!(*fired.borrow(), vec!["every"]);
+}
+
+#[test]
+fn test_resolution() {
+    let (fired, test) = fast();
+    let test = test.vm_config(b"");
+    assert_eq!(tick_period(), Some(Duration::from_millis(10)));
+
+    // The timer fires late, once per tick.
+    let test = test.advance_time(Duration::from_millis(10)).tick();
+    assert_eq!(*fired.borrow(), vec!["fast"]);
+    test.advance_time(Duration::from_millis(10)).tick();
+    assert_eq!(*fired.borrow(), vec!["fast", "fast"]);
+}
+
+#[test]
+fn test_set_resolution() {
+    timer::set_resolution(Duration::from_millis(1));
+    let (fired, test) = fast();
+    test.vm_config(b"")
+        .advance_time(Duration::from_millis(3))
+        .tick();
+    assert_eq!(tick_period(), Some(Duration::from_millis(3)));
+    assert_eq!(*fired.borrow(), vec!["fast"]);
+}